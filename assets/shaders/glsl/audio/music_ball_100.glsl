@@ -50,6 +50,7 @@ uniform vec3  hsv_buffer[6];
 #define JERK_UP_AMP 0.25
 #define JERK_DN_AMP 0.4
 
+// GLSL ES 1.00 has no textureSize, so this has to match the band count of the FFT texture.
 #define NUM_OF_BINS 512.0
 #define FFT_ROW 0.0
 
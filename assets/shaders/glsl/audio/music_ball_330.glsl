@@ -51,7 +51,8 @@ uniform vec3  hsv_buffer[6];
 #define JERK_UP_AMP 0.25
 #define JERK_DN_AMP 0.4
 
-#define NUM_OF_BINS 512.0
+// One bar per texel, however many bands the FFT texture is laid out in.
+#define NUM_OF_BINS float(textureSize(iChannel1, 0).x)
 #define FFT_ROW 0.0
 
 const vec4 BLACK = vec4(0.0, 0.0, 0.0, 1.0);
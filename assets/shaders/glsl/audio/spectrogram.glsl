@@ -0,0 +1,22 @@
+#version 330
+
+in vec2 fragCoord;
+in vec2 fragTexCoord;
+in vec4 fragColor;
+
+out vec4 finalColor;
+
+uniform vec2      iResolution;
+uniform sampler2D iChannel0;
+// Row of iChannel0 holding the newest spectrum; older ones follow it backwards, wrapping around.
+uniform int       writeRow;
+
+void main() {
+    ivec2 size      = textureSize(iChannel0, 0);
+    // Newest spectrum along the bottom edge, scrolling up as it ages.
+    int   age       = int(floor((1.0 - fragTexCoord.y) * float(size.y)));
+    int   row       = (writeRow - age + size.y) % size.y;
+    vec2  uv        = vec2(fragTexCoord.x, (float(row) + 0.5) / float(size.y));
+    float amplitude = texture(iChannel0, uv).r;
+    finalColor = vec4(vec3(amplitude), 1.0);
+}
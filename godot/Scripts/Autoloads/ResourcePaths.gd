@@ -89,6 +89,7 @@ const WATER_TILESET: String = "res://assets/TileSets/Water.tres"
 const GLACIER_TILESET: String = "res://assets/TileSets/GlacierTileset.tres"
 
 const FFT_SHADER: String = "res://assets/shaders/gdshader/audio/fft.gdshader"
+const SPECTROGRAM_SHADER: String = "res://assets/shaders/gdshader/audio/spectrogram.gdshader"
 const IOI_SHADER: String = "res://assets/shaders/gdshader/audio/ioi.gdshader"
 const WAVEFORM_SHADER: String = "res://assets/shaders/gdshader/audio/waveform.gdshader"
 const MUSIC_BALL: String = "res://assets/shaders/gdshader/audio/music_ball.gdshader"
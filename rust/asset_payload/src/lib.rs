@@ -95,7 +95,9 @@ define_payloads! {
         SOUND_FONT_FILE          => "audio/dsdnmoy.sf2",
         MIDI_FILE                => "audio/fingerbib.mid",
         CACHED_WAV               => "audio/cache/cached_wav.wav",
-        CACHED_RHYTHM_DATA       => "audio/cache/RhythmData.tres",
+        CACHED_RHYTHM_DATA       => "audio/cache/RhythmData.rhythm",
+        // unversioned layout written before the container format, only read to migrate it
+        CACHED_RHYTHM_DATA_V0    => "audio/cache/RhythmData.tres",
         BAYER_PNG                => "textures/bayer.png",
         GRAY_NOISE_SMALL_PNG     => "textures/gray_noise_small.png",
         ICEBERGS_JPG             => "textures/icebergs.jpg",
@@ -140,12 +142,14 @@ define_payloads! {
         ICESHEETS_VERT_DRAFT     => "shaders/glsl/ice_sheets/icesheets_vertex_drafting.glsl",
         ICESHEETS_VERT           => "shaders/glsl/ice_sheets/icesheets_vertex.glsl",
         FFT_FRAG                 => "shaders/glsl/audio/fft.glsl",
+        SPECTROGRAM_FRAG         => "shaders/glsl/audio/spectrogram.glsl",
 
         BUFFER_A_GDSHADER                          => "shaders/gdshader/buffer_a.gdshader",
         MAIN_GDSHADER                              => "shaders/gdshader/main.gdshader",
         DREKKER_GDSHADER                           => "shaders/gdshader/color/drekker_effect.gdshader",
         SUPERSAMPLING_GDSHADERINC                  => "shaders/gdshader/color/supersampling.gdshaderinc",
         FFT_GDSHADER                               => "shaders/gdshader/audio/fft.gdshader",
+        SPECTROGRAM_GDSHADER                       => "shaders/gdshader/audio/spectrogram.gdshader",
         GHOST_GDSHADER                             => "shaders/gdshader/audio/ghost.gdshader",
         IOI_GDSHADER                               => "shaders/gdshader/audio/ioi.gdshader",
         MUSIC_BALL_GDSHADER                        => "shaders/gdshader/audio/music_ball.gdshader",
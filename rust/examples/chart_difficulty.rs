@@ -0,0 +1,128 @@
+use asset_payload::payloads::MIDI_FILE;
+use bath::midi::chart::{generate_chart_from_midi, LaneShaping, MidiChartRules};
+use bath::midi::program::ProgramMap;
+use bath::midi::rhythm::{analyze_chart, ChartAnalysisSettings, ChartStats, NoteKind, RhythmLane};
+
+// cargo run --example chart_difficulty
+fn main() {
+    let settings = ChartAnalysisSettings::default();
+    let uki = lane(
+        NoteKind::Hold,
+        &[
+            // Jack.
+            [0_f32, 0.05_f32],
+            [0.1_f32, 0.15_f32],
+            [0.2_f32, 0.25_f32],
+            [0.3_f32, 0.35_f32],
+            // Trill with shizumi.
+            [2_f32, 2_f32],
+            [2.24_f32, 2.24_f32],
+            [2.48_f32, 2.48_f32],
+            // Hold under shizumi taps and over the start of shizumi's hold.
+            [4_f32, 6_f32],
+            // Chord, then alternating too briefly to count.
+            [8_f32, 8_f32],
+            [8.1_f32, 8.1_f32],
+        ],
+    );
+    let shizumi = lane(
+        NoteKind::Hold,
+        &[
+            [2.12_f32, 2.12_f32],
+            [2.36_f32, 2.36_f32],
+            [4.5_f32, 4.5_f32],
+            [5_f32, 5_f32],
+            [5.5_f32, 5.5_f32],
+            [5.8_f32, 7_f32],
+            [8_f32, 8_f32],
+            [8.2_f32, 8.2_f32],
+        ],
+    );
+    // Played on letting go at 10.
+    let kaze = lane(NoteKind::Release, &[[9_f32, 10_f32]]);
+    let stats = analyze_chart(&[uki, shizumi, kaze], &settings);
+
+    assert_eq!(stats.note_count, 19);
+    assert_eq!((stats.start_secs, stats.duration_secs), (0_f32, 10_f32));
+    assert!((stats.average_nps - 1.9_f32).abs() < 1e-5);
+    assert_eq!(stats.jacks.len(), 1);
+    assert_eq!((stats.jacks[0].lanes, stats.jacks[0].notes), ([0, 0], 4));
+    assert_eq!(stats.trills.len(), 1, "{:?}", stats.trills);
+    let trill = stats.trills[0];
+    assert_eq!((trill.lanes, trill.notes, trill.start_secs), ([0, 1], 5, 2_f32));
+    assert_eq!(stats.notes_under_holds, 4);
+    assert!((stats.hold_overlap_secs - 0.2_f32).abs() < 1e-5);
+
+    let densest = stats.peak_windows[0];
+    assert_eq!((densest.notes, densest.nps), (5, 5_f32), "{:?}", densest);
+    assert!(densest.start_secs <= 2_f32 && densest.end_secs > 2.48_f32);
+    assert_eq!(stats.peak_windows.len(), settings.peak_window_count);
+    for (i, a) in stats.peak_windows.iter().enumerate() {
+        for b in &stats.peak_windows[i + 1..] {
+            assert!(a.nps >= b.nps && (a.end_secs <= b.start_secs || b.end_secs <= a.start_secs));
+        }
+    }
+
+    let strip = stats.density_strip(16);
+    assert_eq!(strip.len(), 16);
+    assert_eq!(strip.iter().max(), Some(&255));
+    assert_eq!(strip[strip.len() - 1], 51, "the lone release note at 1 nps");
+
+    // Twice as dense rates harder, and a jack rates harder than the same notes alternating.
+    let stream = |gap: f32, lanes: usize| {
+        let mut chart = vec![lane(NoteKind::Tap, &[]), lane(NoteKind::Tap, &[])];
+        for i in 0..64 {
+            let time = i as f32 * gap;
+            chart[i % lanes].onsets.push([time, time]);
+        }
+        analyze_chart(&chart, &settings).difficulty
+    };
+    assert!(stream(0.125_f32, 2) > stream(0.25_f32, 2));
+    assert!(stream(0.125_f32, 1) > stream(0.125_f32, 2));
+    assert_eq!(analyze_chart(&[], &settings), ChartStats::default());
+    let zero_window = ChartAnalysisSettings {
+        window_secs: 0_f32,
+        step_secs: 0_f32,
+        ..settings
+    };
+    let degenerate = analyze_chart(&[lane(NoteKind::Tap, &[[1_f32, 1_f32]])], &zero_window);
+    assert!(degenerate.average_nps.is_finite() && degenerate.density.iter().all(|[_, nps]| nps.is_finite()));
+    assert!(degenerate.difficulty.is_finite());
+
+    let chart = generate_chart_from_midi(MIDI_FILE(), &ProgramMap::default(), &MidiChartRules::default()).unwrap();
+    // Even with a max hold that would end every note before it starts, no release lands before its press.
+    let mut inverted = MidiChartRules::default();
+    inverted.shaping = LaneShaping {
+        max_hold_secs: -1_f32,
+        ..inverted.shaping
+    };
+    let inverted_chart = generate_chart_from_midi(MIDI_FILE(), &ProgramMap::default(), &inverted).unwrap();
+    assert!(inverted_chart
+        .uki
+        .iter()
+        .chain(&inverted_chart.shizumi)
+        .all(|[press, release]| release >= press));
+
+    let midi_stats = analyze_chart(
+        &[lane(NoteKind::Hold, &chart.uki), lane(NoteKind::Hold, &chart.shizumi)],
+        &settings,
+    );
+    println!(
+        "fingerbib: {} notes over {:.1}s, {:.2} nps average, {:.2} peak, {} jacks, {} trills, {:.1}s of overlapping holds, difficulty {:.2}",
+        midi_stats.note_count,
+        midi_stats.duration_secs,
+        midi_stats.average_nps,
+        midi_stats.peak_nps(),
+        midi_stats.jacks.len(),
+        midi_stats.trills.len(),
+        midi_stats.hold_overlap_secs,
+        midi_stats.difficulty
+    );
+}
+
+fn lane(kind: NoteKind, onsets: &[[f32; 2]]) -> RhythmLane {
+    RhythmLane {
+        onsets: onsets.to_vec(),
+        ..RhythmLane::new("lane", "F", kind)
+    }
+}
@@ -0,0 +1,81 @@
+use bath::audio_analysis::beat::BeatGrid;
+use bath::midi::metronome::{
+    click_times, mix_click_track, render_click_track, render_click_track_to_wav_bytes, ClickAccent, MetronomeSettings,
+};
+use std::io::Cursor;
+
+const SAMPLE_RATE: i32 = 8_000;
+
+// cargo run --example click_track
+fn main() {
+    let grid = BeatGrid::from_bpm(120_f32, 0.5_f32, 4_f32, 4);
+    let settings = MetronomeSettings {
+        accents: ClickAccent::pattern_from_str("X . x -").unwrap(),
+        ..MetronomeSettings::default()
+    };
+    let clicks = click_times(&grid, &settings);
+    assert_eq!(clicks.len(), 4 + grid.beats.len(), "one bar of count-in");
+    assert_eq!(clicks[0], (-1.5_f32, ClickAccent::Strong));
+    assert_eq!(clicks[3], (0_f32, ClickAccent::Silent));
+    assert_eq!(clicks[4], (0.5_f32, ClickAccent::Strong));
+    assert_eq!(clicks[6].1, ClickAccent::Normal);
+    assert!(ClickAccent::pattern_from_str("X?x").is_none());
+
+    // A pickup beat before the first downbeat takes the last accent of the bar and the count-in keeps that phase.
+    let pickup = BeatGrid {
+        first_downbeat: 1,
+        ..grid.clone()
+    };
+    let accents: Vec<ClickAccent> = click_times(&pickup, &MetronomeSettings::default())
+        .iter()
+        .map(|&(_, accent)| accent)
+        .collect();
+    assert_eq!(
+        &accents[..6],
+        &[
+            ClickAccent::Normal,
+            ClickAccent::Strong,
+            ClickAccent::Normal,
+            ClickAccent::Normal,
+            ClickAccent::Normal,
+            ClickAccent::Strong
+        ]
+    );
+
+    let track = render_click_track(&grid, &settings, SAMPLE_RATE, 4_f32);
+    assert!((track.lead_in_secs - 1.5_f32).abs() < 1e-6);
+    assert_eq!(track.samples.len(), (5.5_f32 * SAMPLE_RATE as f32) as usize);
+    let loudness = |song_time: f32| {
+        let start = ((song_time + track.lead_in_secs) * SAMPLE_RATE as f32) as usize;
+        track.samples[start..start + SAMPLE_RATE as usize / 20]
+            .iter()
+            .map(|&(left, _)| (left as i32).abs())
+            .max()
+            .unwrap()
+    };
+    assert_eq!(loudness(0_f32), 0, "silent accent");
+    assert_eq!(loudness(0.2_f32), 0, "nothing between beats");
+    assert!(loudness(0.5_f32) > loudness(1.5_f32) && loudness(1.5_f32) > loudness(1_f32));
+    assert!(loudness(1_f32) > 0);
+
+    let song = vec![(1000_i16, -1000_i16); 2 * SAMPLE_RATE as usize];
+    let mixed = mix_click_track(&song, SAMPLE_RATE, &grid, &settings);
+    let lead_in_frames = (mixed.lead_in_secs * SAMPLE_RATE as f32).round() as usize;
+    assert_eq!(mixed.samples.len(), lead_in_frames + song.len());
+    assert_eq!(mixed.samples[lead_in_frames - 1], (0, 0), "silence under the count-in");
+    // Half way between beats only the song is left, at the song gain.
+    let quiet = mixed.samples[lead_in_frames + SAMPLE_RATE as usize / 4];
+    assert!((quiet.0 as f32 - 1000_f32 * settings.song_gain).abs() <= 1_f32);
+    assert!((quiet.1 as f32 + 1000_f32 * settings.song_gain).abs() <= 1_f32);
+
+    let wav = render_click_track_to_wav_bytes(SAMPLE_RATE, 2, &grid, &settings, 4_f32).unwrap();
+    let reader = hound::WavReader::new(Cursor::new(wav)).unwrap();
+    assert_eq!(reader.spec().channels, 2);
+    assert_eq!(reader.duration() as usize, track.samples.len());
+    println!(
+        "click track: {} clicks, {:.2}s count-in, {} frames",
+        clicks.len(),
+        track.lead_in_secs,
+        track.samples.len()
+    );
+}
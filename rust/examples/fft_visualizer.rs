@@ -8,9 +8,11 @@ use bath::render::{renderer::Renderer, renderer::RendererVector2};
 use bath::sound_render::raylib::RaylibFFTTexture;
 use bath::sound_render::sound_renderer::{
     FFTTexture, AUDIO_STREAM_RING_BUFFER_SIZE, BUFFER_SIZE, FFT_HISTORICAL_SMOOTHING_BUFFER_TIME_SECONDS,
-    FFT_WINDOW_SIZE, MONO, PER_SAMPLE_BIT_DEPTH_HARDCODED, RING_BUFFER_PADDING, SAMPLE_RATE_HARDCODED, WINDOW_TIME,
+    FFT_WINDOW_SIZE, MONO, PER_SAMPLE_BIT_DEPTH_HARDCODED, SAMPLE_RATE_HARDCODED,
 };
-use fftw2_sys::fftw_complex;
+use bath::sound_render::spectrum_analyzer::SpectrumAnalyzer;
+use bath::sound_render::spectrum_bands::{BandLayout, BandScale};
+use bath::sound_render::spectrum_history::{SpectrumClock, SpectrumHistory};
 use hound::SampleFormat::Int;
 use hound::WavReader;
 use raylib::core::audio::RaylibAudio;
@@ -25,20 +27,24 @@ use std::time::SystemTime;
 
 fn main() {
     let mut pitch = PitchDimension::default();
-    pitch.resolve_payload_to_midi_buffer(MIDI_FILE());
+    pitch
+        .resolve_payload_to_midi_buffer(MIDI_FILE())
+        .expect("Failed to parse MIDI file");
 
     #[cfg(feature = "nasa-embed")]
     let wav_bytes =
         pitch.resolve_payload_to_pcm_buffer(SAMPLE_RATE_HARDCODED as i32, MONO as u16, MIDI_FILE, SOUND_FONT_FILE);
 
     #[cfg(not(feature = "nasa-embed"))]
-    let wav_bytes = pitch.resolve_payload_to_pcm_buffer_cache(
-        SAMPLE_RATE_HARDCODED as i32,
-        MONO as u16,
-        MIDI_FILE(),
-        SOUND_FONT_FILE(),
-        CACHED_WAV_PATH,
-    );
+    let wav_bytes = pitch
+        .resolve_payload_to_pcm_buffer_cache(
+            SAMPLE_RATE_HARDCODED as i32,
+            MONO as u16,
+            MIDI_FILE(),
+            SOUND_FONT_FILE(),
+            CACHED_WAV_PATH,
+        )
+        .expect("Failed to load or write WAV cache");
 
     let mut render = RaylibRenderer::init(EXPERIMENTAL_WINDOW_WIDTH, EXPERIMENTAL_WINDOW_HEIGHT);
     let i_resolution = RendererVector2::new(
@@ -54,15 +60,20 @@ fn main() {
     let mut shader = render.load_shader_full(DEBUG_VERT(), FFT_FRAG());
 
     render.set_uniform_vec2(&mut shader, "iResolution", i_resolution);
-    let fft_history_len: usize =
-        (FFT_HISTORICAL_SMOOTHING_BUFFER_TIME_SECONDS as f64 / WINDOW_TIME).ceil() as usize + RING_BUFFER_PADDING;
+    // Each audio chunk is averaged 2:1 into `fft_data` below, so the analyzer sees half the stream rate.
+    let analyzer = SpectrumAnalyzer::new(FFT_WINDOW_SIZE, (SAMPLE_RATE_HARDCODED / 2) as f32);
+    let hop_secs = analyzer.hop_size() as f64 / analyzer.sample_rate as f64;
     let mut fft = RaylibFFTTexture {
-        plan: None,
-        spectrum: [fftw_complex { re: 0.0, im: 0.0 }; FFT_WINDOW_SIZE],
-        fft_history: vec![[0.0; BUFFER_SIZE]; fft_history_len],
-        history_pos: 0_usize,
-        last_fft_time: 0_f64,
-        tapback_pos: 0.01_f32,
+        bands: BandLayout::for_analyzer(BandScale::Linear, BUFFER_SIZE, &analyzer),
+        analyzer,
+        history: SpectrumHistory::with_duration(
+            FFT_HISTORICAL_SMOOTHING_BUFFER_TIME_SECONDS as f64,
+            hop_secs,
+            BUFFER_SIZE,
+        ),
+        clock: SpectrumClock::SamplePosition,
+        spectrogram: None,
+        tapback_pos: 0_f32,
     };
     let mut fft_data = [0_f32; FFT_WINDOW_SIZE];
     let mut fft_image = fft.init_audio_texture();
@@ -134,8 +145,9 @@ fn main() {
                 let avg = (wav_sample[0] as i32 + wav_sample[1] as i32) / 2_i32;
                 *fft_sample = avg as f32 / i16::MAX as f32;
             }
+            fft.capture_frame(&mut fft_data);
         }
-        fft.update_audio_texture(&mut fft_data, &mut fft_image);
+        fft.render_frame(&mut fft_image);
         let len = fft_image.get_pixel_data_size();
         let pixels = unsafe { from_raw_parts(fft_image.data as *const u8, len) };
         //println!("FFT image bytes [0..8]: {:?}", &pixels[0..8.min(len)]);
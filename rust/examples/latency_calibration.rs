@@ -0,0 +1,86 @@
+use bath::midi::calibration::{apply_calibration, estimate_offset, CalibrationCue, CalibrationSession};
+use bath::midi::rhythm::{Judgement, LaneInput, LaneInputKind, RhythmDimension, RhythmOffsets, LANE_UKI};
+
+const AUDIO_LATENCY_SECS: f32 = 0.042;
+const INPUT_LATENCY_SECS: f32 = 0.017;
+const TOLERANCE_SECS: f32 = 0.003;
+
+// cargo run --example latency_calibration
+fn main() {
+    let audio = tap_session(CalibrationCue::Audio, AUDIO_LATENCY_SECS + INPUT_LATENCY_SECS);
+    let visual = tap_session(CalibrationCue::Visual, INPUT_LATENCY_SECS);
+    let audio_estimate = audio.estimate().expect("audio session should have enough taps");
+    let visual_estimate = visual.estimate().expect("visual session should have enough taps");
+    assert_eq!(audio_estimate.rejected, 2, "the two stray taps are outliers");
+    assert!((audio_estimate.offset_secs - (AUDIO_LATENCY_SECS + INPUT_LATENCY_SECS)).abs() <= TOLERANCE_SECS);
+    assert!((visual_estimate.offset_secs - INPUT_LATENCY_SECS).abs() <= TOLERANCE_SECS);
+
+    // Either order of sessions lands on the same offsets.
+    for order in [
+        [
+            (CalibrationCue::Audio, audio_estimate),
+            (CalibrationCue::Visual, visual_estimate),
+        ],
+        [
+            (CalibrationCue::Visual, visual_estimate),
+            (CalibrationCue::Audio, audio_estimate),
+        ],
+    ] {
+        let mut offsets = RhythmOffsets::default();
+        for (cue, estimate) in &order {
+            apply_calibration(&mut offsets, *cue, estimate);
+        }
+        assert!(
+            (offsets.audio_secs - AUDIO_LATENCY_SECS).abs() <= TOLERANCE_SECS,
+            "{:?}",
+            offsets
+        );
+        assert!(
+            (offsets.input_secs - INPUT_LATENCY_SECS).abs() <= TOLERANCE_SECS,
+            "{:?}",
+            offsets
+        );
+    }
+
+    assert!(
+        estimate_offset(&[0.01_f32, 0.02_f32]).is_none(),
+        "too few taps to trust"
+    );
+
+    // A press stamped exactly as late as the device latency judges as perfect once the offsets apply.
+    let mut rhythm = RhythmDimension::default();
+    rhythm.rhythm_data.lane_mut_or_insert(LANE_UKI).onsets = vec![[1_f32, 1.1_f32]];
+    rhythm.load_custom_onsets();
+    rhythm.rhythm_data.offsets = RhythmOffsets {
+        audio_secs: AUDIO_LATENCY_SECS,
+        input_secs: INPUT_LATENCY_SECS,
+    };
+    let press = LaneInput {
+        lane: 0,
+        kind: LaneInputKind::Press,
+        time: 1_f32 + AUDIO_LATENCY_SECS + INPUT_LATENCY_SECS,
+    };
+    assert_eq!(rhythm.judge_input(press), Some(Judgement::Perfect));
+    println!(
+        "calibrated audio {:.1}ms, input {:.1}ms",
+        (audio_estimate.offset_secs - visual_estimate.offset_secs) * 1000_f32,
+        visual_estimate.offset_secs * 1000_f32
+    );
+}
+
+// Taps `latency` late with a few ms of deterministic jitter, plus one tap far too early and one far too late.
+fn tap_session(cue: CalibrationCue, latency: f32) -> CalibrationSession {
+    let mut session = CalibrationSession::new(cue, 120_f32, 0_f32, 4_f32, 16);
+    let cues = session.cues.clone();
+    for (i, cue_time) in cues.iter().enumerate() {
+        let jitter = ((i * 7 % 5) as f32 - 2_f32) * 0.002_f32;
+        let stray = match i {
+            5 => -0.18_f32,
+            11 => 0.2_f32,
+            _ => 0_f32,
+        };
+        session.tap(cue_time + latency + jitter + stray);
+    }
+    assert!(session.is_complete(cues[cues.len() - 1] + 0.3_f32));
+    session
+}
@@ -0,0 +1,154 @@
+use asset_payload::payloads::MIDI_FILE;
+use bath::midi::program::ProgramMap;
+use bath::midi::util::{
+    parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes,
+    parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes, MidiNote,
+};
+use bath::midi::writer::{write_note_buffer_seconds_to_smf_bytes, write_note_buffer_ticks_to_smf_bytes};
+use midly::num::{u15, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use std::collections::HashMap;
+
+// cargo run --example midi_round_trip
+fn main() {
+    let program_map = ProgramMap::default();
+    let midi_bytes = MIDI_FILE();
+    let ticks_per_quarter = match Smf::parse(midi_bytes).unwrap().header.timing {
+        Timing::Metrical(tpq) => tpq.as_int(),
+        Timing::Timecode(..) => panic!("round trip expects a metrical file"),
+    };
+    let bpm = 120_f32;
+
+    let ticks = parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes(midi_bytes, &program_map).unwrap();
+    for format in [Format::SingleTrack, Format::Parallel] {
+        let written = write_note_buffer_ticks_to_smf_bytes(&ticks, ticks_per_quarter, bpm, format).unwrap();
+        let reparsed =
+            parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes(&written, &program_map).unwrap();
+        assert_eq!(
+            sorted_ticks(&ticks),
+            sorted_ticks(&reparsed),
+            "tick round trip ({:?})",
+            format
+        );
+        println!("ticks   {:?}: {} notes round tripped", format, note_count(&reparsed));
+    }
+
+    let seconds = parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes(midi_bytes, &program_map).unwrap();
+    let tick_secs = 60_f32 / (bpm * ticks_per_quarter as f32);
+    for format in [Format::SingleTrack, Format::Parallel] {
+        let written = write_note_buffer_seconds_to_smf_bytes(&seconds, ticks_per_quarter, bpm, format).unwrap();
+        let reparsed =
+            parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes(&written, &program_map).unwrap();
+        let expected = sorted_seconds(&seconds);
+        let actual = sorted_seconds(&reparsed);
+        assert_eq!(
+            expected.len(),
+            actual.len(),
+            "seconds round trip note count ({:?})",
+            format
+        );
+        for (e, a) in expected.iter().zip(actual.iter()) {
+            assert_eq!(e.0, a.0);
+            assert!(
+                (e.1 - a.1).abs() <= tick_secs && (e.2 - a.2).abs() <= tick_secs,
+                "{:?} vs {:?}",
+                e,
+                a
+            );
+        }
+        println!("seconds {:?}: {} notes round tripped", format, actual.len());
+    }
+
+    // Values the file format can't hold are rejected rather than masked.
+    let one_note = |instrument_id: u8, release: u32| {
+        (
+            MidiNote {
+                midi_note: 60,
+                instrument_id,
+            },
+            vec![(0_u32, release)],
+        )
+    };
+    let too_many_programs: HashMap<MidiNote, Vec<(u32, u32)>> = (0..16).map(|program| one_note(program, 10)).collect();
+    let fifteen_programs: HashMap<MidiNote, Vec<(u32, u32)>> = (0..15).map(|program| one_note(program, 10)).collect();
+    let long_gap: HashMap<MidiNote, Vec<(u32, u32)>> = [one_note(0, 1 << 28)].into_iter().collect();
+    for format in [Format::SingleTrack, Format::Parallel] {
+        assert!(write_note_buffer_ticks_to_smf_bytes(&fifteen_programs, ticks_per_quarter, bpm, format).is_ok());
+        assert!(write_note_buffer_ticks_to_smf_bytes(&too_many_programs, ticks_per_quarter, bpm, format).is_err());
+        assert!(write_note_buffer_ticks_to_smf_bytes(&long_gap, ticks_per_quarter, bpm, format).is_err());
+        assert!(write_note_buffer_ticks_to_smf_bytes(&fifteen_programs, ticks_per_quarter, 1_f32, format).is_err());
+        for bad_bpm in [0_f32, -120_f32, f32::NAN, f32::INFINITY] {
+            assert!(
+                write_note_buffer_ticks_to_smf_bytes(&fifteen_programs, ticks_per_quarter, bad_bpm, format).is_err()
+            );
+        }
+        for bad_tpq in [0_u16, 32768_u16, u16::MAX] {
+            assert!(write_note_buffer_ticks_to_smf_bytes(&fifteen_programs, bad_tpq, bpm, format).is_err());
+        }
+    }
+    assert!(
+        write_note_buffer_ticks_to_smf_bytes(&fifteen_programs, ticks_per_quarter, bpm, Format::Sequential).is_err()
+    );
+
+    // A ProgramChange while a note is held doesn't relabel it, the note keeps the program it was struck with.
+    let channel = u4::new(0);
+    let event = |delta: u32, message: MidiMessage| TrackEvent {
+        delta: u28::new(delta),
+        kind: TrackEventKind::Midi { channel, message },
+    };
+    let held_through_change = Smf {
+        header: Header::new(Format::SingleTrack, Timing::Metrical(u15::new(96))),
+        tracks: vec![vec![
+            event(0, MidiMessage::ProgramChange { program: u7::new(5) }),
+            event(
+                0,
+                MidiMessage::NoteOn {
+                    key: u7::new(60),
+                    vel: u7::new(100),
+                },
+            ),
+            event(48, MidiMessage::ProgramChange { program: u7::new(40) }),
+            event(
+                48,
+                MidiMessage::NoteOff {
+                    key: u7::new(60),
+                    vel: u7::new(0),
+                },
+            ),
+            TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            },
+        ]],
+    };
+    let mut held_bytes = Vec::new();
+    held_through_change.write_std(&mut held_bytes).unwrap();
+    let held = parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes(&held_bytes, &program_map).unwrap();
+    assert_eq!(sorted_ticks(&held), vec![(60, 5, 0, 96)]);
+}
+
+fn note_count<T>(buffer: &HashMap<MidiNote, Vec<T>>) -> usize {
+    buffer.values().map(|segments| segments.len()).sum()
+}
+
+fn sorted_ticks(buffer: &HashMap<MidiNote, Vec<(u32, u32)>>) -> Vec<(u8, u8, u32, u32)> {
+    let mut notes: Vec<_> = buffer
+        .iter()
+        .flat_map(|(note, segments)| {
+            segments
+                .iter()
+                .map(move |&(on, off)| (note.midi_note, note.instrument_id, on, off))
+        })
+        .collect();
+    notes.sort_unstable();
+    notes
+}
+
+fn sorted_seconds(buffer: &HashMap<MidiNote, Vec<(f32, f32)>>) -> Vec<(u8, f32, f32)> {
+    let mut notes: Vec<_> = buffer
+        .iter()
+        .flat_map(|(note, segments)| segments.iter().map(move |&(on, off)| (note.midi_note, on, off)))
+        .collect();
+    notes.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    notes
+}
@@ -0,0 +1,55 @@
+use asset_payload::payloads::MIDI_FILE;
+use bath::midi::program::ProgramMap;
+use bath::midi::timeline::NoteTimeline;
+use bath::midi::util::{
+    parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes, sample_active_notes_at_time, MidiNote,
+};
+use std::collections::HashMap;
+
+// cargo run --example note_timeline
+fn main() {
+    // A key retriggered before its release overlaps itself, and another instrument plays the same key.
+    let piano = MidiNote {
+        midi_note: 60,
+        instrument_id: 0,
+    };
+    let strings = MidiNote {
+        midi_note: 60,
+        instrument_id: 48,
+    };
+    let buffer: HashMap<MidiNote, Vec<(f32, f32)>> = HashMap::from([
+        (piano, vec![(0_f32, 2_f32), (1_f32, 3_f32)]),
+        (strings, vec![(0.5_f32, 1.5_f32)]),
+    ]);
+    let timeline = NoteTimeline::from_note_buffer(&buffer);
+    assert_eq!(timeline.active_notes_at(1.25_f32), vec![60, 60]);
+    assert_eq!(timeline.active_intervals_at(1.25_f32).len(), 3);
+    for t in [0_f32, 0.75_f32, 1.25_f32, 2.5_f32, 3_f32] {
+        assert_eq!(
+            timeline.active_notes_at(t),
+            sample_active_notes_at_time(&buffer, t),
+            "t = {}",
+            t
+        );
+    }
+
+    let buffer =
+        parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes(MIDI_FILE(), &ProgramMap::default())
+            .unwrap();
+    let timeline = NoteTimeline::from_note_buffer(&buffer);
+    let end = timeline.end_time();
+    for step in 0..1000 {
+        let t = end * step as f32 / 1000_f32;
+        assert_eq!(
+            timeline.active_notes_at(t),
+            sample_active_notes_at_time(&buffer, t),
+            "t = {}",
+            t
+        );
+    }
+    println!(
+        "note timeline: {} intervals over {:.1}s",
+        timeline.intervals().len(),
+        end
+    );
+}
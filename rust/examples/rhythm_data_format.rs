@@ -0,0 +1,212 @@
+use bath::audio_analysis::beat::BeatGrid;
+use bath::midi::rhythm::{NoteKind, RhythmData, RhythmLane, RhythmOffsets, LANE_SHIZUMI, LANE_UKI};
+use bath::midi::rhythm_format::{crc32, RhythmDataError, RHYTHM_DATA_MAGIC};
+
+// cargo run --example rhythm_data_format
+fn main() {
+    let data = RhythmData {
+        bpm: 127.35_f32,
+        lanes: vec![
+            lane(
+                LANE_UKI,
+                "F",
+                NoteKind::Hold,
+                &[[0.5_f32, 0.62_f32], [1.0_f32, 1.75_f32]],
+            ),
+            lane(
+                LANE_SHIZUMI,
+                "J",
+                NoteKind::Tap,
+                &[[0.25_f32, 0.3_f32], [1.1_f32, 1.1_f32]],
+            ),
+            lane("kaze", "Space", NoteKind::Release, &[[2_f32, 2.5_f32]]),
+            // Not a default lane and not bound yet, and a key whose name has a space in it.
+            lane("ame", "", NoteKind::Tap, &[[3_f32, 3_f32]]),
+            lane("kumo", "Page Up", NoteKind::Hold, &[]),
+            // Keys that only survive the text export if it is quoted rather than trimmed.
+            lane("kiri", " ", NoteKind::Tap, &[]),
+            lane("yuki", " Lead", NoteKind::Tap, &[]),
+            lane("hoshi", "Trail ", NoteKind::Tap, &[]),
+            lane("tsuki", "\"quoted\" \\ key", NoteKind::Tap, &[]),
+        ],
+        beat_grid: BeatGrid::from_bpm(127.35_f32, 0.12_f32, 4_f32, 3),
+        offsets: RhythmOffsets {
+            audio_secs: 0.031_f32,
+            input_secs: -0.0125_f32,
+        },
+    };
+
+    let bytes = data.serialize().unwrap();
+    assert!(bytes.starts_with(&RHYTHM_DATA_MAGIC));
+    assert_eq!(RhythmData::deserialize(&bytes).unwrap(), data, "binary round trip");
+
+    let text = data.to_text();
+    assert_eq!(RhythmData::from_text(&text).unwrap(), data, "text round trip");
+    println!("{}", text);
+
+    for index in [RHYTHM_DATA_MAGIC.len() + 6, bytes.len() / 2, bytes.len() - 1] {
+        let mut corrupted = bytes.clone();
+        corrupted[index] ^= 0x10;
+        assert!(
+            matches!(
+                RhythmData::deserialize(&corrupted),
+                Err(RhythmDataError::ChecksumMismatch { .. })
+            ),
+            "flipped byte {} went unnoticed",
+            index
+        );
+    }
+    assert!(RhythmData::deserialize(&bytes[..bytes.len() - 3]).is_err());
+
+    let mut newer = bytes.clone();
+    newer[RHYTHM_DATA_MAGIC.len()] = 0xff;
+    assert!(matches!(
+        RhythmData::deserialize(&newer),
+        Err(RhythmDataError::UnsupportedVersion(_))
+    ));
+
+    // v0 and v1 only knew the two default lanes as plain hold lists.
+    let legacy = RhythmData {
+        lanes: vec![
+            lane(LANE_UKI, "F", NoteKind::Hold, &data.lanes[0].onsets),
+            lane(LANE_SHIZUMI, "J", NoteKind::Hold, &data.lanes[1].onsets),
+        ],
+        ..data.clone()
+    };
+    let v0 = v0_bytes(&legacy, true);
+    let migrated = RhythmData::deserialize(&v0).unwrap();
+    assert_eq!(
+        migrated,
+        RhythmData {
+            offsets: RhythmOffsets::default(),
+            ..legacy.clone()
+        },
+        "v0 with beat grid"
+    );
+    assert_eq!(
+        RhythmData::deserialize(&migrated.serialize().unwrap()).unwrap(),
+        migrated
+    );
+    let v0_without_grid = RhythmData::deserialize(&v0_bytes(&legacy, false)).unwrap();
+    assert_eq!(v0_without_grid.lanes, legacy.lanes);
+    assert!(v0_without_grid.beat_grid.is_empty());
+    let mut v0_trailing = v0;
+    v0_trailing.push(0);
+    assert!(
+        RhythmData::deserialize(&v0_trailing).is_err(),
+        "v0 with trailing garbage"
+    );
+    let v1 = RhythmData::deserialize(&v1_bytes(&legacy)).unwrap();
+    assert_eq!((v1.bpm, &v1.lanes), (legacy.bpm, &legacy.lanes), "v1 lanes");
+
+    assert!(matches!(
+        RhythmData::from_text("bath-rhythm 2\n[lane uki]\n[lane uki]\n"),
+        Err(RhythmDataError::Text(3, _))
+    ));
+    assert!(matches!(
+        RhythmData::from_text("bath-rhythm 2\n[lane uki]\n0.5 oops\n"),
+        Err(RhythmDataError::Text(3, _))
+    ));
+    let legacy_keys = RhythmData::from_text("bath-rhythm 2\n[lane uki]\nkey G\n[lane ame]\nkey\n").unwrap();
+    assert_eq!(
+        (legacy_keys.lanes[0].key.as_str(), legacy_keys.lanes[1].key.as_str()),
+        ("G", "")
+    );
+    let unquoted_key = RhythmData::from_text("bath-rhythm 2\n[lane uki]\nkey= Page Up \n").unwrap();
+    assert_eq!(unquoted_key.lanes[0].key, " Page Up ");
+    assert!(matches!(
+        RhythmData::from_text("bath-rhythm 2\n[lane uki]\nkey=\"open\n"),
+        Err(RhythmDataError::Text(3, _))
+    ));
+
+    // A key too long for its u16 length prefix is refused instead of written truncated.
+    let long_key = RhythmData {
+        lanes: vec![lane(LANE_UKI, &"F".repeat(u16::MAX as usize + 1), NoteKind::Hold, &[])],
+        ..RhythmData::default()
+    };
+    assert!(matches!(
+        long_key.serialize(),
+        Err(RhythmDataError::StringTooLong("lane key", _))
+    ));
+
+    // Lane names are one word everywhere a lane can come from.
+    assert!(!RhythmLane::is_valid_name("big drum") && !RhythmLane::is_valid_name(""));
+    assert!(matches!(
+        RhythmData::from_text("bath-rhythm 2\n[lane big drum]\n"),
+        Err(RhythmDataError::Text(2, _))
+    ));
+    let spaced = RhythmData {
+        lanes: vec![RhythmLane {
+            name: "big drum".to_string(),
+            ..lane(LANE_UKI, "F", NoteKind::Hold, &[])
+        }],
+        ..RhythmData::default()
+    };
+    assert!(matches!(
+        RhythmData::deserialize(&spaced.serialize().unwrap()),
+        Err(RhythmDataError::Malformed("lane name"))
+    ));
+    assert!(matches!(
+        RhythmData::deserialize(&v1_bytes(&spaced)),
+        Err(RhythmDataError::Malformed("lane name"))
+    ));
+    println!("rhythm data format: binary, text, v0 and v1 migration ok");
+}
+
+// The layout `RhythmData::serialize` wrote before the versioned container.
+fn v0_bytes(data: &RhythmData, with_grid: bool) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&data.bpm.to_le_bytes());
+    put_f32s(&mut bytes, data.lanes[0].onsets.as_flattened());
+    put_f32s(&mut bytes, data.lanes[1].onsets.as_flattened());
+    if with_grid {
+        let grid = &data.beat_grid;
+        bytes.extend_from_slice(&grid.beats_per_bar.to_le_bytes());
+        bytes.extend_from_slice(&grid.first_downbeat.to_le_bytes());
+        put_f32s(&mut bytes, &grid.beats);
+        bytes.extend_from_slice(&(grid.tempo_curve.len() as u32).to_le_bytes());
+        for [time, bpm] in &grid.tempo_curve {
+            bytes.extend_from_slice(&time.to_le_bytes());
+            bytes.extend_from_slice(&bpm.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+// Version 1 of the container, bpm and lanes only: each lane was a name and a flat press/release list.
+fn v1_bytes(data: &RhythmData) -> Vec<u8> {
+    let mut lanes = Vec::new();
+    lanes.extend_from_slice(&(data.lanes.len() as u32).to_le_bytes());
+    for lane in &data.lanes {
+        lanes.extend_from_slice(&(lane.name.len() as u16).to_le_bytes());
+        lanes.extend_from_slice(lane.name.as_bytes());
+        put_f32s(&mut lanes, lane.onsets.as_flattened());
+    }
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&RHYTHM_DATA_MAGIC);
+    bytes.extend_from_slice(&1_u16.to_le_bytes());
+    bytes.extend_from_slice(&2_u16.to_le_bytes());
+    bytes.extend_from_slice(b"BPM ");
+    bytes.extend_from_slice(&4_u32.to_le_bytes());
+    bytes.extend_from_slice(&data.bpm.to_le_bytes());
+    bytes.extend_from_slice(b"LANE");
+    bytes.extend_from_slice(&(lanes.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&lanes);
+    let crc = crc32(&bytes);
+    bytes.extend_from_slice(&crc.to_le_bytes());
+    bytes
+}
+
+fn lane(name: &str, key: &str, kind: NoteKind, onsets: &[[f32; 2]]) -> RhythmLane {
+    RhythmLane {
+        onsets: onsets.to_vec(),
+        ..RhythmLane::new(name, key, kind)
+    }
+}
+
+fn put_f32s(bytes: &mut Vec<u8>, values: &[f32]) {
+    bytes.extend_from_slice(&(values.len() as u32).to_le_bytes());
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
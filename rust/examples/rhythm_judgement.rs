@@ -0,0 +1,94 @@
+use bath::midi::rhythm::{
+    Judgement, JudgementEngine, JudgementWindows, LaneInput, LaneInputKind, NoteKind, RhythmLane,
+};
+
+// cargo run --example rhythm_judgement
+fn main() {
+    let uki = lane(NoteKind::Hold, &[[1_f32, 1.08_f32], [2_f32, 3_f32], [4_f32, 4.08_f32]]);
+    let shizumi = lane(NoteKind::Hold, &[[1.5_f32, 1.58_f32], [2.5_f32, 2.58_f32]]);
+    let mut engine = JudgementEngine::new(&[uki, shizumi], JudgementWindows::default());
+
+    // Too early to reach the first note's good window, so it is ignored rather than judged.
+    assert_eq!(engine.input(press(0, 0.8_f32)), None);
+    assert_eq!(engine.input(press(0, 1.01_f32)), Some(Judgement::Perfect));
+    // Tap releases are not graded.
+    assert_eq!(engine.input(release(0, 1.09_f32)), None);
+    assert_eq!(engine.input(press(1, 1.545_f32)), Some(Judgement::Great));
+    assert_eq!(engine.combo(), 2);
+
+    // Hold: pressed late but within good, dropped half way through.
+    assert_eq!(engine.input(press(0, 2.08_f32)), Some(Judgement::Good));
+    assert_eq!(engine.input(release(0, 2.5_f32)), Some(Judgement::Miss));
+    assert_eq!(engine.combo(), 0);
+    assert_eq!(engine.max_combo(), 3);
+
+    // The second shizumi note was never pressed; advancing past its window misses it.
+    engine.advance(2.7_f32);
+    assert_eq!(engine.last_judgement(), Some(Judgement::Miss));
+    assert_eq!(engine.input(press(0, 3.99_f32)), Some(Judgement::Perfect));
+    engine.advance(5_f32);
+    assert!(engine.is_finished());
+
+    assert_eq!(engine.count(Judgement::Perfect), 2);
+    assert_eq!(engine.count(Judgement::Great), 1);
+    assert_eq!(engine.count(Judgement::Good), 1);
+    assert_eq!(engine.count(Judgement::Miss), 2);
+    assert_eq!(engine.score(), 300 + 200 + 100 + 300);
+    assert!((engine.accuracy() - 900_f32 / 1800_f32).abs() < 1e-6);
+    let results = engine.results();
+    assert_eq!(results.len(), 5);
+    let hold = results.iter().find(|r| r.lane == 0 && r.note_index == 1).unwrap();
+    assert_eq!(hold.release, Some(Judgement::Miss));
+    assert!((hold.release_offset.unwrap() + 0.5_f32).abs() < 1e-4);
+
+    // A hold kept down past its release completes on its own.
+    let mut engine = JudgementEngine::new(&[lane(NoteKind::Hold, &[[0_f32, 1_f32]])], JudgementWindows::default());
+    assert_eq!(engine.input(press(0, 0_f32)), Some(Judgement::Perfect));
+    engine.advance(1.2_f32);
+    assert_eq!(engine.results()[0].release, Some(Judgement::Perfect));
+    assert_eq!(engine.combo(), 2);
+    engine.reset();
+    assert_eq!((engine.combo(), engine.score(), engine.results().len()), (0, 0, 0));
+
+    // Tap lanes never hold, however long the note is written.
+    let mut engine = JudgementEngine::new(&[lane(NoteKind::Tap, &[[0_f32, 1_f32]])], JudgementWindows::default());
+    assert_eq!(engine.input(press(0, 0.02_f32)), Some(Judgement::Perfect));
+    assert_eq!(engine.input(release(0, 0.3_f32)), None);
+    engine.advance(2_f32);
+    assert_eq!((engine.results().len(), engine.combo()), (1, 1));
+
+    // Release lanes ignore presses and grade only letting go.
+    let notes = [[0_f32, 1_f32], [2_f32, 3_f32]];
+    let mut engine = JudgementEngine::new(&[lane(NoteKind::Release, &notes)], JudgementWindows::default());
+    assert_eq!(engine.input(press(0, 0_f32)), None);
+    assert_eq!(engine.input(release(0, 1.05_f32)), Some(Judgement::Great));
+    engine.advance(3.5_f32);
+    let results = engine.results();
+    assert_eq!(results[0].press, None);
+    assert_eq!(results[1].release, Some(Judgement::Miss));
+    assert!(engine.is_finished());
+    println!("rhythm judgement ok");
+}
+
+fn lane(kind: NoteKind, onsets: &[[f32; 2]]) -> RhythmLane {
+    RhythmLane {
+        onsets: onsets.to_vec(),
+        ..RhythmLane::new("lane", "F", kind)
+    }
+}
+
+fn press(lane: usize, time: f32) -> LaneInput {
+    LaneInput {
+        lane,
+        kind: LaneInputKind::Press,
+        time,
+    }
+}
+
+fn release(lane: usize, time: f32) -> LaneInput {
+    LaneInput {
+        lane,
+        kind: LaneInputKind::Release,
+        time,
+    }
+}
@@ -0,0 +1,52 @@
+use bath::sound_render::spectrogram::{SpectrogramRing, DEFAULT_SPECTROGRAM_ROWS};
+use bath::sound_render::spectrum_history::SpectrumHistory;
+
+// cargo run --example spectrogram_ring
+fn main() {
+    let mut ring = SpectrogramRing::new(4);
+    assert!(ring.is_empty() && ring.row_for_age(0).is_none());
+    let rows: Vec<usize> = (0..6).map(|_| ring.advance()).collect();
+    assert_eq!(rows, vec![0, 1, 2, 3, 0, 1], "round robin from the top row");
+    assert_eq!((ring.write_row(), ring.len()), (1, 4));
+    // The shader's unwrap, (writeRow - age + rows) % rows.
+    for age in 0..4 {
+        let shader_row = (ring.write_row() as i32 - age as i32 + 4).rem_euclid(4) as usize;
+        assert_eq!(ring.row_for_age(age), Some(shader_row));
+    }
+    assert_eq!(ring.row_for_age(1), Some(0));
+    assert!(ring.row_for_age(4).is_none(), "overwritten");
+    ring.clear();
+    assert_eq!((ring.write_row(), ring.len()), (3, 0));
+
+    // Rows follow the analyses in the history, each written once, and only up to the tapback time.
+    let mut history = SpectrumHistory::new(16, 1);
+    let mut ring = SpectrogramRing::new(4);
+    for i in 0..3 {
+        history.push(i as f64, &[i as f32]);
+    }
+    let written: Vec<(usize, f32)> = ring
+        .take_new(&history, 1_f64)
+        .into_iter()
+        .map(|(row, spectrum)| (row, spectrum[0]))
+        .collect();
+    assert_eq!(written, vec![(0, 0_f32), (1, 1_f32)]);
+    assert!(ring.take_new(&history, 1.5_f64).is_empty(), "nothing new yet");
+    for i in 3..10 {
+        history.push(i as f64, &[i as f32]);
+    }
+    let written: Vec<(usize, f32)> = ring
+        .take_new(&history, 9_f64)
+        .into_iter()
+        .map(|(row, spectrum)| (row, spectrum[0]))
+        .collect();
+    assert_eq!(
+        written,
+        vec![(2, 6_f32), (3, 7_f32), (0, 8_f32), (1, 9_f32)],
+        "frames 2 to 5 would be overwritten right away"
+    );
+    assert_eq!(ring.row_for_age(0), Some(1));
+
+    assert_eq!(SpectrogramRing::default().rows(), DEFAULT_SPECTROGRAM_ROWS);
+    assert_eq!(SpectrogramRing::new(0).rows(), 1);
+    println!("spectrogram ring: newest frame in row {}", ring.write_row());
+}
@@ -0,0 +1,89 @@
+use asset_payload::payloads::{DEBUG_VERT, MIDI_FILE, SOUND_FONT_FILE, SPECTROGRAM_FRAG};
+use bath::midi::player::MidiPlayer;
+use bath::midi::program::ProgramMap;
+use bath::render::raylib::RaylibRenderer;
+use bath::render::raylib_util::{EXPERIMENTAL_WINDOW_HEIGHT, EXPERIMENTAL_WINDOW_WIDTH};
+use bath::render::{renderer::Renderer, renderer::RendererVector2};
+use bath::sound_render::raylib::RaylibFFTTexture;
+use bath::sound_render::sound_renderer::{
+    FFTTexture, AUDIO_STREAM_RING_BUFFER_SIZE, BUFFER_SIZE, FFT_WINDOW_SIZE, MONO, PER_SAMPLE_BIT_DEPTH_HARDCODED,
+    SAMPLE_RATE_HARDCODED,
+};
+use bath::sound_render::spectrogram::{SpectrogramRing, WRITE_ROW_UNIFORM};
+use bath::sound_render::spectrum_analyzer::SpectrumAnalyzer;
+use bath::sound_render::spectrum_bands::{BandLayout, BandScale};
+use bath::sound_render::spectrum_history::{SpectrumClock, SpectrumHistory};
+use raylib::core::audio::RaylibAudio;
+use raylib::texture::RaylibTexture2D;
+use std::slice::from_raw_parts;
+
+fn main() {
+    let mut midi_player = MidiPlayer::new(
+        SAMPLE_RATE_HARDCODED as i32,
+        MONO as u16,
+        MIDI_FILE(),
+        SOUND_FONT_FILE(),
+        &ProgramMap::default(),
+    )
+    .expect("Failed to start MIDI player");
+
+    let mut render = RaylibRenderer::init(EXPERIMENTAL_WINDOW_WIDTH, EXPERIMENTAL_WINDOW_HEIGHT);
+    let i_resolution = RendererVector2::new(
+        render.handle.get_screen_width() as f32,
+        render.handle.get_screen_height() as f32,
+    );
+    let mut buffer_a = render.init_render_target(i_resolution, true);
+    let mut shader = render.load_shader_full(DEBUG_VERT(), SPECTROGRAM_FRAG());
+    render.set_uniform_vec2(&mut shader, "iResolution", i_resolution);
+
+    // Each audio chunk is averaged 2:1 into `fft_data` below, so the analyzer sees half the stream rate.
+    let analyzer = SpectrumAnalyzer::new(FFT_WINDOW_SIZE, (SAMPLE_RATE_HARDCODED / 2) as f32);
+    let ring = SpectrogramRing::default();
+    let mut fft = RaylibFFTTexture {
+        bands: BandLayout::for_analyzer(BandScale::Mel, BUFFER_SIZE, &analyzer),
+        analyzer,
+        // Enough history that a burst of analyses between two frames still all get a row.
+        history: SpectrumHistory::new(ring.rows(), BUFFER_SIZE),
+        clock: SpectrumClock::SamplePosition,
+        spectrogram: Some(ring),
+        tapback_pos: 0_f32,
+    };
+    let mut fft_data = [0_f32; FFT_WINDOW_SIZE];
+    let mut fft_image = fft.init_audio_texture();
+    let mut fft_texture = render
+        .handle
+        .load_texture_from_image(&render.thread, &fft_image)
+        .unwrap();
+    render.set_uniform_sampler2d(&mut shader, "iChannel0", &fft_texture);
+
+    let raylib_audio = RaylibAudio::init_audio_device().unwrap();
+    raylib_audio.set_audio_stream_buffer_size_default(AUDIO_STREAM_RING_BUFFER_SIZE as i32);
+    let mut audio_stream = raylib_audio.new_audio_stream(SAMPLE_RATE_HARDCODED, PER_SAMPLE_BIT_DEPTH_HARDCODED, MONO);
+    audio_stream.play();
+    let mut chunk_samples = [0_i16; AUDIO_STREAM_RING_BUFFER_SIZE];
+    let mut chunk_pcm = [0_f32; AUDIO_STREAM_RING_BUFFER_SIZE];
+
+    while !render.handle.window_should_close() {
+        if audio_stream.is_processed() {
+            midi_player.fill(&mut chunk_pcm);
+            for (sample, pcm) in chunk_samples.iter_mut().zip(chunk_pcm.iter()) {
+                *sample = (pcm.clamp(-1_f32, 1_f32) * i16::MAX as f32) as i16;
+            }
+            let _ = audio_stream.update(&chunk_samples);
+            for (fft_sample, wav_sample) in fft_data.iter_mut().zip(chunk_samples.chunks_exact(2)) {
+                let avg = (wav_sample[0] as i32 + wav_sample[1] as i32) / 2_i32;
+                *fft_sample = avg as f32 / i16::MAX as f32;
+            }
+            fft.capture_frame(&mut fft_data);
+        }
+        fft.render_frame(&mut fft_image);
+        let len = fft_image.get_pixel_data_size();
+        let pixels = unsafe { from_raw_parts(fft_image.data as *const u8, len) };
+        fft_texture.update_texture(pixels).unwrap();
+        render.set_uniform_sampler2d(&mut shader, "iChannel0", &fft_texture);
+        if let Some(ring) = &fft.spectrogram {
+            render.set_uniform_int(&mut shader, WRITE_ROW_UNIFORM, ring.write_row() as i32);
+        }
+        render.draw_shader_screen(&mut shader, &mut buffer_a);
+    }
+}
@@ -0,0 +1,95 @@
+use bath::sound_render::sound_renderer::BUFFER_SIZE;
+use bath::sound_render::spectrum_analyzer::SpectrumAnalyzer;
+use std::f32::consts::PI;
+
+const FFT_SIZE: usize = 64;
+// Golden values from a float64 direct DFT following the WebAudio analyser algorithm step by step: Blackman
+// window, 1/N scaling, smoothing 0.8 over linear magnitudes, -100..-30 dB byte mapping.
+const GOLDEN_BYTES_FIRST: [u8; 32] = [
+    0, 0, 0, 167, 225, 242, 225, 167, 0, 0, 56, 152, 187, 187, 152, 56, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+const GOLDEN_BYTES_SECOND: [u8; 32] = [
+    0, 0, 0, 160, 218, 234, 218, 175, 203, 220, 203, 167, 180, 180, 145, 49, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0,
+];
+// (bin, dB) after the second frame.
+const GOLDEN_DECIBELS_SECOND: [(usize, f32); 6] = [
+    (3, -55.9155),
+    (5, -35.4933),
+    (7, -51.6931),
+    (9, -39.5728),
+    (12, -50.572),
+    (15, -86.4032),
+];
+
+// cargo run --example spectrum_analyzer
+fn main() {
+    // An on-bin sine plus an off-bin cosine that leaks into its neighbours, then a different on-bin sine.
+    let first = frame(|n| 0.5_f32 * sine(5_f32, n) + 0.1_f32 * (2_f32 * PI * 12.5_f32 * n / FFT_SIZE as f32).cos());
+    let second = frame(|n| 0.25_f32 * sine(9_f32, n));
+    let mut analyzer = SpectrumAnalyzer::new(FFT_SIZE, 44_100_f32);
+    assert_eq!(analyzer.frequency_bin_count(), 32);
+    let mut bytes = [0_u8; 32];
+
+    analyzer.push_samples(&first);
+    analyzer.analyze();
+    analyzer.byte_frequency_data(&mut bytes);
+    assert_eq!(bytes, GOLDEN_BYTES_FIRST, "first frame");
+
+    analyzer.push_samples(&second);
+    analyzer.analyze();
+    analyzer.byte_frequency_data(&mut bytes);
+    assert_eq!(bytes, GOLDEN_BYTES_SECOND, "second frame, smoothed with the first");
+    let mut decibels = [0_f32; 32];
+    analyzer.float_frequency_data(&mut decibels);
+    for (bin, expected) in GOLDEN_DECIBELS_SECOND {
+        assert!(
+            (decibels[bin] - expected).abs() < 0.01_f32,
+            "bin {} {} dB, expected {}",
+            bin,
+            decibels[bin],
+            expected
+        );
+    }
+    let mut normalized = [0_f32; 32];
+    analyzer.normalized_frequency_data(&mut normalized);
+    for (byte, value) in bytes.iter().zip(normalized) {
+        assert_eq!(*byte, (value * 255_f32).floor() as u8);
+    }
+
+    // Samples arriving in small blocks end up in the same window as one whole frame.
+    let mut streamed = SpectrumAnalyzer::new(FFT_SIZE, 44_100_f32);
+    streamed.push_samples(&second);
+    for block in first.chunks(24) {
+        streamed.push_samples(block);
+    }
+    streamed.analyze();
+    streamed.byte_frequency_data(&mut bytes);
+    assert_eq!(bytes, GOLDEN_BYTES_FIRST, "streamed");
+
+    // Without smoothing each analysis stands alone.
+    analyzer.reset();
+    analyzer.smoothing_time_constant = 0_f32;
+    analyzer.push_samples(&second);
+    analyzer.analyze();
+    analyzer.analyze();
+    analyzer.float_frequency_data(&mut decibels);
+    assert!((decibels[9] - 20_f32 * (0.25_f32 * 0.42_f32 * 0.5_f32).log10()).abs() < 1e-3_f32);
+    assert!(
+        decibels[20] < analyzer.min_decibels,
+        "only rounding noise away from the tone"
+    );
+
+    let analyzer = SpectrumAnalyzer::default();
+    assert_eq!(analyzer.frequency_bin_count(), BUFFER_SIZE);
+    assert_eq!(analyzer.bin_frequency(1), 44_100_f32 / 1024_f32);
+    println!("spectrum analyzer matches the WebAudio golden values");
+}
+
+fn frame(sample: impl Fn(f32) -> f32) -> Vec<f32> {
+    (0..FFT_SIZE).map(|n| sample(n as f32)).collect()
+}
+
+fn sine(bin: f32, n: f32) -> f32 {
+    (2_f32 * PI * bin * n / FFT_SIZE as f32).sin()
+}
@@ -0,0 +1,102 @@
+use bath::sound_render::sound_renderer::{BUFFER_SIZE, SAMPLE_RATE};
+use bath::sound_render::spectrum_analyzer::SpectrumAnalyzer;
+use bath::sound_render::spectrum_bands::{BandLayout, BandScale, DEFAULT_MIN_BAND_HZ};
+
+// cargo run --example spectrum_bands
+fn main() {
+    let bin_hz = SAMPLE_RATE / (2 * BUFFER_SIZE) as f32;
+    let ramp: Vec<f32> = (0..BUFFER_SIZE).map(|bin| bin as f32).collect();
+
+    // The default layout is the plain bins the texture always held.
+    let linear = BandLayout::default();
+    assert_eq!((linear.band_count(), linear.scale()), (BUFFER_SIZE, BandScale::Linear));
+    let mut bands = vec![0_f32; linear.band_count()];
+    linear.apply(&ramp, &mut bands);
+    assert_eq!(bands, ramp);
+    assert_eq!(linear.center_frequency(1), 1.5_f32 * bin_hz);
+
+    for scale in BandScale::ALL {
+        assert_eq!(BandScale::from_name(scale.name()), Some(scale));
+        let layout = BandLayout::new(scale, 64, BUFFER_SIZE, SAMPLE_RATE);
+        let centers = layout.center_frequencies();
+        assert!(centers.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", scale);
+        for (band, &center) in centers.iter().enumerate() {
+            let (low, high) = layout.band_range(band);
+            assert!(low < center && center < high, "{:?} band {}", scale, band);
+            if band > 0 {
+                assert_eq!(layout.band_range(band - 1).1, low, "contiguous");
+            }
+        }
+        let (low, _) = layout.band_range(0);
+        let (_, high) = layout.band_range(layout.band_count() - 1);
+        assert!(high <= SAMPLE_RATE / 2_f32 * 1.0001_f32);
+        if scale != BandScale::Linear {
+            assert!(low >= DEFAULT_MIN_BAND_HZ * 0.9999_f32, "{:?} starts at {}", scale, low);
+        }
+
+        // Wide bands take their loudest bin, bands narrower than a bin interpolate at their center.
+        let mut bands = vec![0_f32; layout.band_count()];
+        layout.apply(&ramp, &mut bands);
+        for (band, &value) in bands.iter().enumerate() {
+            let (low, high) = layout.band_range(band);
+            if high - low < bin_hz && (low / bin_hz).ceil() >= (high / bin_hz).ceil() {
+                assert!(
+                    (value - centers[band] / bin_hz).abs() < 1e-3_f32,
+                    "{:?} band {}",
+                    scale,
+                    band
+                );
+            } else {
+                assert!(
+                    value * bin_hz < high && value * bin_hz >= low - 1e-2_f32,
+                    "{:?} band {}",
+                    scale,
+                    band
+                );
+            }
+        }
+    }
+
+    // Perceptual layouts give the bass many more texels than the linear one.
+    let below = |layout: &BandLayout, hz: f32| layout.center_frequencies().iter().filter(|&&f| f < hz).count();
+    assert_eq!(below(&linear, 500_f32), 12);
+    for scale in [
+        BandScale::Logarithmic,
+        BandScale::Mel,
+        BandScale::Bark,
+        BandScale::Octave,
+    ] {
+        let layout = BandLayout::new(scale, BUFFER_SIZE, BUFFER_SIZE, SAMPLE_RATE);
+        assert!(below(&layout, 500_f32) > 5 * below(&linear, 500_f32), "{:?}", scale);
+    }
+    let mel = BandLayout::with_range(BandScale::Mel, 2, BUFFER_SIZE, SAMPLE_RATE, 1_f32, 2000_f32);
+    assert!(
+        (mel.band_range(0).1 - 675.75_f32).abs() < 0.1_f32,
+        "half way up the mel scale"
+    );
+
+    // 30 bands over the 10 octaves from 20 Hz round to third octaves on the 1 kHz grid, 25 Hz to 16 kHz being
+    // the ones that fit below Nyquist.
+    let octave = BandLayout::new(BandScale::Octave, 30, BUFFER_SIZE, SAMPLE_RATE);
+    let centers = octave.center_frequencies();
+    assert!(centers.iter().any(|&f| (f - 1000_f32).abs() < 0.01_f32));
+    assert!(centers
+        .windows(2)
+        .all(|pair| (pair[1] / pair[0] - 2_f32.powf(1_f32 / 3_f32)).abs() < 1e-4_f32));
+    assert_eq!(octave.band_count(), 29);
+
+    let analyzer = SpectrumAnalyzer::new(1024, 16_000_f32);
+    let bark = BandLayout::for_analyzer(BandScale::Bark, 24, &analyzer);
+    assert_eq!((bark.bin_count(), bark.band_count()), (512, 24));
+    assert!(bark.band_range(23).1 <= 8_000.1_f32);
+    println!(
+        "band layouts: bark centers {:.0} Hz .. {:.0} Hz, mel gives {} of {} texels below 500 Hz",
+        bark.center_frequency(0),
+        bark.center_frequency(23),
+        below(
+            &BandLayout::new(BandScale::Mel, BUFFER_SIZE, BUFFER_SIZE, SAMPLE_RATE),
+            500_f32
+        ),
+        BUFFER_SIZE
+    );
+}
@@ -0,0 +1,85 @@
+use bath::sound_render::spectrum_analyzer::SpectrumAnalyzer;
+use bath::sound_render::spectrum_history::{SpectrumClock, SpectrumHistory};
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+const FFT_SIZE: usize = 64;
+const SAMPLE_RATE: f32 = 6_400_f32;
+const HOP_SECS: f64 = FFT_SIZE as f64 / SAMPLE_RATE as f64;
+
+// cargo run --example spectrum_history
+fn main() {
+    let mut history = SpectrumHistory::new(4, 2);
+    assert!(history.is_empty() && history.at_time(1_f64).is_none());
+    for i in 0..6 {
+        history.push(i as f64 * 0.25_f64, &[i as f32, -(i as f32)]);
+    }
+    assert_eq!((history.len(), history.capacity()), (4, 4));
+    assert_eq!(history.latest(), Some((1.25_f64, &[5_f32, -5_f32][..])));
+    assert_eq!(history.get(3).unwrap().1, &[2_f32, -2_f32]);
+    assert!(history.get(4).is_none(), "overwritten");
+    assert_eq!(history.at_time(0.875_f64).unwrap().1[0], 3_f32);
+    assert_eq!(history.at_time(0.75_f64).unwrap().1[0], 3_f32, "inclusive");
+    assert_eq!(history.at_time(9_f64).unwrap().1[0], 5_f32);
+    assert_eq!(
+        history.at_time(0_f64).unwrap().1[0],
+        2_f32,
+        "clamped to the oldest kept"
+    );
+    assert_eq!(history.ago(1.25_f64, 0.5_f64).unwrap().0, 0.75_f64);
+    history.push(1.5_f64, &[7_f32]);
+    assert_eq!(history.latest().unwrap().1, &[7_f32, 0_f32], "short spectra are padded");
+    history.clear();
+    assert!(history.latest().is_none());
+
+    // Driven by the sample clock, each analysis is stamped with the stream time of its newest sample, so
+    // looking back 0.15s finds the tone that was playing then even though it all arrived at once.
+    let mut analyzer = SpectrumAnalyzer::new(FFT_SIZE, SAMPLE_RATE);
+    analyzer.smoothing_time_constant = 0_f32;
+    let clock = SpectrumClock::SamplePosition;
+    let mut history = SpectrumHistory::with_duration(0.5_f64, HOP_SECS, analyzer.frequency_bin_count());
+    assert_eq!(history.capacity(), 51);
+    let signal: Vec<f32> = (0..(0.3_f32 * SAMPLE_RATE) as usize)
+        .map(|n| {
+            let bin = if n < (0.2_f32 * SAMPLE_RATE) as usize {
+                4_f32
+            } else {
+                12_f32
+            };
+            (2_f32 * PI * bin * n as f32 / FFT_SIZE as f32).sin()
+        })
+        .collect();
+    analyzer.process(&signal, |analyzer| {
+        let mut spectrum = vec![0_f32; analyzer.frequency_bin_count()];
+        analyzer.float_frequency_data(&mut spectrum);
+        history.push(clock.now(analyzer), &spectrum);
+    });
+    assert_eq!(analyzer.sample_position(), signal.len() as u64);
+    let now = clock.now(&analyzer);
+    assert!((now - 0.3_f64).abs() < 1e-9);
+    assert_eq!(history.len(), signal.len() / FFT_SIZE);
+    let (time, spectrum) = history.latest().unwrap();
+    assert_eq!((time, peak_bin(spectrum)), (now, 12));
+    let (time, spectrum) = history.ago(now, 0.15_f64).unwrap();
+    assert!(time <= now - 0.15_f64 && time > now - 0.15_f64 - HOP_SECS);
+    assert_eq!(peak_bin(spectrum), 4);
+
+    // Seeking moves the stream position without touching the analysis.
+    analyzer.set_sample_position(SAMPLE_RATE as u64 * 10);
+    assert!((clock.now(&analyzer) - 10_f64).abs() < 1e-9);
+
+    let start = Instant::now() - Duration::from_millis(250);
+    let elapsed = SpectrumClock::Monotonic(start).now(&analyzer);
+    assert!((0.25_f64..1_f64).contains(&elapsed), "{}", elapsed);
+    println!(
+        "spectrum history: {} spectra over {:.2}s, 0.15s ago was bin 4",
+        history.len(),
+        now
+    );
+}
+
+fn peak_bin(spectrum: &[f32]) -> usize {
+    (0..spectrum.len())
+        .max_by(|&a, &b| spectrum[a].total_cmp(&spectrum[b]))
+        .unwrap()
+}
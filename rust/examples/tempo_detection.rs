@@ -0,0 +1,118 @@
+use asset_payload::payloads::SHADERTOY_EXPERIMENT_OGG;
+use bath::audio_analysis::beat::{track_beats, DEFAULT_BEATS_PER_BAR};
+use bath::audio_analysis::tempo::{detect_tempo_native, TempoEstimate};
+use bath::audio_analysis::util::{decode_ogg_to_mono, detect_bpm_aubio_ogg, BpmDetector};
+use std::f32::consts::PI;
+
+const CLICK_SAMPLE_RATE: u32 = 12_000;
+const CLICK_TRACK_SECS: f32 = 30.0;
+// One analysis hop at 12kHz is ~11.6ms.
+const ONSET_TOLERANCE_SECS: f32 = 0.02;
+const BPM_TOLERANCE_RATIO: f32 = 0.02;
+// Reference tempo of `shadertoy_music_experiment_min_bitrate.ogg`, checked against aubio wherever it is linked so
+// the native estimate is held to it on every target.
+const SHADERTOY_EXPERIMENT_BPM: f32 = 125.0;
+
+// cargo run --example tempo_detection
+fn main() {
+    for bpm in [75_f32, 100_f32, 128_f32, 140_f32] {
+        let (samples, clicks) = click_track(bpm);
+        let estimate = detect_tempo_native(&samples, CLICK_SAMPLE_RATE);
+        assert!(
+            (estimate.bpm - bpm).abs() <= bpm * BPM_TOLERANCE_RATIO,
+            "click track at {} bpm estimated as {}",
+            bpm,
+            estimate.bpm
+        );
+        assert_eq!(estimate.onsets.len(), clicks.len(), "onset count at {} bpm", bpm);
+        for (onset, click) in estimate.onsets.iter().zip(&clicks) {
+            assert!(
+                (onset - click).abs() <= ONSET_TOLERANCE_SECS,
+                "onset {} vs click {}",
+                onset,
+                click
+            );
+        }
+        println!(
+            "click track {:>5.1} bpm -> {:.2} (confidence {:.2})",
+            bpm, estimate.bpm, estimate.confidence
+        );
+
+        let grid = track_beats(&samples, CLICK_SAMPLE_RATE, DEFAULT_BEATS_PER_BAR);
+        assert_eq!(grid.beats.len(), clicks.len(), "beat count at {} bpm", bpm);
+        for (beat, click) in grid.beats.iter().zip(&clicks) {
+            assert!(
+                (beat - click).abs() <= ONSET_TOLERANCE_SECS,
+                "beat {} vs click {}",
+                beat,
+                click
+            );
+        }
+        // `click_track` accents every fourth click starting with the first.
+        assert_eq!(grid.first_downbeat, 0, "downbeat phase at {} bpm", bpm);
+        assert!((grid.bpm_at(CLICK_TRACK_SECS * 0.5_f32) - bpm).abs() <= bpm * BPM_TOLERANCE_RATIO);
+    }
+
+    // A sample rate too low for a two-bin spectrum gives a zero estimate instead of panicking.
+    assert_eq!(detect_tempo_native(&[0.5_f32; 64], 1), TempoEstimate::default());
+
+    let ogg_bytes = SHADERTOY_EXPERIMENT_OGG();
+    let (samples, sample_rate) = decode_ogg_to_mono(ogg_bytes).expect("failed to decode bundled OGG");
+    let native = detect_tempo_native(&samples, sample_rate);
+    assert!(native.bpm > 0_f32, "native detector found no tempo in the bundled OGG");
+    println!(
+        "native: {:.2} bpm (confidence {:.2}), {} onsets",
+        native.bpm,
+        native.confidence,
+        native.onsets.len()
+    );
+    assert!(
+        matches_reference(native.bpm),
+        "native {} bpm vs reference {} bpm",
+        native.bpm,
+        SHADERTOY_EXPERIMENT_BPM
+    );
+    if BpmDetector::AUBIO_AVAILABLE {
+        let aubio_bpm = detect_bpm_aubio_ogg(ogg_bytes);
+        println!("aubio:  {:.2} bpm", aubio_bpm);
+        assert!(
+            matches_reference(aubio_bpm),
+            "aubio {} bpm vs reference {} bpm",
+            aubio_bpm,
+            SHADERTOY_EXPERIMENT_BPM
+        );
+    }
+}
+
+// Tempo octave errors are a matter of taste between detectors, so half/double time also counts.
+fn matches_reference(bpm: f32) -> bool {
+    [0.5_f32, 1_f32, 2_f32]
+        .iter()
+        .any(|factor| (bpm * factor - SHADERTOY_EXPERIMENT_BPM).abs() <= SHADERTOY_EXPERIMENT_BPM * BPM_TOLERANCE_RATIO)
+}
+
+// Decaying sine bursts on every beat, accented on the downbeat, over low level noise.
+fn click_track(bpm: f32) -> (Vec<f32>, Vec<f32>) {
+    let sample_rate = CLICK_SAMPLE_RATE as f32;
+    let mut samples = vec![0_f32; (CLICK_TRACK_SECS * sample_rate) as usize];
+    let mut seed = 1_u32;
+    for sample in samples.iter_mut() {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        *sample = ((seed >> 8) as f32 / (1 << 24) as f32 - 0.5_f32) * 0.01_f32;
+    }
+    let period = 60_f32 / bpm;
+    let burst_len = (0.03_f32 * sample_rate) as usize;
+    let mut clicks = Vec::new();
+    let mut time = 0.25_f32;
+    while time < CLICK_TRACK_SECS - 0.1_f32 {
+        let amplitude = if clicks.len() % 4 == 0 { 1_f32 } else { 0.6_f32 };
+        let start = (time * sample_rate) as usize;
+        for i in 0..burst_len {
+            let envelope = (-(i as f32) / (0.005_f32 * sample_rate)).exp();
+            samples[start + i] += amplitude * envelope * (2_f32 * PI * 1_337_f32 * i as f32 / sample_rate).sin();
+        }
+        clicks.push(time);
+        time += period;
+    }
+    (samples, clicks)
+}
@@ -18,6 +18,7 @@ fn main() {
 // cargo run --example ice_sheets --features tests-only
 // cargo run --example drekker_effect --features tests-only
 // cargo run --example fft_visualizer --features tests-only
+// cargo run --example spectrogram_visualizer --features tests-only
 // cargo run --example debug_space --features tests-only
 // cargo run --example audio_test --features tests-only
 // cargo run --example rlgl_test --features tests-only
@@ -27,3 +28,16 @@ fn main() {
 // cargo run --example ghost_dither_opengl11_texture --features tests-only,opengl-11
 // cargo run --example ghost_dither_glsl100 --features tests-only,glsl-100
 // cargo run --example room --features tests-only,opengl-11
+// cargo run --example midi_round_trip
+// cargo run --example tempo_detection
+// cargo run --example rhythm_data_format
+// cargo run --example rhythm_judgement
+// cargo run --example latency_calibration
+// cargo run --example chart_difficulty
+// cargo run --example click_track
+// cargo run --example spectrum_analyzer
+// cargo run --example window_functions
+// cargo run --example spectrum_history
+// cargo run --example spectrum_bands
+// cargo run --example spectrogram_ring
+// cargo run --example note_timeline
@@ -0,0 +1,106 @@
+use bath::audio_analysis::fft::WindowFunction;
+use bath::sound_render::spectrum_analyzer::SpectrumAnalyzer;
+use std::f32::consts::PI;
+
+const FFT_SIZE: usize = 256;
+// (window, main lobe half width in bins, highest sidelobe allowed in dB below the peak) for a tone half way
+// between bins, where leakage is worst.
+const SIDELOBES: [(WindowFunction, usize, f32); 5] = [
+    (WindowFunction::Rectangular, 1, -9_f32),
+    (WindowFunction::Hann, 2, -30_f32),
+    (WindowFunction::Hamming, 2, -40_f32),
+    (WindowFunction::Blackman, 3, -56_f32),
+    (WindowFunction::BlackmanHarris, 4, -90_f32),
+];
+
+// cargo run --example window_functions
+fn main() {
+    for (window_function, half_width, max_sidelobe) in SIDELOBES {
+        assert_eq!(WindowFunction::from_name(window_function.name()), Some(window_function));
+        let mut analyzer = analyzer(window_function);
+
+        let decibels = spectrum(&mut analyzer, 40.3_f32);
+        assert_eq!(peak_bin(&decibels), 40, "{:?}", window_function);
+
+        let decibels = spectrum(&mut analyzer, 40.5_f32);
+        let peak = decibels[40].max(decibels[41]);
+        let sidelobe = decibels
+            .iter()
+            .enumerate()
+            .filter(|&(bin, _)| bin + half_width <= 40 || bin > 40 + half_width)
+            .map(|(_, &db)| db)
+            .fold(f32::NEG_INFINITY, f32::max)
+            - peak;
+        assert!(
+            sidelobe < max_sidelobe,
+            "{:?} sidelobe {} dB, expected below {}",
+            window_function,
+            sidelobe,
+            max_sidelobe
+        );
+        if window_function == WindowFunction::Rectangular {
+            assert!(sidelobe > -20_f32, "an unwindowed off-bin tone leaks");
+        }
+        println!("{:>16}: sidelobes {:.1} dB", window_function.name(), sidelobe);
+    }
+    assert_eq!(WindowFunction::default(), WindowFunction::Blackman);
+    assert_eq!(WindowFunction::from_name("kaiser"), None);
+
+    // A quarter hop analyses four times per FFT size, and each analysis sees the newest FFT size samples.
+    let signal: Vec<f32> = (0..4096).map(|n| tone(12_f32, n) * (n as f32 / 4096_f32)).collect();
+    let mut hopped = analyzer(WindowFunction::Hann);
+    hopped.set_hop_size(FFT_SIZE / 4);
+    assert_eq!(hopped.hop_size(), 64);
+    let mut analyses = 0;
+    for block in signal.chunks(100) {
+        analyses += hopped.process(block, |_| {});
+    }
+    assert_eq!(analyses, 4096 / 64);
+    let mut whole = analyzer(WindowFunction::Hann);
+    whole.push_samples(&signal[signal.len() - FFT_SIZE..]);
+    whole.analyze();
+    let (mut a, mut b) = ([0_f32; FFT_SIZE / 2], [0_f32; FFT_SIZE / 2]);
+    hopped.float_frequency_data(&mut a);
+    whole.float_frequency_data(&mut b);
+    assert_eq!(a, b);
+
+    let mut clamped = analyzer(WindowFunction::Hann);
+    clamped.set_hop_size(0);
+    assert_eq!(clamped.hop_size(), 1);
+    clamped.set_hop_size(4 * FFT_SIZE);
+    assert_eq!(clamped.hop_size(), FFT_SIZE);
+    assert_eq!(clamped.process(&signal[..FFT_SIZE - 1], |_| {}), 0);
+    let mut peaks = Vec::new();
+    clamped.process(&signal[FFT_SIZE - 1..FFT_SIZE], |analyzer| {
+        let mut decibels = [0_f32; FFT_SIZE / 2];
+        analyzer.float_frequency_data(&mut decibels);
+        peaks.push(peak_bin(&decibels));
+    });
+    assert_eq!(peaks, vec![12]);
+}
+
+fn analyzer(window_function: WindowFunction) -> SpectrumAnalyzer {
+    let mut analyzer = SpectrumAnalyzer::new(FFT_SIZE, 44_100_f32);
+    analyzer.smoothing_time_constant = 0_f32;
+    analyzer.set_window_function(window_function);
+    analyzer
+}
+
+fn spectrum(analyzer: &mut SpectrumAnalyzer, bin: f32) -> Vec<f32> {
+    let frame: Vec<f32> = (0..FFT_SIZE).map(|n| tone(bin, n)).collect();
+    analyzer.push_samples(&frame);
+    analyzer.analyze();
+    let mut decibels = vec![0_f32; analyzer.frequency_bin_count()];
+    analyzer.float_frequency_data(&mut decibels);
+    decibels
+}
+
+fn peak_bin(decibels: &[f32]) -> usize {
+    (0..decibels.len())
+        .max_by(|&a, &b| decibels[a].total_cmp(&decibels[b]))
+        .unwrap()
+}
+
+fn tone(bin: f32, n: usize) -> f32 {
+    (2_f32 * PI * bin * n as f32 / FFT_SIZE as f32).sin()
+}
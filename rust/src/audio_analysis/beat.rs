@@ -0,0 +1,219 @@
+use crate::audio_analysis::tempo::{estimate_bpm, OnsetEnvelope};
+
+pub const DEFAULT_BEATS_PER_BAR: u32 = 4;
+// How hard the dynamic program penalizes inter-beat intervals that stray from the global period.
+const TIGHTNESS: f32 = 100.0;
+// Beats on each side whose intervals are pooled (median) into one tempo curve point.
+const TEMPO_CURVE_RADIUS: usize = 4;
+const EDGE_TRIM_RATIO: f32 = 0.5;
+const EDGE_TRIM_RADIUS: usize = 2;
+
+/// Tracked beats of a song. `beats[first_downbeat]` starts bar 0 and beats before it count as a pickup.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BeatGrid {
+    pub beats: Vec<f32>,
+    pub beats_per_bar: u32,
+    pub first_downbeat: u32,
+    /// (time, bpm) at each beat from the second one on.
+    pub tempo_curve: Vec<[f32; 2]>,
+}
+
+impl BeatGrid {
+    /// Regular grid for a known constant tempo, starting with a downbeat at `offset`.
+    pub fn from_bpm(bpm: f32, offset: f32, duration: f32, beats_per_bar: u32) -> Self {
+        if bpm <= 0_f32 {
+            return Self::default();
+        }
+        let period = 60_f32 / bpm;
+        let count = ((duration - offset) / period).floor().max(0_f32) as usize + 1;
+        let beats: Vec<f32> = (0..count).map(|i| offset + i as f32 * period).collect();
+        let tempo_curve = beats.iter().skip(1).map(|&time| [time, bpm]).collect();
+        Self {
+            beats,
+            beats_per_bar: beats_per_bar.max(1),
+            first_downbeat: 0,
+            tempo_curve,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.beats.is_empty()
+    }
+
+    /// (bar, beat within bar) of beat `index`. Pickup beats before `first_downbeat` land in bar -1.
+    pub fn position_of(&self, index: usize) -> (i32, u32) {
+        let per_bar = self.beats_per_bar.max(1) as i32;
+        let relative = index as i32 - self.first_downbeat as i32;
+        (relative.div_euclid(per_bar), relative.rem_euclid(per_bar) as u32)
+    }
+
+    pub fn downbeats(&self) -> impl Iterator<Item = f32> + '_ {
+        self.beats
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.position_of(*index).1 == 0)
+            .map(|(_, &time)| time)
+    }
+
+    pub fn next_beat_after(&self, time: f32) -> Option<f32> {
+        let index = self.beats.partition_point(|&beat| beat <= time);
+        self.beats.get(index).copied()
+    }
+
+    pub fn nearest_beat(&self, time: f32) -> Option<usize> {
+        let index = self.beats.partition_point(|&beat| beat < time);
+        let before = index.checked_sub(1);
+        let after = (index < self.beats.len()).then_some(index);
+        match (before, after) {
+            (Some(b), Some(a)) if time - self.beats[b] <= self.beats[a] - time => Some(b),
+            (_, Some(a)) => Some(a),
+            (before, None) => before,
+        }
+    }
+
+    /// Moves `time` onto the nearest 1/`subdivision` beat line. Times outside the grid are returned unchanged.
+    pub fn snap(&self, time: f32, subdivision: u32) -> f32 {
+        let subdivision = subdivision.max(1) as f32;
+        let index = self.beats.partition_point(|&beat| beat <= time);
+        if index == 0 || index >= self.beats.len() {
+            return time;
+        }
+        let start = self.beats[index - 1];
+        let length = self.beats[index] - start;
+        let steps = ((time - start) / length * subdivision).round();
+        start + steps / subdivision * length
+    }
+
+    pub fn bpm_at(&self, time: f32) -> f32 {
+        let index = self.tempo_curve.partition_point(|&[t, _]| t <= time);
+        self.tempo_curve
+            .get(index.saturating_sub(1))
+            .map_or(0_f32, |&[_, bpm]| bpm)
+    }
+}
+
+/// Tracks beats through `samples` with Ellis-style dynamic programming over the spectral-flux envelope:
+/// every beat candidate scores its onset strength plus the best predecessor roughly one period earlier.
+/// The bar phase goes to whichever beat-of-bar carries the strongest onsets on average.
+pub fn track_beats(samples: &[f32], sample_rate: u32, beats_per_bar: u32) -> BeatGrid {
+    if samples.is_empty() || sample_rate == 0 {
+        return BeatGrid::default();
+    }
+    let envelope = OnsetEnvelope::from_mono(samples, sample_rate);
+    let novelty = envelope.novelty();
+    let (bpm, _) = estimate_bpm(&novelty, envelope.frame_rate);
+    if bpm <= 0_f32 {
+        return BeatGrid::default();
+    }
+    let period = 60_f32 * envelope.frame_rate / bpm;
+    let beat_frames = trim_weak_edges(&novelty, dynamic_programming_beats(&novelty, period));
+    let beats_per_bar = beats_per_bar.max(1);
+    let first_downbeat = strongest_bar_phase(&novelty, &beat_frames, beats_per_bar);
+    let beats: Vec<f32> = beat_frames.iter().map(|&frame| envelope.frame_time(frame)).collect();
+    let tempo_curve = tempo_curve(&beats);
+    BeatGrid {
+        beats,
+        beats_per_bar,
+        first_downbeat,
+        tempo_curve,
+    }
+}
+
+fn dynamic_programming_beats(novelty: &[f32], period: f32) -> Vec<usize> {
+    let count = novelty.len();
+    let mean = novelty.iter().sum::<f32>() / count.max(1) as f32;
+    let std = (novelty.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / count.max(1) as f32).sqrt();
+    let strength: Vec<f32> = novelty
+        .iter()
+        .map(|v| if std > 0_f32 { v / std } else { 0_f32 })
+        .collect();
+    let min_gap = (period * 0.5_f32).round().max(1_f32) as usize;
+    let max_gap = (period * 2_f32).round() as usize;
+    let mut score = vec![0_f32; count];
+    let mut backlink: Vec<Option<usize>> = vec![None; count];
+    for frame in 0..count {
+        let mut best: Option<(f32, usize)> = None;
+        for gap in min_gap..=max_gap.min(frame) {
+            let previous = frame - gap;
+            let deviation = (gap as f32 / period).ln();
+            let candidate = score[previous] - TIGHTNESS * deviation * deviation;
+            if best.is_none_or(|(best_score, _)| candidate > best_score) {
+                best = Some((candidate, previous));
+            }
+        }
+        // A chain whose best link would cost more than it carries is worth less than starting fresh here.
+        score[frame] = strength[frame];
+        if let Some((best_score, previous)) = best {
+            if best_score > 0_f32 {
+                score[frame] += best_score;
+                backlink[frame] = Some(previous);
+            }
+        }
+    }
+    let tail_start = count.saturating_sub(period.round() as usize);
+    let Some(mut frame) = (tail_start..count).max_by(|&a, &b| score[a].total_cmp(&score[b])) else {
+        return Vec::new();
+    };
+    let mut frames = vec![frame];
+    while let Some(previous) = backlink[frame] {
+        frames.push(previous);
+        frame = previous;
+    }
+    frames.reverse();
+    frames
+}
+
+// The backtracked chain keeps marching through leading and trailing silence, so beats at either end whose
+// onset strength is under half the RMS of all beat strengths are dropped (as librosa does). Strength is the
+// local max around the beat since one onset often spreads its flux over neighbouring frames.
+fn trim_weak_edges(novelty: &[f32], beat_frames: Vec<usize>) -> Vec<usize> {
+    if beat_frames.is_empty() {
+        return beat_frames;
+    }
+    let strength = |frame: usize| {
+        let lo = frame.saturating_sub(EDGE_TRIM_RADIUS);
+        let hi = (frame + EDGE_TRIM_RADIUS + 1).min(novelty.len());
+        novelty[lo..hi].iter().copied().fold(0_f32, f32::max)
+    };
+    let strengths: Vec<f32> = beat_frames.iter().map(|&frame| strength(frame)).collect();
+    let rms = (strengths.iter().map(|s| s * s).sum::<f32>() / strengths.len() as f32).sqrt();
+    let threshold = EDGE_TRIM_RATIO * rms;
+    let start = strengths.iter().position(|&s| s >= threshold).unwrap_or(0);
+    let end = strengths
+        .iter()
+        .rposition(|&s| s >= threshold)
+        .map_or(strengths.len(), |i| i + 1);
+    beat_frames[start..end].to_vec()
+}
+
+fn strongest_bar_phase(novelty: &[f32], beat_frames: &[usize], beats_per_bar: u32) -> u32 {
+    let phases = beats_per_bar.min(beat_frames.len() as u32);
+    let strengths: Vec<f32> = (0..phases)
+        .map(|phase| {
+            let accents: Vec<f32> = beat_frames
+                .iter()
+                .skip(phase as usize)
+                .step_by(beats_per_bar as usize)
+                .map(|&frame| novelty[frame])
+                .collect();
+            accents.iter().sum::<f32>() / accents.len().max(1) as f32
+        })
+        .collect();
+    (0..phases)
+        .max_by(|&a, &b| strengths[a as usize].total_cmp(&strengths[b as usize]))
+        .unwrap_or(0)
+}
+
+fn tempo_curve(beats: &[f32]) -> Vec<[f32; 2]> {
+    let intervals: Vec<f32> = beats.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    (0..intervals.len())
+        .map(|i| {
+            let lo = i.saturating_sub(TEMPO_CURVE_RADIUS);
+            let hi = (i + TEMPO_CURVE_RADIUS + 1).min(intervals.len());
+            let mut window = intervals[lo..hi].to_vec();
+            window.sort_by(f32::total_cmp);
+            let median = window[window.len() / 2];
+            [beats[i + 1], 60_f32 / median]
+        })
+        .collect()
+}
@@ -1 +1,4 @@
+pub mod beat;
+pub mod fft;
+pub mod tempo;
 pub mod util;
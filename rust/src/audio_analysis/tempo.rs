@@ -0,0 +1,245 @@
+use crate::audio_analysis::fft::{hann_window, magnitude_spectrum};
+
+// Same analysis grid as the aubio path in `util`: 1024/512 at 44.1kHz, scaled for other sample rates.
+const REFERENCE_SAMPLE_RATE: f32 = 44_100.0;
+const REFERENCE_WINDOW_SIZE: usize = 1024;
+const REFERENCE_HOP_SIZE: usize = 512;
+const LOG_COMPRESSION: f32 = 100.0;
+const NOVELTY_MEAN_WINDOW_SECS: f32 = 0.1;
+const ONSET_PEAK_WINDOW_SECS: f32 = 0.05;
+const ONSET_MIN_GAP_SECS: f32 = 0.05;
+const ONSET_THRESHOLD_STD: f32 = 0.5;
+pub const MIN_BPM: f32 = 40.0;
+pub const MAX_BPM: f32 = 240.0;
+// Log-gaussian tempo prior (Ellis 2007) that settles octave ambiguity toward moderate tempi.
+const PRIOR_CENTER_BPM: f32 = 120.0;
+const PRIOR_WIDTH_OCTAVES: f32 = 1.0;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TempoEstimate {
+    pub bpm: f32,
+    /// Normalized autocorrelation of the onset envelope at the chosen beat period, 0..1.
+    pub confidence: f32,
+    /// Onset times in seconds.
+    pub onsets: Vec<f32>,
+}
+
+/// Spectral-flux onset envelope of a mono signal sampled on a fixed hop grid.
+pub struct OnsetEnvelope {
+    pub frame_rate: f32,
+    /// Seconds from the start of a frame to the sample its flux value is attributed to.
+    pub frame_offset_secs: f32,
+    pub values: Vec<f32>,
+    /// Mean spectral magnitude per frame, for following how a sound decays after its onset.
+    pub energy: Vec<f32>,
+}
+
+impl OnsetEnvelope {
+    pub fn from_mono(samples: &[f32], sample_rate: u32) -> Self {
+        Self::from_mono_bands(samples, sample_rate, &[]).remove(0)
+    }
+
+    /// One envelope per band in a single STFT pass. `split_hz` are ascending band edges, so `n` splits
+    /// give `n + 1` bands from DC to Nyquist.
+    pub fn from_mono_bands(samples: &[f32], sample_rate: u32, split_hz: &[f32]) -> Vec<Self> {
+        let (window_size, hop_size) = analysis_grid(sample_rate);
+        let bin_count = window_size / 2 + 1;
+        let frame_rate = sample_rate as f32 / hop_size as f32;
+        let frame_offset_secs = window_size as f32 * 0.5 / sample_rate as f32;
+        if bin_count < 2 {
+            // Too low a sample rate to split into bands, every envelope comes back empty.
+            return (0..=split_hz.len())
+                .map(|_| Self {
+                    frame_rate,
+                    frame_offset_secs,
+                    values: Vec::new(),
+                    energy: Vec::new(),
+                })
+                .collect();
+        }
+        let bin_hz = sample_rate as f32 / window_size as f32;
+        let mut band_edges = vec![0_usize];
+        band_edges.extend(
+            split_hz
+                .iter()
+                .map(|hz| ((hz / bin_hz).round() as usize).clamp(1, bin_count - 1)),
+        );
+        band_edges.push(bin_count);
+        let window = hann_window(window_size);
+        let mut magnitudes = Vec::with_capacity(bin_count);
+        let mut previous: Vec<f32> = vec![0_f32; bin_count];
+        let mut frame = vec![0_f32; window_size];
+        let mut bands: Vec<Self> = (0..band_edges.len() - 1)
+            .map(|_| Self {
+                frame_rate,
+                frame_offset_secs,
+                values: Vec::new(),
+                energy: Vec::new(),
+            })
+            .collect();
+        let mut start = 0;
+        while start < samples.len() {
+            let end = (start + window_size).min(samples.len());
+            frame[..end - start].copy_from_slice(&samples[start..end]);
+            frame[end - start..].fill(0_f32);
+            magnitude_spectrum(&frame, &window, &mut magnitudes);
+            for (band, edges) in bands.iter_mut().zip(band_edges.windows(2)) {
+                let mut flux = 0_f32;
+                let mut energy = 0_f32;
+                for bin in edges[0]..edges[1] {
+                    let magnitude = magnitudes[bin] / window_size as f32;
+                    let compressed = (1_f32 + LOG_COMPRESSION * magnitude).ln();
+                    flux += (compressed - previous[bin]).max(0_f32);
+                    previous[bin] = compressed;
+                    energy += magnitude;
+                }
+                band.values.push(flux);
+                band.energy.push(energy / (edges[1] - edges[0]).max(1) as f32);
+            }
+            start += hop_size;
+        }
+        bands
+    }
+
+    /// Envelope minus its local mean, half-wave rectified, so sustained loudness does not read as onsets.
+    pub fn novelty(&self) -> Vec<f32> {
+        let radius = (NOVELTY_MEAN_WINDOW_SECS * self.frame_rate).round().max(1_f32) as usize;
+        let local_means = moving_mean(&self.values, radius);
+        self.values
+            .iter()
+            .zip(local_means)
+            .map(|(value, mean)| (value - mean).max(0_f32))
+            .collect()
+    }
+
+    pub fn frame_time(&self, frame: usize) -> f32 {
+        frame as f32 / self.frame_rate + self.frame_offset_secs
+    }
+}
+
+pub fn detect_tempo_native(samples: &[f32], sample_rate: u32) -> TempoEstimate {
+    if samples.is_empty() || sample_rate == 0 {
+        return TempoEstimate::default();
+    }
+    let envelope = OnsetEnvelope::from_mono(samples, sample_rate);
+    let novelty = envelope.novelty();
+    let onsets = pick_onsets(&envelope, &novelty);
+    let (bpm, confidence) = estimate_bpm(&novelty, envelope.frame_rate);
+    TempoEstimate {
+        bpm,
+        confidence,
+        onsets,
+    }
+}
+
+fn analysis_grid(sample_rate: u32) -> (usize, usize) {
+    let ratio = sample_rate as f32 / REFERENCE_SAMPLE_RATE;
+    let hop_size = ((REFERENCE_HOP_SIZE as f32 * ratio).round() as usize).max(1);
+    let window_size = ((REFERENCE_WINDOW_SIZE as f32 * ratio).round() as usize)
+        .next_power_of_two()
+        .max(hop_size.next_power_of_two());
+    (window_size, hop_size)
+}
+
+fn pick_onsets(envelope: &OnsetEnvelope, novelty: &[f32]) -> Vec<f32> {
+    pick_onset_frames(envelope, novelty)
+        .into_iter()
+        .map(|frame| envelope.frame_time(frame))
+        .collect()
+}
+
+/// Frames of `novelty` that are local maxima above mean + `ONSET_THRESHOLD_STD` deviations, at least
+/// `ONSET_MIN_GAP_SECS` apart.
+pub(crate) fn pick_onset_frames(envelope: &OnsetEnvelope, novelty: &[f32]) -> Vec<usize> {
+    let count = novelty.len();
+    if count == 0 {
+        return Vec::new();
+    }
+    let mean = novelty.iter().sum::<f32>() / count as f32;
+    let variance = novelty.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / count as f32;
+    let threshold = mean + ONSET_THRESHOLD_STD * variance.sqrt();
+    let radius = (ONSET_PEAK_WINDOW_SECS * envelope.frame_rate).round().max(1_f32) as usize;
+    let min_gap = ONSET_MIN_GAP_SECS * envelope.frame_rate;
+    let mut frames: Vec<usize> = Vec::new();
+    for (frame, &value) in novelty.iter().enumerate() {
+        if value <= threshold || value <= 0_f32 {
+            continue;
+        }
+        let lo = frame.saturating_sub(radius);
+        let hi = (frame + radius + 1).min(count);
+        // Ties on a plateau go to its first frame.
+        let is_peak =
+            novelty[lo..frame].iter().all(|&v| v < value) && novelty[frame + 1..hi].iter().all(|&v| v <= value);
+        if !is_peak {
+            continue;
+        }
+        if frames.last().is_none_or(|&last| (frame - last) as f32 >= min_gap) {
+            frames.push(frame);
+        }
+    }
+    frames
+}
+
+// Autocorrelation of the novelty curve weighted by the tempo prior; the peak lag is refined with a
+// parabola through its neighbours before converting back to BPM.
+pub(crate) fn estimate_bpm(novelty: &[f32], frame_rate: f32) -> (f32, f32) {
+    let min_lag = (60_f32 * frame_rate / MAX_BPM).floor().max(1_f32) as usize;
+    let max_lag = (60_f32 * frame_rate / MIN_BPM).ceil() as usize;
+    if novelty.len() <= max_lag + 1 {
+        return (0_f32, 0_f32);
+    }
+    let mean = novelty.iter().sum::<f32>() / novelty.len() as f32;
+    let centered: Vec<f32> = novelty.iter().map(|v| v - mean).collect();
+    let autocorrelation: Vec<f32> = (0..=max_lag + 1)
+        .map(|lag| {
+            centered[..centered.len() - lag]
+                .iter()
+                .zip(&centered[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+        })
+        .collect();
+    let energy = autocorrelation[0];
+    if energy <= 0_f32 {
+        return (0_f32, 0_f32);
+    }
+    let prior = |lag: f32| {
+        let octaves = (60_f32 * frame_rate / lag / PRIOR_CENTER_BPM).log2() / PRIOR_WIDTH_OCTAVES;
+        (-0.5_f32 * octaves * octaves).exp()
+    };
+    let best_lag = (min_lag..=max_lag)
+        .max_by(|&a, &b| {
+            let score_a = autocorrelation[a] * prior(a as f32);
+            let score_b = autocorrelation[b] * prior(b as f32);
+            score_a.total_cmp(&score_b)
+        })
+        .unwrap();
+    let (left, center, right) = (
+        autocorrelation[best_lag - 1],
+        autocorrelation[best_lag],
+        autocorrelation[best_lag + 1],
+    );
+    let curvature = left - 2_f32 * center + right;
+    let offset = if curvature < 0_f32 {
+        (0.5_f32 * (left - right) / curvature).clamp(-0.5_f32, 0.5_f32)
+    } else {
+        0_f32
+    };
+    let lag = best_lag as f32 + offset;
+    (60_f32 * frame_rate / lag, (center / energy).clamp(0_f32, 1_f32))
+}
+
+fn moving_mean(values: &[f32], radius: usize) -> Vec<f32> {
+    let mut prefix = Vec::with_capacity(values.len() + 1);
+    prefix.push(0_f64);
+    for value in values {
+        prefix.push(prefix.last().unwrap() + *value as f64);
+    }
+    (0..values.len())
+        .map(|i| {
+            let lo = i.saturating_sub(radius);
+            let hi = (i + radius + 1).min(values.len());
+            ((prefix[hi] - prefix[lo]) / (hi - lo) as f64) as f32
+        })
+        .collect()
+}
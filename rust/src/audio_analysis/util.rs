@@ -1,3 +1,4 @@
+use crate::audio_analysis::tempo::{detect_tempo_native, TempoEstimate};
 #[cfg(all(not(target_arch = "wasm32"), not(target_os = "linux")))]
 use aubio_rs::{OnsetMode::SpecFlux, Smpl, Tempo};
 #[cfg(all(not(target_arch = "wasm32"), not(target_os = "linux")))]
@@ -115,3 +116,122 @@ pub fn detect_bpm_aubio_ogg(ogg_bytes: &[u8]) -> f32 {
 
     bpm
 }
+
+/// Which tempo detector `detect_bpm_wav`/`detect_bpm_ogg` use. `Aubio` only exists off Linux/wasm32 and
+/// falls back to `Native` elsewhere, so every target gets a real BPM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BpmDetector {
+    Native,
+    Aubio,
+}
+
+impl BpmDetector {
+    pub const AUBIO_AVAILABLE: bool = cfg!(all(not(target_arch = "wasm32"), not(target_os = "linux")));
+
+    fn resolve(self) -> Self {
+        match self {
+            BpmDetector::Aubio if !Self::AUBIO_AVAILABLE => BpmDetector::Native,
+            detector => detector,
+        }
+    }
+}
+
+impl Default for BpmDetector {
+    fn default() -> Self {
+        if Self::AUBIO_AVAILABLE {
+            BpmDetector::Aubio
+        } else {
+            BpmDetector::Native
+        }
+    }
+}
+
+pub fn detect_bpm_wav(wav_bytes: &[u8], detector: BpmDetector) -> f32 {
+    match detector.resolve() {
+        BpmDetector::Aubio => detect_bpm_aubio_wav(wav_bytes),
+        BpmDetector::Native => detect_tempo_native_wav(wav_bytes).bpm,
+    }
+}
+
+pub fn detect_bpm_ogg(ogg_bytes: &[u8], detector: BpmDetector) -> f32 {
+    match detector.resolve() {
+        BpmDetector::Aubio => detect_bpm_aubio_ogg(ogg_bytes),
+        BpmDetector::Native => detect_tempo_native_ogg(ogg_bytes).bpm,
+    }
+}
+
+pub fn detect_tempo_native_wav(wav_bytes: &[u8]) -> TempoEstimate {
+    match decode_wav_to_mono(wav_bytes) {
+        Some((samples, sample_rate)) => detect_tempo_native(&samples, sample_rate),
+        None => TempoEstimate::default(),
+    }
+}
+
+pub fn detect_tempo_native_ogg(ogg_bytes: &[u8]) -> TempoEstimate {
+    match decode_ogg_to_mono(ogg_bytes) {
+        Some((samples, sample_rate)) => detect_tempo_native(&samples, sample_rate),
+        None => TempoEstimate::default(),
+    }
+}
+
+/// (mono samples in -1..1, sample rate). Channels are averaged.
+pub fn decode_wav_to_mono(wav_bytes: &[u8]) -> Option<(Vec<f32>, u32)> {
+    let mut reader = hound::WavReader::new(std::io::Cursor::new(wav_bytes)).ok()?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>().ok()?,
+        hound::SampleFormat::Int => {
+            let scale = 1_f32 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<_, _>>()
+                .ok()?
+        },
+    };
+    Some((
+        downmix_interleaved(&interleaved, spec.channels as usize),
+        spec.sample_rate,
+    ))
+}
+
+pub fn decode_ogg_to_mono(ogg_bytes: &[u8]) -> Option<(Vec<f32>, u32)> {
+    let (interleaved, channels, sample_rate) = decode_ogg_interleaved(ogg_bytes)?;
+    let interleaved: Vec<f32> = interleaved.into_iter().map(|s| s as f32 / i16::MAX as f32).collect();
+    Some((downmix_interleaved(&interleaved, channels), sample_rate))
+}
+
+/// (left, right) frames; mono is copied to both sides and surround keeps its front left and right.
+pub fn decode_ogg_to_stereo(ogg_bytes: &[u8]) -> Option<(Vec<(i16, i16)>, u32)> {
+    let (interleaved, channels, sample_rate) = decode_ogg_interleaved(ogg_bytes)?;
+    // Vorbis puts the centre between the front pair for 3 and 5 or more channels.
+    let right = match channels {
+        1 => 0,
+        2 | 4 => 1,
+        _ => 2,
+    };
+    let frames = interleaved
+        .chunks_exact(channels)
+        .map(|frame| (frame[0], frame[right]))
+        .collect();
+    Some((frames, sample_rate))
+}
+
+fn decode_ogg_interleaved(ogg_bytes: &[u8]) -> Option<(Vec<i16>, usize, u32)> {
+    let mut ogg = lewton::inside_ogg::OggStreamReader::new(std::io::Cursor::new(ogg_bytes)).ok()?;
+    let channels = (ogg.ident_hdr.audio_channels as usize).max(1);
+    let sample_rate = ogg.ident_hdr.audio_sample_rate;
+    let mut interleaved: Vec<i16> = Vec::new();
+    while let Ok(Some(packet)) = ogg.read_dec_packet_itl() {
+        interleaved.extend(packet);
+    }
+    Some((interleaved, channels, sample_rate))
+}
+
+fn downmix_interleaved(interleaved: &[f32], channels: usize) -> Vec<f32> {
+    let channels = channels.max(1);
+    interleaved
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
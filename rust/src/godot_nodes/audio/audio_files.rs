@@ -1,5 +1,5 @@
-use crate::audio_analysis::util::{detect_bpm_aubio_ogg, detect_bpm_aubio_wav};
-use godot::builtin::GString;
+use crate::audio_analysis::util::{detect_bpm_ogg, detect_bpm_wav, detect_tempo_native_ogg, BpmDetector};
+use godot::builtin::{GString, PackedFloat32Array};
 use godot::classes::file_access::ModeFlags;
 use godot::classes::{FileAccess, Node};
 use godot::obj::Base;
@@ -19,7 +19,7 @@ impl AudioFiles {
         let wav_path = wav_file_path.to_string();
         let wav_file = FileAccess::open(&wav_path, ModeFlags::READ).unwrap();
         let wav_bytes = wav_file.get_buffer(wav_file.get_length() as i64).to_vec();
-        detect_bpm_aubio_wav(&wav_bytes)
+        detect_bpm_wav(&wav_bytes, BpmDetector::default())
     }
 
     #[func]
@@ -27,6 +27,23 @@ impl AudioFiles {
         let ogg_path = ogg_file_path.to_string();
         let ogg_file = FileAccess::open(&ogg_path, ModeFlags::READ).unwrap();
         let ogg_bytes = ogg_file.get_buffer(ogg_file.get_length() as i64).to_vec();
-        detect_bpm_aubio_ogg(&ogg_bytes)
+        detect_bpm_ogg(&ogg_bytes, BpmDetector::default())
+    }
+
+    #[func]
+    pub fn detect_bpm_ogg_native(&self, ogg_file_path: GString) -> f32 {
+        let ogg_path = ogg_file_path.to_string();
+        let ogg_file = FileAccess::open(&ogg_path, ModeFlags::READ).unwrap();
+        let ogg_bytes = ogg_file.get_buffer(ogg_file.get_length() as i64).to_vec();
+        detect_bpm_ogg(&ogg_bytes, BpmDetector::Native)
+    }
+
+    /// Onset times in seconds from the native spectral-flux detector.
+    #[func]
+    pub fn detect_onsets_ogg(&self, ogg_file_path: GString) -> PackedFloat32Array {
+        let ogg_path = ogg_file_path.to_string();
+        let ogg_file = FileAccess::open(&ogg_path, ModeFlags::READ).unwrap();
+        let ogg_bytes = ogg_file.get_buffer(ogg_file.get_length() as i64).to_vec();
+        PackedFloat32Array::from(detect_tempo_native_ogg(&ogg_bytes).onsets.as_slice())
     }
 }
@@ -1,7 +1,10 @@
+use crate::audio_analysis::fft::WindowFunction;
 use crate::sound_render::godot::GodotFFTTexture;
 use crate::sound_render::sound_renderer::FFTTexture;
-use godot::builtin::PackedFloat32Array;
-use godot::classes::{AudioEffectSpectrumAnalyzerInstance, INode2D, Image, ImageTexture, Node, Node2D};
+use crate::sound_render::spectrum_bands::BandScale;
+use godot::builtin::{GString, PackedFloat32Array};
+use godot::classes::{AudioEffectCapture, INode2D, Image, ImageTexture, Node, Node2D};
+use godot::global::godot_error;
 use godot::obj::{Base, Gd, NewAlloc, WithBaseField};
 use godot::register::{godot_api, GodotClass};
 
@@ -12,7 +15,7 @@ pub struct FFTTextureNode {
     render: Option<Gd<GodotFFTTexture>>,
     fft_data: Option<PackedFloat32Array>,
     audio_image: Option<Gd<Image>>,
-    spectrum: Option<Gd<AudioEffectSpectrumAnalyzerInstance>>,
+    spectrum: Option<Gd<AudioEffectCapture>>,
     #[var]
     audio_texture: Option<Gd<ImageTexture>>,
 }
@@ -43,3 +46,92 @@ impl INode2D for FFTTextureNode {
         self.audio_texture = ImageTexture::create_from_image(&image.clone());
     }
 }
+
+#[godot_api]
+impl FFTTextureNode {
+    /// One of "rectangular", "hann", "hamming", "blackman" (the default) or "blackman_harris". Call after
+    /// the node is ready.
+    #[func]
+    pub fn set_window_function(&mut self, name: GString) -> bool {
+        let Some(window_function) = WindowFunction::from_name(&name.to_string()) else {
+            godot_error!("FFTTextureNode: unknown window function {}", name);
+            return false;
+        };
+        match self.render.as_mut() {
+            Some(render) => {
+                render.bind_mut().analyzer_mut().set_window_function(window_function);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// New samples between analyses, clamped to 1..=FFT size. Half the FFT size gives 50% overlap and a
+    /// spectrogram row per half window. Call after the node is ready.
+    #[func]
+    pub fn set_hop_size(&mut self, hop_size: i32) -> bool {
+        match self.render.as_mut() {
+            Some(render) => {
+                render.bind_mut().analyzer_mut().set_hop_size(hop_size.max(1) as usize);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Lays the texture out in `band_count` bands on one of the "linear", "logarithmic", "mel", "bark" or
+    /// "octave" scales and recreates `audio_texture` at the new width. Call after the node is ready.
+    #[func]
+    pub fn set_band_layout(&mut self, scale: GString, band_count: i32) -> bool {
+        let Some(scale) = BandScale::from_name(&scale.to_string()) else {
+            godot_error!("FFTTextureNode: unknown band scale {}", scale);
+            return false;
+        };
+        let Some(render) = self.render.as_mut() else {
+            return false;
+        };
+        render.bind_mut().set_band_layout(scale, band_count.max(1) as usize);
+        self.recreate_audio_texture();
+        true
+    }
+
+    /// Turns `audio_texture` into a scrolling spectrogram of `rows` rows, or back into the single spectrum
+    /// row for 0. Shaders unwrap it with the `writeRow` uniform from `get_write_row`.
+    #[func]
+    pub fn set_spectrogram_rows(&mut self, rows: i32) {
+        let Some(render) = self.render.as_mut() else {
+            return;
+        };
+        render.bind_mut().set_spectrogram_rows(rows.max(0) as usize);
+        self.recreate_audio_texture();
+    }
+
+    /// Row of the spectrogram holding the newest frame, for the `writeRow` uniform.
+    #[func]
+    pub fn get_write_row(&self) -> i32 {
+        self.render
+            .as_ref()
+            .and_then(|render| render.bind().spectrogram().map(|ring| ring.write_row() as i32))
+            .unwrap_or(0)
+    }
+
+    /// Center frequency in Hz of each texel of `audio_texture`.
+    #[func]
+    pub fn get_band_center_frequencies(&self) -> PackedFloat32Array {
+        match self.render.as_ref() {
+            Some(render) => PackedFloat32Array::from(render.bind().bands().center_frequencies()),
+            None => PackedFloat32Array::new(),
+        }
+    }
+}
+
+impl FFTTextureNode {
+    fn recreate_audio_texture(&mut self) {
+        let Some(render) = self.render.as_mut() else {
+            return;
+        };
+        let image = render.bind_mut().init_audio_texture();
+        self.audio_image = Some(image.clone());
+        self.audio_texture = ImageTexture::create_from_image(&image);
+    }
+}
@@ -1,8 +1,11 @@
 use crate::godot_nodes::audio::audio_bus::{AudioBus, BUS};
+use crate::midi::rhythm::{RhythmData, RhythmOffsets};
+use asset_payload::CACHED_RHYTHM_DATA_PATH;
 use godot::classes::audio_effect_spectrum_analyzer::FftSize;
 use godot::classes::{
     AudioEffectSpectrumAnalyzer, AudioEffectSpectrumAnalyzerInstance, AudioServer, AudioStream, Engine, INode, Node,
 };
+use godot::global::godot_warn;
 use godot::obj::{Base, Gd, GodotClass, NewGd};
 use godot::register::{godot_api, GodotClass};
 
@@ -15,6 +18,7 @@ pub struct MusicDimensionsManagerRust {
     _audio_stream: Gd<AudioStream>,
     #[export]
     song_time: f32,
+    latency_offsets: RhythmOffsets,
 }
 
 #[godot_api]
@@ -34,6 +38,7 @@ impl INode for MusicDimensionsManagerRust {
             .unwrap()
             .cast::<AudioEffectSpectrumAnalyzerInstance>();
         self.spectrum_analyzer_instance = Some(audio_effect_instance);
+        self.reload_latency_offsets();
 
         //AudioPoolManager::singleton().bind_mut().play_music(self.audio_stream.clone(), 0.0);
     }
@@ -41,6 +46,24 @@ impl INode for MusicDimensionsManagerRust {
 
 #[godot_api]
 impl MusicDimensionsManagerRust {
+    /// Song time minus the calibrated output latency, i.e. what the player is hearing right now.
+    #[func]
+    pub fn get_audible_song_time(&self) -> f32 {
+        self.latency_offsets.audible_time(self.song_time)
+    }
+
+    /// Re-reads the offsets saved by calibration in `RhythmDimensionGodot`.
+    #[func]
+    pub fn reload_latency_offsets(&mut self) {
+        self.latency_offsets = match RhythmData::load_from_file(CACHED_RHYTHM_DATA_PATH) {
+            Ok(data) => data.offsets,
+            Err(e) => {
+                godot_warn!("MusicDimensionsManagerRust: no latency offsets ({})", e);
+                RhythmOffsets::default()
+            },
+        };
+    }
+
     pub fn singleton() -> Gd<MusicDimensionsManagerRust> {
         Engine::singleton()
             .get_singleton(&MusicDimensionsManagerRust::class_name().to_string_name())
@@ -48,6 +71,23 @@ impl MusicDimensionsManagerRust {
             .cast::<MusicDimensionsManagerRust>()
     }
 
+    /// The manager if it is registered as an engine singleton.
+    pub fn try_singleton() -> Option<Gd<MusicDimensionsManagerRust>> {
+        let name = MusicDimensionsManagerRust::class_name().to_string_name();
+        let engine = Engine::singleton();
+        if !engine.has_singleton(&name) {
+            return None;
+        }
+        engine
+            .get_singleton(&name)
+            .map(|singleton| singleton.cast::<MusicDimensionsManagerRust>())
+    }
+
+    /// Takes freshly calibrated offsets without waiting for the next `reload_latency_offsets`.
+    pub fn set_latency_offsets(&mut self, offsets: RhythmOffsets) {
+        self.latency_offsets = offsets;
+    }
+
     pub fn spectrum_instance(&self) -> Gd<AudioEffectSpectrumAnalyzerInstance> {
         self.spectrum_analyzer_instance
             .clone()
@@ -2,10 +2,10 @@ use crate::midi::pitch::PitchDimension;
 use asset_payload::payloads::{MIDI_FILE, SOUND_FONT_FILE};
 use asset_payload::CACHED_WAV_PATH_GD;
 
-use crate::sound_render::sound_renderer::MONO;
-use godot::builtin::{PackedByteArray, PackedVector3Array, Vector3};
+use crate::sound_render::sound_renderer::STEREO;
+use godot::builtin::{GString, PackedByteArray, PackedVector3Array, Vector3};
 use godot::classes::{AudioServer, AudioStreamWav, INode, Node};
-use godot::global::godot_print;
+use godot::global::{godot_error, godot_print};
 use godot::obj::{Base, Gd};
 use godot::prelude::{godot_api, GodotClass};
 
@@ -30,14 +30,23 @@ impl INode for PitchDimensionGodot {
 
     fn ready(&mut self) {
         let sample_rate = AudioServer::singleton().get_mix_rate() as i32;
-        self.inner.resolve_payload_to_midi_buffer(MIDI_FILE());
-        let wav_bytes = self.inner.resolve_payload_to_pcm_buffer_cache(
+        if let Err(e) = self.inner.resolve_payload_to_midi_buffer(MIDI_FILE()) {
+            godot_error!("PitchDimensionGodot: {}", e);
+            return;
+        }
+        let wav_bytes = match self.inner.resolve_payload_to_pcm_buffer_cache(
             sample_rate,
-            MONO as u16,
+            STEREO as u16,
             MIDI_FILE(),
             SOUND_FONT_FILE(),
             CACHED_WAV_PATH_GD,
-        );
+        ) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                godot_error!("PitchDimensionGodot: {}", e);
+                return;
+            },
+        };
         let buffer = PackedByteArray::from(wav_bytes);
         let stream = AudioStreamWav::load_from_buffer(&buffer).expect("Failed to decode WAV from buffer");
         self.wav_stream = Some(stream);
@@ -55,6 +64,58 @@ impl PitchDimensionGodot {
         out
     }
 
+    /// Bit n set when pitch class n (C = 0) is sounding.
+    #[func]
+    pub fn get_pitch_class_set(&self) -> i32 {
+        self.inner.get_harmony().pitch_classes as i32
+    }
+
+    #[func]
+    pub fn get_chord_name(&self) -> GString {
+        match self.inner.get_harmony().chord {
+            Some(chord) => GString::from(chord.to_string().as_str()),
+            None => GString::new(),
+        }
+    }
+
+    /// (root pitch class, inversion, 1 if a chord was recognized else 0).
+    #[func]
+    pub fn get_chord(&self) -> Vector3 {
+        match self.inner.get_harmony().chord {
+            Some(chord) => Vector3::new(chord.root as f32, chord.inversion as f32, 1.0),
+            None => Vector3::ZERO,
+        }
+    }
+
+    #[func]
+    pub fn get_key_name(&self) -> GString {
+        match self.inner.get_harmony().key {
+            Some(key) => GString::from(key.to_string().as_str()),
+            None => GString::new(),
+        }
+    }
+
+    /// (tonic pitch class, 1 if minor else 0, confidence).
+    #[func]
+    pub fn get_key(&self) -> Vector3 {
+        match self.inner.get_harmony().key {
+            Some(key) => Vector3::new(key.tonic as f32, if key.minor { 1.0 } else { 0.0 }, key.confidence),
+            None => Vector3::ZERO,
+        }
+    }
+
+    /// (bar, beat, fraction) of the current song time.
+    #[func]
+    pub fn get_musical_position(&self) -> Vector3 {
+        let position = self.inner.musical_clock().seconds_to_position(self.song_time);
+        Vector3::new(position.bar as f32, position.beat as f32, position.fraction)
+    }
+
+    #[func]
+    pub fn get_seconds_until_next_downbeat(&self) -> f32 {
+        self.inner.musical_clock().seconds_until_next_downbeat(self.song_time)
+    }
+
     #[func]
     pub fn get_wav_stream(&self) -> Gd<AudioStreamWav> {
         self.wav_stream.clone().unwrap()
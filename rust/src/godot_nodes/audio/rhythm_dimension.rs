@@ -1,8 +1,20 @@
-use crate::midi::rhythm::RhythmDimension;
-use godot::builtin::{PackedVector2Array, Vector2};
-use godot::classes::{INode, Node};
-use godot::obj::Base;
-use godot::prelude::{godot_api, GodotClass};
+use crate::godot_nodes::audio::music_dimension_manager::MusicDimensionsManagerRust;
+use crate::midi::calibration::{CalibrationCue, DEFAULT_CALIBRATION_CLICKS};
+use crate::midi::chart::{AudioChartRules, MidiChartRules};
+use crate::midi::metronome::{render_click_track, ClickAccent, ClickTrack, MetronomeSettings};
+use crate::midi::program::ProgramMap;
+use crate::midi::rhythm::{Judgement, JudgementWindows, LaneInput, LaneInputKind, RhythmDimension, RhythmLane};
+use crate::sound_render::sound_renderer::STEREO;
+use asset_payload::payloads::{MIDI_FILE, SHADERTOY_EXPERIMENT_OGG};
+use godot::builtin::{
+    Array, Dictionary, GString, PackedByteArray, PackedFloat32Array, PackedInt32Array, PackedVector2Array, Variant,
+    Vector2,
+};
+use godot::classes::image::Format;
+use godot::classes::{AudioServer, AudioStreamWav, INode, Image, ImageTexture, Node};
+use godot::global::godot_error;
+use godot::obj::{Base, Gd};
+use godot::prelude::{godot_api, GodotClass, ToGodot};
 
 #[derive(GodotClass)]
 #[class(init, base=Node)]
@@ -33,35 +45,394 @@ impl RhythmDimensionGodot {
     }
 
     #[func]
-    pub fn get_f_onset_count(&self) -> i32 {
-        self.inner.f_onset_count as i32
+    pub fn get_lane_count(&self) -> i32 {
+        self.inner.lanes.len() as i32
     }
 
     #[func]
-    pub fn get_j_onset_count(&self) -> i32 {
-        self.inner.j_onset_count as i32
+    pub fn get_lane_name(&self, lane: i32) -> GString {
+        self.lane(lane)
+            .map_or(GString::new(), |lane| GString::from(lane.name.as_str()))
     }
 
     #[func]
-    pub fn get_f_onsets(&self) -> PackedVector2Array {
+    pub fn get_lane_key(&self, lane: i32) -> GString {
+        self.lane(lane)
+            .map_or(GString::new(), |lane| GString::from(lane.key.as_str()))
+    }
+
+    /// "tap", "hold" or "release".
+    #[func]
+    pub fn get_lane_kind(&self, lane: i32) -> GString {
+        self.lane(lane)
+            .map_or(GString::new(), |lane| GString::from(lane.kind.name()))
+    }
+
+    /// Lane index bound to `key`, or -1.
+    #[func]
+    pub fn get_lane_for_key(&self, key: GString) -> i32 {
+        self.inner.lane_for_key(&key.to_string()).map_or(-1, |lane| lane as i32)
+    }
+
+    #[func]
+    pub fn get_lane_onset_count(&self, lane: i32) -> i32 {
+        self.lane(lane).map_or(0, |lane| lane.onsets.len() as i32)
+    }
+
+    /// (press, release) of every note in `lane`.
+    #[func]
+    pub fn get_lane_onsets(&self, lane: i32) -> PackedVector2Array {
         let mut arr = PackedVector2Array::new();
-        for [start, end] in &self.inner.f_onsets_flat_buffer {
+        for [start, end] in self.lane(lane).map_or(&[][..], |lane| lane.onsets.as_slice()) {
             arr.push(Vector2::new(*start, *end));
         }
         arr
     }
 
     #[func]
-    pub fn get_j_onsets(&self) -> PackedVector2Array {
-        let mut arr = PackedVector2Array::new();
-        for [start, end] in &self.inner.j_onsets_flat_buffer {
-            arr.push(Vector2::new(*start, *end));
+    pub fn get_seconds_until_next_downbeat(&self) -> f32 {
+        self.inner.seconds_until_next_downbeat(self.song_time)
+    }
+
+    #[func]
+    pub fn get_time_of_next_click(&self) -> f32 {
+        self.inner.time_of_next_click
+    }
+
+    #[func]
+    pub fn get_beats(&self) -> PackedFloat32Array {
+        PackedFloat32Array::from(self.inner.rhythm_data.beat_grid.beats.as_slice())
+    }
+
+    #[func]
+    pub fn get_downbeats(&self) -> PackedFloat32Array {
+        self.inner.rhythm_data.beat_grid.downbeats().collect()
+    }
+
+    #[func]
+    pub fn get_bpm_at(&self, time: f32) -> f32 {
+        self.inner.rhythm_data.beat_grid.bpm_at(time)
+    }
+
+    #[func]
+    pub fn snap_onsets_to_grid(&mut self, subdivision: i32) {
+        self.inner.snap_onsets_to_grid(subdivision.max(1) as u32);
+    }
+
+    #[func]
+    pub fn generate_chart_from_midi(&mut self) {
+        if let Err(e) =
+            self.inner
+                .generate_chart_from_midi(MIDI_FILE(), &ProgramMap::default(), &MidiChartRules::default())
+        {
+            godot_error!("RhythmDimensionGodot: {}", e);
+        }
+    }
+
+    #[func]
+    pub fn generate_chart_from_audio(&mut self, split_hz: f32) {
+        let rules = AudioChartRules {
+            split_hz,
+            ..AudioChartRules::default()
+        };
+        if !self.inner.generate_chart_from_audio(SHADERTOY_EXPERIMENT_OGG(), &rules) {
+            godot_error!("RhythmDimensionGodot: failed to decode the song OGG");
+        }
+    }
+
+    /// Returns the judgement name, or an empty string when the press hit no note.
+    #[func]
+    pub fn press_lane(&mut self, lane: i32) -> GString {
+        self.judge(lane, LaneInputKind::Press)
+    }
+
+    #[func]
+    pub fn release_lane(&mut self, lane: i32) -> GString {
+        self.judge(lane, LaneInputKind::Release)
+    }
+
+    /// `press_lane` for whichever lane is bound to `key`; empty when no lane uses it.
+    #[func]
+    pub fn press_key(&mut self, key: GString) -> GString {
+        match self.inner.lane_for_key(&key.to_string()) {
+            Some(lane) => self.judge(lane as i32, LaneInputKind::Press),
+            None => GString::new(),
+        }
+    }
+
+    #[func]
+    pub fn release_key(&mut self, key: GString) -> GString {
+        match self.inner.lane_for_key(&key.to_string()) {
+            Some(lane) => self.judge(lane as i32, LaneInputKind::Release),
+            None => GString::new(),
         }
-        arr
+    }
+
+    #[func]
+    pub fn set_judgement_windows(&mut self, perfect_secs: f32, great_secs: f32, good_secs: f32) {
+        self.inner.judgement.windows = JudgementWindows {
+            perfect_secs,
+            great_secs,
+            good_secs,
+            ..self.inner.judgement.windows
+        };
+    }
+
+    #[func]
+    pub fn reset_judgement(&mut self) {
+        self.inner.judgement.reset();
+    }
+
+    #[func]
+    pub fn get_combo(&self) -> i32 {
+        self.inner.judgement.combo() as i32
+    }
+
+    #[func]
+    pub fn get_max_combo(&self) -> i32 {
+        self.inner.judgement.max_combo() as i32
+    }
+
+    #[func]
+    pub fn get_score(&self) -> i32 {
+        self.inner.judgement.score() as i32
+    }
+
+    #[func]
+    pub fn get_accuracy(&self) -> f32 {
+        self.inner.judgement.accuracy()
+    }
+
+    #[func]
+    pub fn get_last_judgement(&self) -> GString {
+        judgement_name(self.inner.judgement.last_judgement())
+    }
+
+    /// [perfect, great, good, miss]
+    #[func]
+    pub fn get_judgement_counts(&self) -> PackedInt32Array {
+        Judgement::ALL
+            .iter()
+            .map(|&judgement| self.inner.judgement.count(judgement) as i32)
+            .collect()
+    }
+
+    #[func]
+    pub fn get_note_results(&self) -> Array<Dictionary> {
+        let mut results = Array::new();
+        for result in self.inner.judgement.results() {
+            let mut entry = Dictionary::new();
+            entry.set("lane", result.lane as i32);
+            entry.set("note_index", result.note_index as i32);
+            entry.set("press", judgement_name(result.press));
+            entry.set(
+                "press_offset",
+                result.press_offset.map_or(Variant::nil(), |offset| offset.to_variant()),
+            );
+            entry.set("release", judgement_name(result.release));
+            entry.set(
+                "release_offset",
+                result
+                    .release_offset
+                    .map_or(Variant::nil(), |offset| offset.to_variant()),
+            );
+            results.push(&entry);
+        }
+        results
+    }
+
+    #[func]
+    pub fn get_chart_difficulty(&self) -> f32 {
+        self.inner.chart_stats().difficulty
+    }
+
+    #[func]
+    pub fn get_chart_stats(&self) -> Dictionary {
+        let stats = self.inner.chart_stats();
+        let mut dict = Dictionary::new();
+        dict.set("note_count", stats.note_count as i32);
+        dict.set("duration", stats.duration_secs);
+        dict.set("average_nps", stats.average_nps);
+        dict.set("peak_nps", stats.peak_nps());
+        dict.set("jack_count", stats.jacks.len() as i32);
+        dict.set("trill_count", stats.trills.len() as i32);
+        dict.set("hold_overlap", stats.hold_overlap_secs);
+        dict.set("notes_under_holds", stats.notes_under_holds as i32);
+        dict.set("difficulty", stats.difficulty);
+        let mut peak_windows = Array::<Dictionary>::new();
+        for window in &stats.peak_windows {
+            let mut entry = Dictionary::new();
+            entry.set("start", window.start_secs);
+            entry.set("end", window.end_secs);
+            entry.set("notes", window.notes as i32);
+            entry.set("nps", window.nps);
+            peak_windows.push(&entry);
+        }
+        dict.set("peak_windows", peak_windows);
+        dict
+    }
+
+    /// `width` x 1 R8 strip of note density from the first note to the last, brightest at the peak.
+    #[func]
+    pub fn get_density_strip(&self, width: i32) -> Option<Gd<ImageTexture>> {
+        let strip = self.inner.chart_stats().density_strip(width.max(1) as usize);
+        let image = Image::create_from_data(width.max(1), 1, false, Format::R8, &PackedByteArray::from(strip))?;
+        ImageTexture::create_from_image(&image)
+    }
+
+    /// Clicks alone over `duration_secs` of song, after the count-in. `accents` is one of X x . - per beat
+    /// ("X.x."), empty to accent downbeats only.
+    #[func]
+    pub fn get_click_track_stream(
+        &self,
+        accents: GString,
+        count_in_bars: i32,
+        duration_secs: f32,
+    ) -> Option<Gd<AudioStreamWav>> {
+        let settings = metronome_settings(&accents, count_in_bars)?;
+        let sample_rate = AudioServer::singleton().get_mix_rate() as i32;
+        let grid = self.inner.metronome_grid(duration_secs);
+        click_track_stream(&render_click_track(&grid, &settings, sample_rate, duration_secs))
+    }
+
+    /// The song with the click track mixed on top, for checking the chart against the beat by ear.
+    #[func]
+    pub fn get_song_with_clicks_stream(&self, accents: GString, count_in_bars: i32) -> Option<Gd<AudioStreamWav>> {
+        let settings = metronome_settings(&accents, count_in_bars)?;
+        let Some(track) = self
+            .inner
+            .render_song_with_clicks(SHADERTOY_EXPERIMENT_OGG(), &settings)
+        else {
+            godot_error!("RhythmDimensionGodot: could not decode the song for the click track");
+            return None;
+        };
+        click_track_stream(&track)
+    }
+
+    /// Starts tapping along to clicks (`visual == false`) or flashes (`visual == true`) at the song BPM.
+    /// Non-positive `click_count` uses the default.
+    #[func]
+    pub fn start_calibration(&mut self, visual: bool, click_count: i32) {
+        let cue = if visual {
+            CalibrationCue::Visual
+        } else {
+            CalibrationCue::Audio
+        };
+        let click_count = if click_count > 0 {
+            click_count as usize
+        } else {
+            DEFAULT_CALIBRATION_CLICKS
+        };
+        self.inner.start_calibration(cue, self.song_time, click_count);
+    }
+
+    /// Song times at which the running calibration expects a click or flash to be played.
+    #[func]
+    pub fn get_calibration_cues(&self) -> PackedFloat32Array {
+        match &self.inner.calibration {
+            Some(session) => PackedFloat32Array::from(session.cues.as_slice()),
+            None => PackedFloat32Array::new(),
+        }
+    }
+
+    #[func]
+    pub fn calibration_tap(&mut self) {
+        self.inner.calibration_tap(self.song_time);
+    }
+
+    #[func]
+    pub fn is_calibration_complete(&self) -> bool {
+        self.inner
+            .calibration
+            .as_ref()
+            .is_none_or(|session| session.is_complete(self.song_time))
+    }
+
+    /// Applies and saves the measured offset and hands it to `MusicDimensionsManagerRust` when that is
+    /// registered. Returns false when too few taps agreed to trust it.
+    #[func]
+    pub fn finish_calibration(&mut self) -> bool {
+        if self.inner.finish_calibration().is_none() {
+            return false;
+        }
+        if let Some(mut manager) = MusicDimensionsManagerRust::try_singleton() {
+            manager.bind_mut().set_latency_offsets(self.inner.rhythm_data.offsets);
+        }
+        true
+    }
+
+    #[func]
+    pub fn get_audio_offset(&self) -> f32 {
+        self.inner.rhythm_data.offsets.audio_secs
+    }
+
+    #[func]
+    pub fn get_input_offset(&self) -> f32 {
+        self.inner.rhythm_data.offsets.input_secs
     }
 
     #[func]
     pub fn reset_song_time(&mut self) {
         self.song_time = 0.0;
+        self.inner.judgement.reset();
+    }
+}
+
+impl RhythmDimensionGodot {
+    fn lane(&self, lane: i32) -> Option<&RhythmLane> {
+        usize::try_from(lane).ok().and_then(|lane| self.inner.lanes.get(lane))
+    }
+
+    fn judge(&mut self, lane: i32, kind: LaneInputKind) -> GString {
+        if self.lane(lane).is_none() {
+            godot_error!(
+                "RhythmDimensionGodot: lane {} out of range, {} lanes",
+                lane,
+                self.inner.lanes.len()
+            );
+            return GString::new();
+        }
+        let input = LaneInput {
+            lane: lane as usize,
+            kind,
+            time: self.song_time,
+        };
+        judgement_name(self.inner.judge_input(input))
+    }
+}
+
+fn judgement_name(judgement: Option<Judgement>) -> GString {
+    match judgement {
+        Some(judgement) => GString::from(judgement.to_string().as_str()),
+        None => GString::new(),
+    }
+}
+
+// Negative `count_in_bars` keeps the default count-in.
+fn metronome_settings(accents: &GString, count_in_bars: i32) -> Option<MetronomeSettings> {
+    let Some(accents) = ClickAccent::pattern_from_str(&accents.to_string()) else {
+        godot_error!(
+            "RhythmDimensionGodot: bad accent pattern {:?}, use X x . -",
+            accents.to_string()
+        );
+        return None;
+    };
+    let mut settings = MetronomeSettings {
+        accents,
+        ..MetronomeSettings::default()
+    };
+    if let Ok(count_in_bars) = u32::try_from(count_in_bars) {
+        settings.count_in_bars = count_in_bars;
+    }
+    Some(settings)
+}
+
+fn click_track_stream(track: &ClickTrack) -> Option<Gd<AudioStreamWav>> {
+    match track.to_wav_bytes(STEREO as u16) {
+        Ok(wav_bytes) => AudioStreamWav::load_from_buffer(&PackedByteArray::from(wav_bytes)),
+        Err(e) => {
+            godot_error!("RhythmDimensionGodot: {}", e);
+            None
+        },
     }
 }
@@ -0,0 +1,180 @@
+use crate::midi::program::ProgramMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+/// Bump whenever the renderer output changes for identical inputs so existing caches get regenerated.
+pub const WAV_CACHE_RENDER_VERSION: u32 = 1;
+const SIDECAR_EXTENSION: &str = "meta";
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[derive(Debug)]
+pub enum WavCacheError {
+    Io(PathBuf, io::Error),
+    Render(Box<dyn Error>),
+}
+
+impl fmt::Display for WavCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WavCacheError::Io(path, e) => write!(f, "WAV cache i/o failed for {}: {}", path.display(), e),
+            WavCacheError::Render(e) => write!(f, "failed to render WAV for cache: {}", e),
+        }
+    }
+}
+
+impl Error for WavCacheError {}
+
+/// Identity of a rendered WAV: a content hash of every render input plus the settings in plain text
+/// so a sidecar can be eyeballed when a cache looks wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavCacheKey {
+    pub hash: u64,
+    pub sample_rate: i32,
+    pub channels: u16,
+    pub render_version: u32,
+}
+
+impl WavCacheKey {
+    pub fn new(sample_rate: i32, channels: u16, midi_bytes: &[u8], sf2_bytes: &[u8], program_map: &ProgramMap) -> Self {
+        let mut hasher = Fnv1a::default();
+        hasher.write(&WAV_CACHE_RENDER_VERSION.to_le_bytes());
+        hasher.write(&sample_rate.to_le_bytes());
+        hasher.write(&channels.to_le_bytes());
+        // Length prefixes keep (midi, sf2) boundaries from aliasing.
+        hasher.write(&(midi_bytes.len() as u64).to_le_bytes());
+        hasher.write(midi_bytes);
+        hasher.write(&(sf2_bytes.len() as u64).to_le_bytes());
+        hasher.write(sf2_bytes);
+        for (channel, program) in program_map.overrides() {
+            hasher.write(&[channel, program.bank_msb, program.bank_lsb, program.program]);
+        }
+        Self {
+            hash: hasher.finish(),
+            sample_rate,
+            channels,
+            render_version: WAV_CACHE_RENDER_VERSION,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Sidecar {
+    key: WavCacheKey,
+    wav_len: u64,
+}
+
+impl Sidecar {
+    fn to_text(&self) -> String {
+        format!(
+            "hash={:016x}\nsample_rate={}\nchannels={}\nrender_version={}\nwav_len={}\n",
+            self.key.hash, self.key.sample_rate, self.key.channels, self.key.render_version, self.wav_len
+        )
+    }
+
+    fn from_text(text: &str) -> Option<Self> {
+        let mut hash = None;
+        let mut sample_rate = None;
+        let mut channels = None;
+        let mut render_version = None;
+        let mut wav_len = None;
+        for line in text.lines() {
+            let (field, value) = line.split_once('=')?;
+            let value = value.trim();
+            match field.trim() {
+                "hash" => hash = u64::from_str_radix(value, 16).ok(),
+                "sample_rate" => sample_rate = value.parse().ok(),
+                "channels" => channels = value.parse().ok(),
+                "render_version" => render_version = value.parse().ok(),
+                "wav_len" => wav_len = value.parse().ok(),
+                _ => {},
+            }
+        }
+        Some(Self {
+            key: WavCacheKey {
+                hash: hash?,
+                sample_rate: sample_rate?,
+                channels: channels?,
+                render_version: render_version?,
+            },
+            wav_len: wav_len?,
+        })
+    }
+}
+
+/// `<cache_path>.meta`, written next to the WAV.
+pub fn wav_cache_sidecar_path(cache_path: &Path) -> PathBuf {
+    let mut sidecar = cache_path.as_os_str().to_owned();
+    sidecar.push(".");
+    sidecar.push(SIDECAR_EXTENSION);
+    PathBuf::from(sidecar)
+}
+
+/// Returns the cached WAV when its sidecar matches `key` and the file is intact, otherwise calls `render`,
+/// writes the WAV and then the sidecar (so a crash mid-write never leaves a sidecar vouching for a partial file).
+pub fn load_or_render_wav_cache(
+    cache_path: &Path,
+    key: WavCacheKey,
+    render: impl FnOnce() -> Result<Vec<u8>, Box<dyn Error>>,
+) -> Result<Vec<u8>, WavCacheError> {
+    if let Some(bytes) = read_valid_cache(cache_path, key) {
+        return Ok(bytes);
+    }
+    let bytes = render().map_err(WavCacheError::Render)?;
+    let io_err = |path: &Path| {
+        let path = path.to_path_buf();
+        move |e| WavCacheError::Io(path, e)
+    };
+    if let Some(parent_dir) = cache_path.parent() {
+        fs::create_dir_all(parent_dir).map_err(io_err(parent_dir))?;
+    }
+    let sidecar_path = wav_cache_sidecar_path(cache_path);
+    if let Err(e) = fs::remove_file(&sidecar_path) {
+        if e.kind() != io::ErrorKind::NotFound {
+            return Err(io_err(&sidecar_path)(e));
+        }
+    }
+    fs::write(cache_path, &bytes).map_err(io_err(cache_path))?;
+    let sidecar = Sidecar {
+        key,
+        wav_len: bytes.len() as u64,
+    };
+    fs::write(&sidecar_path, sidecar.to_text()).map_err(io_err(&sidecar_path))?;
+    Ok(bytes)
+}
+
+fn read_valid_cache(cache_path: &Path, key: WavCacheKey) -> Option<Vec<u8>> {
+    let text = fs::read_to_string(wav_cache_sidecar_path(cache_path)).ok()?;
+    let sidecar = Sidecar::from_text(&text)?;
+    if sidecar.key != key {
+        return None;
+    }
+    let bytes = fs::read(cache_path).ok()?;
+    if bytes.len() as u64 != sidecar.wav_len {
+        return None;
+    }
+    Some(bytes)
+}
+
+// FNV-1a rather than `DefaultHasher`, whose output is not guaranteed stable across Rust releases.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,150 @@
+use crate::midi::rhythm::RhythmOffsets;
+
+pub const DEFAULT_CALIBRATION_CLICKS: usize = 16;
+pub const DEFAULT_CALIBRATION_LEAD_IN_BEATS: f32 = 4.0;
+// Taps further than this (in MADs, scaled to a gaussian sigma) from the median tap are rejected.
+const OUTLIER_MADS: f32 = 3.0;
+const MAD_TO_SIGMA: f32 = 1.4826;
+// Floor under the rejection band so a very steady player does not get most of their taps thrown out.
+const MIN_OUTLIER_BAND_SECS: f32 = 0.01;
+const MIN_ACCEPTED_TAPS: usize = 4;
+
+/// What the player taps along to. Audio clicks measure output and input latency together, visual flashes
+/// measure input latency alone, so running both separates the two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationCue {
+    Audio,
+    Visual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OffsetEstimate {
+    /// Mean of the accepted taps minus their cue times; positive means taps land late.
+    pub offset_secs: f32,
+    /// Standard deviation of the accepted taps.
+    pub spread_secs: f32,
+    pub accepted: usize,
+    pub rejected: usize,
+}
+
+/// A run of evenly spaced cues and the taps collected against them, all in song time.
+#[derive(Debug, Clone)]
+pub struct CalibrationSession {
+    pub cue: CalibrationCue,
+    pub cues: Vec<f32>,
+    pub period: f32,
+    taps: Vec<f32>,
+}
+
+impl CalibrationSession {
+    /// `click_count` cues at `bpm`, the first one `lead_in_beats` after `start_secs` so the player can
+    /// pick up the pulse before it counts.
+    pub fn new(cue: CalibrationCue, bpm: f32, start_secs: f32, lead_in_beats: f32, click_count: usize) -> Self {
+        let period = 60_f32 / bpm.max(1_f32);
+        let first = start_secs + lead_in_beats * period;
+        Self {
+            cue,
+            cues: (0..click_count).map(|i| first + i as f32 * period).collect(),
+            period,
+            taps: Vec::new(),
+        }
+    }
+
+    pub fn tap(&mut self, time: f32) {
+        self.taps.push(time);
+    }
+
+    pub fn taps(&self) -> &[f32] {
+        &self.taps
+    }
+
+    /// Half a period after the last cue no tap can belong to it any more.
+    pub fn is_complete(&self, time: f32) -> bool {
+        self.cues.last().is_none_or(|&last| time > last + self.period * 0.5_f32)
+    }
+
+    /// Each tap minus its nearest cue. Taps outside the cue run are dropped.
+    pub fn tap_offsets(&self) -> Vec<f32> {
+        let half_period = self.period * 0.5_f32;
+        self.taps
+            .iter()
+            .filter_map(|&tap| {
+                let index = self.cues.partition_point(|&cue| cue < tap);
+                let before = index.checked_sub(1).map(|i| tap - self.cues[i]);
+                let after = self.cues.get(index).map(|&cue| tap - cue);
+                let offset = match (before, after) {
+                    (Some(b), Some(a)) => {
+                        if b.abs() <= a.abs() {
+                            b
+                        } else {
+                            a
+                        }
+                    },
+                    (Some(b), None) => b,
+                    (None, Some(a)) => a,
+                    (None, None) => return None,
+                };
+                (offset.abs() <= half_period).then_some(offset)
+            })
+            .collect()
+    }
+
+    pub fn estimate(&self) -> Option<OffsetEstimate> {
+        estimate_offset(&self.tap_offsets())
+    }
+}
+
+/// Mean of `offsets` after dropping those more than `OUTLIER_MADS` robust deviations from the median,
+/// None when fewer than `MIN_ACCEPTED_TAPS` survive.
+pub fn estimate_offset(offsets: &[f32]) -> Option<OffsetEstimate> {
+    if offsets.is_empty() {
+        return None;
+    }
+    let center = median(offsets.to_vec());
+    let mad = median(offsets.iter().map(|offset| (offset - center).abs()).collect());
+    let band = (OUTLIER_MADS * MAD_TO_SIGMA * mad).max(MIN_OUTLIER_BAND_SECS);
+    let accepted: Vec<f32> = offsets
+        .iter()
+        .copied()
+        .filter(|offset| (offset - center).abs() <= band)
+        .collect();
+    if accepted.len() < MIN_ACCEPTED_TAPS {
+        return None;
+    }
+    let mean = accepted.iter().sum::<f32>() / accepted.len() as f32;
+    let variance = accepted
+        .iter()
+        .map(|offset| (offset - mean) * (offset - mean))
+        .sum::<f32>()
+        / accepted.len() as f32;
+    Some(OffsetEstimate {
+        offset_secs: mean,
+        spread_secs: variance.sqrt(),
+        accepted: accepted.len(),
+        rejected: offsets.len() - accepted.len(),
+    })
+}
+
+/// Folds one session's estimate into `offsets`. An audio session measures `audio_secs + input_secs`, a
+/// visual one `input_secs`; keeping that sum fixed when the input side changes lets the two sessions run in
+/// either order.
+pub fn apply_calibration(offsets: &mut RhythmOffsets, cue: CalibrationCue, estimate: &OffsetEstimate) {
+    match cue {
+        CalibrationCue::Audio => offsets.audio_secs = estimate.offset_secs - offsets.input_secs,
+        CalibrationCue::Visual => {
+            let total = offsets.audio_secs + offsets.input_secs;
+            offsets.input_secs = estimate.offset_secs;
+            offsets.audio_secs = total - offsets.input_secs;
+        },
+    }
+}
+
+fn median(mut values: Vec<f32>) -> f32 {
+    values.sort_by(f32::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) * 0.5_f32
+    } else {
+        values[middle]
+    }
+}
@@ -0,0 +1,232 @@
+use crate::audio_analysis::tempo::{pick_onset_frames, OnsetEnvelope};
+use crate::midi::program::ProgramMap;
+use crate::midi::util::{parse_midi_events_into_channel_notes_from_bytes, ChannelNote, MidiError};
+use std::ops::RangeInclusive;
+
+pub const DEFAULT_SPLIT_NOTE: u8 = 60;
+pub const DEFAULT_SPLIT_HZ: f32 = 250.0;
+pub const DEFAULT_MIN_HOLD_SECS: f32 = 0.08;
+pub const DEFAULT_MIN_GAP_SECS: f32 = 0.06;
+pub const DEFAULT_MAX_HOLD_SECS: f32 = 2.0;
+// Audio holds end once band energy falls to this fraction of its level at the onset.
+const RELEASE_ENERGY_RATIO: f32 = 0.3;
+// A sharp attack splashes flux into every band, so onsets weaker than this fraction of the band's strong
+// onsets (90th percentile) are taken as spill from the other band.
+const BAND_SPILL_RATIO: f32 = 0.25;
+const BAND_SPILL_PERCENTILE: f32 = 0.9;
+const DRUM_CHANNEL: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    Uki,
+    Shizumi,
+}
+
+/// Sends every note matching all of the set filters to `lane`. `None` matches anything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaneRule {
+    pub channel: Option<u8>,
+    pub program: Option<u8>,
+    pub notes: RangeInclusive<u8>,
+    pub lane: Lane,
+}
+
+impl LaneRule {
+    pub fn new(notes: RangeInclusive<u8>, lane: Lane) -> Self {
+        Self {
+            channel: None,
+            program: None,
+            notes,
+            lane,
+        }
+    }
+
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    pub fn with_program(mut self, program: u8) -> Self {
+        self.program = Some(program);
+        self
+    }
+
+    pub fn matches(&self, note: &ChannelNote) -> bool {
+        self.channel.is_none_or(|channel| channel == note.channel)
+            && self.program.is_none_or(|program| program == note.program)
+            && self.notes.contains(&note.midi_note)
+    }
+}
+
+/// Press/release spacing applied to both lanes regardless of where the notes came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaneShaping {
+    /// Holds shorter than this are stretched so a tap still has a visible release.
+    pub min_hold_secs: f32,
+    pub max_hold_secs: f32,
+    /// Space kept between a release and the next press in the same lane; closer presses merge.
+    pub min_gap_secs: f32,
+}
+
+impl Default for LaneShaping {
+    fn default() -> Self {
+        Self {
+            min_hold_secs: DEFAULT_MIN_HOLD_SECS,
+            max_hold_secs: DEFAULT_MAX_HOLD_SECS,
+            min_gap_secs: DEFAULT_MIN_GAP_SECS,
+        }
+    }
+}
+
+/// Notes on `ignored_channels` are dropped, otherwise the first matching rule wins and notes that match
+/// none are left out of the chart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiChartRules {
+    pub rules: Vec<LaneRule>,
+    pub ignored_channels: Vec<u8>,
+    pub shaping: LaneShaping,
+}
+
+impl Default for MidiChartRules {
+    /// Drums are skipped, everything from middle C up is uki and everything below is shizumi.
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                LaneRule::new(DEFAULT_SPLIT_NOTE..=127, Lane::Uki),
+                LaneRule::new(0..=DEFAULT_SPLIT_NOTE - 1, Lane::Shizumi),
+            ],
+            ignored_channels: vec![DRUM_CHANNEL],
+            shaping: LaneShaping::default(),
+        }
+    }
+}
+
+impl MidiChartRules {
+    /// No rules at all, for building a mapping from scratch with `with_rule`.
+    pub fn empty() -> Self {
+        Self {
+            rules: Vec::new(),
+            ignored_channels: Vec::new(),
+            shaping: LaneShaping::default(),
+        }
+    }
+
+    pub fn with_rule(mut self, rule: LaneRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn lane_for(&self, note: &ChannelNote) -> Option<Lane> {
+        if self.ignored_channels.contains(&note.channel) {
+            return None;
+        }
+        self.rules.iter().find(|rule| rule.matches(note)).map(|rule| rule.lane)
+    }
+}
+
+/// Audio notes come from the low band (shizumi) and the high band (uki) split at `split_hz`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioChartRules {
+    pub split_hz: f32,
+    pub shaping: LaneShaping,
+}
+
+impl Default for AudioChartRules {
+    fn default() -> Self {
+        Self {
+            split_hz: DEFAULT_SPLIT_HZ,
+            shaping: LaneShaping::default(),
+        }
+    }
+}
+
+/// Generated [press, release] pairs for the uki and shizumi lanes of `RhythmData`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChartLanes {
+    pub uki: Vec<[f32; 2]>,
+    pub shizumi: Vec<[f32; 2]>,
+}
+
+pub fn generate_chart_from_midi(
+    midi_bytes: &[u8],
+    program_map: &ProgramMap,
+    rules: &MidiChartRules,
+) -> Result<ChartLanes, MidiError> {
+    let notes = parse_midi_events_into_channel_notes_from_bytes(midi_bytes, program_map)?;
+    let mut uki = Vec::new();
+    let mut shizumi = Vec::new();
+    for note in &notes {
+        match rules.lane_for(note) {
+            Some(Lane::Uki) => uki.push((note.onset, note.release)),
+            Some(Lane::Shizumi) => shizumi.push((note.onset, note.release)),
+            None => {},
+        }
+    }
+    Ok(ChartLanes {
+        uki: shape_lane(uki, &rules.shaping),
+        shizumi: shape_lane(shizumi, &rules.shaping),
+    })
+}
+
+/// Onsets come from per-band spectral flux; each hold lasts until that band's energy decays.
+pub fn generate_chart_from_audio(samples: &[f32], sample_rate: u32, rules: &AudioChartRules) -> ChartLanes {
+    if samples.is_empty() || sample_rate == 0 {
+        return ChartLanes::default();
+    }
+    let bands = OnsetEnvelope::from_mono_bands(samples, sample_rate, &[rules.split_hz]);
+    let mut lanes = bands.iter().map(|band| shape_lane(band_notes(band), &rules.shaping));
+    let shizumi = lanes.next().unwrap_or_default();
+    let uki = lanes.next().unwrap_or_default();
+    ChartLanes { uki, shizumi }
+}
+
+fn band_notes(band: &OnsetEnvelope) -> Vec<(f32, f32)> {
+    let novelty = band.novelty();
+    let mut frames = pick_onset_frames(band, &novelty);
+    let mut strengths: Vec<f32> = frames.iter().map(|&frame| novelty[frame]).collect();
+    strengths.sort_by(f32::total_cmp);
+    if let Some(&strong) = strengths.get((strengths.len() as f32 * BAND_SPILL_PERCENTILE) as usize) {
+        frames.retain(|&frame| novelty[frame] >= strong * BAND_SPILL_RATIO);
+    }
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, &onset)| {
+            let next_onset = frames.get(i + 1).copied().unwrap_or(band.energy.len());
+            // The onset frame often only catches the start of the attack, so the level is taken one frame later too.
+            let peak_end = (onset + 2).min(next_onset).max(onset + 1);
+            let peak = band.energy[onset..peak_end].iter().copied().fold(0_f32, f32::max);
+            let release = (onset + 1..next_onset)
+                .find(|&frame| band.energy[frame] < peak * RELEASE_ENERGY_RATIO)
+                .unwrap_or(next_onset);
+            (band.frame_time(onset), band.frame_time(release))
+        })
+        .collect()
+}
+
+// Sorts by press and merges presses closer than `min_gap_secs` (chords, flams) into one, then clamps every
+// hold into [min_hold, max_hold] (max_hold wins if they cross) while keeping `min_gap_secs` clear before the
+// next press. A release never lands before its press, even for the last note or a negative max_hold.
+fn shape_lane(mut notes: Vec<(f32, f32)>, shaping: &LaneShaping) -> Vec<[f32; 2]> {
+    notes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut merged: Vec<(f32, f32)> = Vec::with_capacity(notes.len());
+    for (press, release) in notes {
+        match merged.last_mut() {
+            Some(last) if press - last.0 < shaping.min_gap_secs => last.1 = last.1.max(release),
+            _ => merged.push((press, release.max(press))),
+        }
+    }
+    merged
+        .iter()
+        .enumerate()
+        .map(|(i, &(press, release))| {
+            let mut release = release
+                .max(press + shaping.min_hold_secs)
+                .min(press + shaping.max_hold_secs);
+            if let Some(&(next_press, _)) = merged.get(i + 1) {
+                release = release.min(next_press - shaping.min_gap_secs);
+            }
+            [press, release.max(press)]
+        })
+        .collect()
+}
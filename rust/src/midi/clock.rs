@@ -0,0 +1,309 @@
+use crate::midi::util::{prepare_events, MidiError, TickClock, DEFAULT_US_PER_QN};
+use midly::{MetaMessage, Smf, TrackEventKind};
+
+const DEFAULT_NUMERATOR: u8 = 4;
+const DEFAULT_DENOMINATOR: u8 = 4;
+const SYNTHETIC_TICKS_PER_QUARTER: f64 = 480_f64;
+
+/// 0-based bar and beat (in units of the time signature denominator), `fraction` is how far into the beat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MusicalPosition {
+    pub bar: u32,
+    pub beat: u32,
+    pub fraction: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TempoSegment {
+    tick: f64,
+    secs: f64,
+    quarters: f64,
+    us_per_qn: f64,
+    secs_per_tick: f64,
+    quarters_per_tick: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MeterSegment {
+    quarters: f64,
+    bar: u32,
+    numerator: u8,
+    denominator: u8,
+}
+
+impl MeterSegment {
+    fn beat_quarters(&self) -> f64 {
+        4_f64 / self.denominator as f64
+    }
+
+    fn bar_quarters(&self) -> f64 {
+        self.numerator as f64 * self.beat_quarters()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct KeySegment {
+    quarters: f64,
+    sharps: i8,
+    minor: bool,
+}
+
+/// Tempo, time signature and key signature maps of a song, converting between seconds, ticks
+/// and (bar, beat, fraction). Works for metrical and SMPTE timecode files alike.
+pub struct MusicalClock {
+    tick_clock: TickClock,
+    tempos: Vec<TempoSegment>,
+    meters: Vec<MeterSegment>,
+    keys: Vec<KeySegment>,
+}
+
+impl Default for MusicalClock {
+    fn default() -> Self {
+        Self::empty(TickClock::Metrical {
+            ticks_per_quarter: SYNTHETIC_TICKS_PER_QUARTER as f32,
+        })
+    }
+}
+
+impl MusicalClock {
+    pub fn from_smf(smf: &Smf) -> Result<Self, MidiError> {
+        let tick_clock = TickClock::from_timing(smf.header.timing)?;
+        let mut clock = Self::empty(tick_clock);
+        let mut pending_meters = Vec::new();
+        let mut pending_keys = Vec::new();
+        for (tick, event) in prepare_events(smf) {
+            match event {
+                TrackEventKind::Meta(MetaMessage::Tempo(us)) => clock.push_tempo(tick as f64, us.as_int() as f64),
+                TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, denominator_pow, _, _)) => {
+                    pending_meters.push((tick as f64, numerator.max(1), 1_u8 << denominator_pow.min(7)));
+                },
+                TrackEventKind::Meta(MetaMessage::KeySignature(sharps, minor)) => {
+                    pending_keys.push((tick as f64, sharps, minor));
+                },
+                _ => {},
+            }
+        }
+        for (tick, numerator, denominator) in pending_meters {
+            let quarters = clock.ticks_to_quarters(tick);
+            clock.push_meter(quarters, numerator, denominator);
+        }
+        for (tick, sharps, minor) in pending_keys {
+            let quarters = clock.ticks_to_quarters(tick);
+            clock.keys.push(KeySegment {
+                quarters,
+                sharps,
+                minor,
+            });
+        }
+        Ok(clock)
+    }
+
+    /// Constant tempo clock for audio-only songs where only a detected BPM is known.
+    pub fn from_bpm(bpm: f32, numerator: u8, denominator: u8) -> Self {
+        let mut clock = Self::empty(TickClock::Metrical {
+            ticks_per_quarter: SYNTHETIC_TICKS_PER_QUARTER as f32,
+        });
+        if bpm > 0_f32 {
+            clock.push_tempo(0_f64, 60_000_000_f64 / bpm as f64);
+        }
+        clock.push_meter(0_f64, numerator.max(1), denominator.max(1));
+        clock
+    }
+
+    pub fn seconds_to_ticks(&self, secs: f32) -> f64 {
+        let secs = secs as f64;
+        let segment = self.tempo_segment_by(|s| s.secs <= secs);
+        segment.tick + (secs - segment.secs) / segment.secs_per_tick
+    }
+
+    pub fn ticks_to_seconds(&self, tick: f64) -> f32 {
+        let segment = self.tempo_segment_by(|s| s.tick <= tick);
+        (segment.secs + (tick - segment.tick) * segment.secs_per_tick) as f32
+    }
+
+    pub fn seconds_to_position(&self, secs: f32) -> MusicalPosition {
+        self.quarters_to_position(self.seconds_to_quarters(secs as f64))
+    }
+
+    pub fn position_to_seconds(&self, position: MusicalPosition) -> f32 {
+        let meter = self.meter_by(|m| m.bar <= position.bar);
+        let quarters = meter.quarters
+            + (position.bar - meter.bar) as f64 * meter.bar_quarters()
+            + (position.beat as f64 + position.fraction as f64) * meter.beat_quarters();
+        self.quarters_to_seconds(quarters) as f32
+    }
+
+    pub fn ticks_to_position(&self, tick: f64) -> MusicalPosition {
+        self.quarters_to_position(self.ticks_to_quarters(tick))
+    }
+
+    pub fn position_to_ticks(&self, position: MusicalPosition) -> f64 {
+        self.seconds_to_ticks(self.position_to_seconds(position))
+    }
+
+    pub fn bpm_at(&self, secs: f32) -> f32 {
+        let secs = secs as f64;
+        (60_000_000_f64 / self.tempo_segment_by(|s| s.secs <= secs).us_per_qn) as f32
+    }
+
+    /// (numerator, denominator) in effect at `secs`.
+    pub fn time_signature_at(&self, secs: f32) -> (u8, u8) {
+        let quarters = self.seconds_to_quarters(secs as f64);
+        let meter = self.meter_by(|m| m.quarters <= quarters);
+        (meter.numerator, meter.denominator)
+    }
+
+    /// (sharps, minor) in effect at `secs`, negative sharps are flats. C major when the file has none.
+    pub fn key_signature_at(&self, secs: f32) -> (i8, bool) {
+        let quarters = self.seconds_to_quarters(secs as f64);
+        let idx = self.keys.partition_point(|k| k.quarters <= quarters);
+        match idx {
+            0 => (0, false),
+            _ => (self.keys[idx - 1].sharps, self.keys[idx - 1].minor),
+        }
+    }
+
+    pub fn seconds_until_next_beat(&self, secs: f32) -> f32 {
+        let position = self.seconds_to_position(secs);
+        let next = self.advance_beat(position);
+        (self.position_to_seconds(next) - secs).max(0_f32)
+    }
+
+    pub fn seconds_until_next_downbeat(&self, secs: f32) -> f32 {
+        let position = self.seconds_to_position(secs);
+        let next = MusicalPosition {
+            bar: position.bar + 1,
+            beat: 0,
+            fraction: 0_f32,
+        };
+        (self.position_to_seconds(next) - secs).max(0_f32)
+    }
+
+    fn empty(tick_clock: TickClock) -> Self {
+        let mut clock = Self {
+            tick_clock,
+            tempos: Vec::new(),
+            meters: Vec::new(),
+            keys: Vec::new(),
+        };
+        clock.push_tempo(0_f64, DEFAULT_US_PER_QN as f64);
+        clock.meters.push(MeterSegment {
+            quarters: 0_f64,
+            bar: 0,
+            numerator: DEFAULT_NUMERATOR,
+            denominator: DEFAULT_DENOMINATOR,
+        });
+        clock
+    }
+
+    fn push_tempo(&mut self, tick: f64, us_per_qn: f64) {
+        let (secs, quarters) = match self.tempos.last() {
+            Some(last) => (
+                last.secs + (tick - last.tick) * last.secs_per_tick,
+                last.quarters + (tick - last.tick) * last.quarters_per_tick,
+            ),
+            None => (0_f64, 0_f64),
+        };
+        let secs_per_quarter = us_per_qn / 1_000_000_f64;
+        let (secs_per_tick, quarters_per_tick) = match self.tick_clock {
+            TickClock::Metrical { ticks_per_quarter } => {
+                let ticks_per_quarter = ticks_per_quarter as f64;
+                (secs_per_quarter / ticks_per_quarter, 1_f64 / ticks_per_quarter)
+            },
+            TickClock::Timecode { ticks_per_second } => {
+                let secs_per_tick = 1_f64 / ticks_per_second as f64;
+                (secs_per_tick, secs_per_tick / secs_per_quarter)
+            },
+        };
+        let segment = TempoSegment {
+            tick,
+            secs,
+            quarters,
+            us_per_qn,
+            secs_per_tick,
+            quarters_per_tick,
+        };
+        match self.tempos.last_mut() {
+            Some(last) if last.tick == tick => *last = segment,
+            _ => self.tempos.push(segment),
+        }
+    }
+
+    // A meter change that lands mid-bar starts a fresh bar right there.
+    fn push_meter(&mut self, quarters: f64, numerator: u8, denominator: u8) {
+        let last = *self.meters.last().unwrap();
+        let bars_elapsed = ((quarters - last.quarters) / last.bar_quarters()).ceil().max(0_f64) as u32;
+        let segment = MeterSegment {
+            quarters,
+            bar: last.bar + bars_elapsed,
+            numerator,
+            denominator,
+        };
+        match self.meters.last_mut() {
+            Some(last) if last.quarters == quarters => {
+                *last = MeterSegment {
+                    bar: last.bar,
+                    ..segment
+                }
+            },
+            _ => self.meters.push(segment),
+        }
+    }
+
+    fn tempo_segment_by(&self, before: impl Fn(&TempoSegment) -> bool) -> &TempoSegment {
+        let idx = self.tempos.partition_point(before);
+        &self.tempos[idx.saturating_sub(1)]
+    }
+
+    fn meter_by(&self, before: impl Fn(&MeterSegment) -> bool) -> &MeterSegment {
+        let idx = self.meters.partition_point(before);
+        &self.meters[idx.saturating_sub(1)]
+    }
+
+    fn ticks_to_quarters(&self, tick: f64) -> f64 {
+        let segment = self.tempo_segment_by(|s| s.tick <= tick);
+        segment.quarters + (tick - segment.tick) * segment.quarters_per_tick
+    }
+
+    fn seconds_to_quarters(&self, secs: f64) -> f64 {
+        let segment = self.tempo_segment_by(|s| s.secs <= secs);
+        segment.quarters + (secs - segment.secs) / segment.secs_per_tick * segment.quarters_per_tick
+    }
+
+    fn quarters_to_seconds(&self, quarters: f64) -> f64 {
+        let segment = self.tempo_segment_by(|s| s.quarters <= quarters);
+        segment.secs + (quarters - segment.quarters) / segment.quarters_per_tick * segment.secs_per_tick
+    }
+
+    fn quarters_to_position(&self, quarters: f64) -> MusicalPosition {
+        let quarters = quarters.max(0_f64);
+        let meter = self.meter_by(|m| m.quarters <= quarters);
+        let into_meter = quarters - meter.quarters;
+        let bars = (into_meter / meter.bar_quarters()).floor();
+        let into_bar = into_meter - bars * meter.bar_quarters();
+        let beats = into_bar / meter.beat_quarters();
+        let beat = (beats.floor() as u32).min(meter.numerator as u32 - 1);
+        MusicalPosition {
+            bar: meter.bar + bars as u32,
+            beat,
+            fraction: (beats - beat as f64).clamp(0_f64, 1_f64) as f32,
+        }
+    }
+
+    fn advance_beat(&self, position: MusicalPosition) -> MusicalPosition {
+        let meter = self.meter_by(|m| m.bar <= position.bar);
+        if position.beat + 1 >= meter.numerator as u32 {
+            MusicalPosition {
+                bar: position.bar + 1,
+                beat: 0,
+                fraction: 0_f32,
+            }
+        } else {
+            MusicalPosition {
+                bar: position.bar,
+                beat: position.beat + 1,
+                fraction: 0_f32,
+            }
+        }
+    }
+}
@@ -3,22 +3,25 @@
 use asset_payload::payloads::MIDI_FILE;
 use asset_payload::SOUND_FONT_FILE_PATH;
 use midir::{MidiOutput, MidiOutputConnection, MidiOutputPort};
-use midly::{MidiMessage, Smf, TrackEventKind};
+use midly::{Format, MidiMessage, Smf, TrackEventKind};
 use rdev::{Event, EventType, Key};
 use rustysynth::{Instrument, InstrumentRegion, Preset, SoundFont};
 use std::collections::HashSet;
 use std::error::Error;
+use std::fs;
 use std::fs::File;
 use std::io::{stdout, BufReader, Write};
 use std::process::{exit, Child, Command};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use terminal_size::{terminal_size, Width};
 
+use crate::midi::program::ProgramMap;
 use crate::midi::util::{
     parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes,
     parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes, prepare_events, process_midi_events_with_timing,
 };
+use crate::midi::writer::{write_note_buffer_seconds_to_smf_bytes, NoteRecorder};
 
 pub fn run_playback() -> Result<(), Box<dyn Error>> {
     print_full_structure(SOUND_FONT_FILE_PATH, 0, 0)?;
@@ -27,15 +30,18 @@ pub fn run_playback() -> Result<(), Box<dyn Error>> {
     // let (midi_output, midi_port) = connect_to_first_midi_port();
     // let mut midi_connection = midi_output.connect(&midi_port, "rust-midi")?;
     // let mut pressed_keys: HashSet<Key> = HashSet::new();
+    // let mut recorder = NoteRecorder::default();
+    // let recording_start = SystemTime::now();
     // let _ = listen(move |event| {
-    //     handle_key_event(event, &mut midi_connection, &mut pressed_keys);
+    //     handle_key_event(event, &mut midi_connection, &mut pressed_keys, &mut recorder, recording_start);
     // });
     // let _ = fluidsynth_process.kill();
     //TODO: the above is all^^ for testing midi keyboard user input
     let midi_bytes = MIDI_FILE();
-    let _ = parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes(&midi_bytes);
-    let _ = parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes(&midi_bytes);
-    play_midi(&midi_bytes);
+    let program_map = ProgramMap::default();
+    let _ = parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes(&midi_bytes, &program_map);
+    let _ = parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes(&midi_bytes, &program_map);
+    play_midi(&midi_bytes)?;
 
     Ok(())
 }
@@ -69,13 +75,32 @@ pub fn connect_to_first_midi_port() -> (MidiOutput, MidiOutputPort) {
     exit(1);
 }
 
-pub fn handle_key_event(event: Event, connection: &mut MidiOutputConnection, active_keys: &mut HashSet<Key>) {
+const RECORDED_INPUT_PATH: &str = "recorded_input.mid";
+const RECORDED_INPUT_TICKS_PER_QUARTER: u16 = 480;
+const RECORDED_INPUT_BPM: f32 = 120.0;
+
+pub fn handle_key_event(
+    event: Event,
+    connection: &mut MidiOutputConnection,
+    active_keys: &mut HashSet<Key>,
+    recorder: &mut NoteRecorder,
+    recording_start: SystemTime,
+) {
+    let time_secs = event
+        .time
+        .duration_since(recording_start)
+        .unwrap_or_default()
+        .as_secs_f32();
     match event.event_type {
-        EventType::KeyPress(Key::Escape) => exit(0),
+        EventType::KeyPress(Key::Escape) => {
+            save_recorded_input(std::mem::take(recorder), time_secs);
+            exit(0)
+        },
         EventType::KeyPress(key) => {
             if let Some(note) = map_key_to_midi_note(key) {
                 if active_keys.insert(key) {
                     let _ = connection.send(&[0x90, note, 100]);
+                    recorder.press(note, time_secs);
                 }
             }
         },
@@ -83,6 +108,7 @@ pub fn handle_key_event(event: Event, connection: &mut MidiOutputConnection, act
             if let Some(note) = map_key_to_midi_note(key) {
                 if active_keys.remove(&key) {
                     let _ = connection.send(&[0x80, note, 0]);
+                    recorder.release(note, time_secs);
                 }
             }
         },
@@ -91,6 +117,25 @@ pub fn handle_key_event(event: Event, connection: &mut MidiOutputConnection, act
     render(active_keys);
 }
 
+fn save_recorded_input(recorder: NoteRecorder, time_secs: f32) {
+    if recorder.is_empty() {
+        return;
+    }
+    let buffer = recorder.finish(time_secs);
+    match write_note_buffer_seconds_to_smf_bytes(
+        &buffer,
+        RECORDED_INPUT_TICKS_PER_QUARTER,
+        RECORDED_INPUT_BPM,
+        Format::SingleTrack,
+    ) {
+        Ok(bytes) => match fs::write(RECORDED_INPUT_PATH, bytes) {
+            Ok(()) => println!("recorded input written to {}", RECORDED_INPUT_PATH),
+            Err(e) => eprintln!("Failed to write {}: {}", RECORDED_INPUT_PATH, e),
+        },
+        Err(e) => eprintln!("Failed to encode recorded input: {}", e),
+    }
+}
+
 fn map_key_to_midi_note(key: Key) -> Option<u8> {
     key_bindings().into_iter().find(|b| b.key == key).map(|b| b.midi_note)
 }
@@ -281,17 +326,14 @@ fn note_name_no_octave(note_number: u8) -> &'static str {
     NAMES[(note_number % 12) as usize]
 }
 
-pub fn play_midi(midi_bytes: &[u8]) {
+pub fn play_midi(midi_bytes: &[u8]) -> Result<(), Box<dyn Error>> {
     const MIDI_NOTE_ON: u8 = 0x90;
     const MIDI_NOTE_OFF: u8 = 0x80;
 
     let (midi_out, port) = connect_to_first_midi_port();
     let mut conn = midi_out.connect(&port, "rust-midi").unwrap();
-    let smf = Smf::parse(&midi_bytes).unwrap();
+    let smf = Smf::parse(&midi_bytes)?;
     let events = prepare_events(&smf);
-    // const TARGET_CHANNEL: u8 = 0;
-    // const PROGRAM: u8 = 0;
-    // events = inject_program_change(events, TARGET_CHANNEL, PROGRAM);
     let mut last_time = 0.0;
     process_midi_events_with_timing(events, &smf, |event_time, event, ch| {
         let delay = event_time - last_time;
@@ -318,7 +360,8 @@ pub fn play_midi(midi_bytes: &[u8]) {
                 }
             }
         }
-    });
+    })?;
+    Ok(())
 }
 
 const L0: &str = "";
@@ -0,0 +1,289 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+pub const PITCH_CLASS_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+pub const KEY_WINDOW_SECS: f32 = 8.0;
+
+// Krumhansl-Kessler key profiles, index 0 is the tonic.
+const MAJOR_PROFILE: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR_PROFILE: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Power,
+    Dominant7,
+    Major7,
+    Minor7,
+    HalfDiminished7,
+    Diminished7,
+}
+
+impl ChordQuality {
+    /// Chord tones as semitones above the root, in stacking order (root, third, fifth, seventh).
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            ChordQuality::Major => &[0, 4, 7],
+            ChordQuality::Minor => &[0, 3, 7],
+            ChordQuality::Diminished => &[0, 3, 6],
+            ChordQuality::Augmented => &[0, 4, 8],
+            ChordQuality::Sus2 => &[0, 2, 7],
+            ChordQuality::Sus4 => &[0, 5, 7],
+            ChordQuality::Power => &[0, 7],
+            ChordQuality::Dominant7 => &[0, 4, 7, 10],
+            ChordQuality::Major7 => &[0, 4, 7, 11],
+            ChordQuality::Minor7 => &[0, 3, 7, 10],
+            ChordQuality::HalfDiminished7 => &[0, 3, 6, 10],
+            ChordQuality::Diminished7 => &[0, 3, 6, 9],
+        }
+    }
+
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            ChordQuality::Major => "",
+            ChordQuality::Minor => "m",
+            ChordQuality::Diminished => "dim",
+            ChordQuality::Augmented => "aug",
+            ChordQuality::Sus2 => "sus2",
+            ChordQuality::Sus4 => "sus4",
+            ChordQuality::Power => "5",
+            ChordQuality::Dominant7 => "7",
+            ChordQuality::Major7 => "maj7",
+            ChordQuality::Minor7 => "m7",
+            ChordQuality::HalfDiminished7 => "m7b5",
+            ChordQuality::Diminished7 => "dim7",
+        }
+    }
+
+    const ALL: [ChordQuality; 12] = [
+        ChordQuality::Major,
+        ChordQuality::Minor,
+        ChordQuality::Diminished,
+        ChordQuality::Augmented,
+        ChordQuality::Sus2,
+        ChordQuality::Sus4,
+        ChordQuality::Power,
+        ChordQuality::Dominant7,
+        ChordQuality::Major7,
+        ChordQuality::Minor7,
+        ChordQuality::HalfDiminished7,
+        ChordQuality::Diminished7,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChordName {
+    pub root: u8,
+    pub quality: ChordQuality,
+    pub bass: u8,
+    /// 0 = root position, 1 = third in the bass, 2 = fifth, 3 = seventh.
+    pub inversion: u8,
+}
+
+impl fmt::Display for ChordName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", PITCH_CLASS_NAMES[self.root as usize], self.quality.suffix())?;
+        if self.inversion > 0 {
+            write!(f, "/{}", PITCH_CLASS_NAMES[self.bass as usize])?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEstimate {
+    pub tonic: u8,
+    pub minor: bool,
+    /// Pearson correlation of the windowed chroma against the winning profile.
+    pub confidence: f32,
+}
+
+impl fmt::Display for KeyEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = if self.minor { "minor" } else { "major" };
+        write!(f, "{} {}", PITCH_CLASS_NAMES[self.tonic as usize], mode)
+    }
+}
+
+/// 12-bit pitch class set, bit `n` set when pitch class `n` (C = 0) sounds.
+pub fn pitch_class_set(notes: &[u8]) -> u16 {
+    notes.iter().fold(0_u16, |set, note| set | (1 << (note % 12)))
+}
+
+/// Matches the sounding notes against chord templates on every root. The template covering the most
+/// sounding pitch classes wins, extra tones cost a point, and a root in the bass breaks ties.
+pub fn recognize_chord(notes: &[u8]) -> Option<ChordName> {
+    let set = pitch_class_set(notes);
+    let bass = notes.iter().min()? % 12;
+    let mut best: Option<(i32, ChordName)> = None;
+    for root in 0_u8..12 {
+        for quality in ChordQuality::ALL {
+            let intervals = quality.intervals();
+            let template = intervals
+                .iter()
+                .fold(0_u16, |mask, interval| mask | (1 << ((root + interval) % 12)));
+            if set & template != template {
+                continue;
+            }
+            let extras = (set & !template).count_ones() as i32;
+            let mut score = intervals.len() as i32 * 2 - extras * 2;
+            if root == bass {
+                score += 1;
+            }
+            let inversion = intervals
+                .iter()
+                .position(|interval| (root + interval) % 12 == bass)
+                .unwrap_or(0) as u8;
+            let candidate = ChordName {
+                root,
+                quality,
+                bass,
+                inversion,
+            };
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, candidate));
+            }
+        }
+    }
+    best.map(|(_, chord)| chord)
+}
+
+/// Running key estimate over a sliding window of chroma, weighted by how long each pitch class sounded.
+pub struct KeyEstimator {
+    window_secs: f32,
+    frames: VecDeque<(f32, [f32; 12])>,
+    last_time: Option<f32>,
+    last_notes: Vec<u8>,
+}
+
+impl Default for KeyEstimator {
+    fn default() -> Self {
+        Self::new(KEY_WINDOW_SECS)
+    }
+}
+
+impl KeyEstimator {
+    pub fn new(window_secs: f32) -> Self {
+        Self {
+            window_secs,
+            frames: VecDeque::new(),
+            last_time: None,
+            last_notes: Vec::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.last_time = None;
+        self.last_notes.clear();
+    }
+
+    /// The time since the previous call is credited to the notes that were sounding then, `notes` only
+    /// count from `time` onwards.
+    pub fn update(&mut self, time: f32, notes: &[u8]) -> Option<KeyEstimate> {
+        let dt = match self.last_time {
+            Some(last) if time >= last => time - last,
+            _ => {
+                self.frames.clear();
+                0_f32
+            },
+        };
+        self.last_time = Some(time);
+        let mut chroma = [0_f32; 12];
+        for note in &self.last_notes {
+            chroma[(note % 12) as usize] += dt;
+        }
+        self.last_notes.clear();
+        self.last_notes.extend_from_slice(notes);
+        self.frames.push_back((time, chroma));
+        while let Some(&(oldest, _)) = self.frames.front() {
+            if time - oldest <= self.window_secs {
+                break;
+            }
+            self.frames.pop_front();
+        }
+        self.estimate()
+    }
+
+    pub fn estimate(&self) -> Option<KeyEstimate> {
+        let mut chroma = [0_f32; 12];
+        for (_, frame) in &self.frames {
+            for (sum, weight) in chroma.iter_mut().zip(frame.iter()) {
+                *sum += weight;
+            }
+        }
+        if chroma.iter().all(|&weight| weight <= 0_f32) {
+            return None;
+        }
+        let mut best: Option<KeyEstimate> = None;
+        for tonic in 0_u8..12 {
+            for (minor, profile) in [(false, &MAJOR_PROFILE), (true, &MINOR_PROFILE)] {
+                let rotated: [f32; 12] = std::array::from_fn(|pc| profile[(pc + 12 - tonic as usize) % 12]);
+                let confidence = pearson(&chroma, &rotated);
+                if best.is_none_or(|b| confidence > b.confidence) {
+                    best = Some(KeyEstimate {
+                        tonic,
+                        minor,
+                        confidence,
+                    });
+                }
+            }
+        }
+        best
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HarmonySnapshot {
+    pub pitch_classes: u16,
+    pub chord: Option<ChordName>,
+    pub key: Option<KeyEstimate>,
+}
+
+#[derive(Default)]
+pub struct HarmonyAnalyzer {
+    key_estimator: KeyEstimator,
+    snapshot: HarmonySnapshot,
+}
+
+impl HarmonyAnalyzer {
+    /// Feed the active note set (e.g. from `sample_active_notes_at_time`) at song time `time`.
+    pub fn update(&mut self, time: f32, notes: &[u8]) -> HarmonySnapshot {
+        self.snapshot = HarmonySnapshot {
+            pitch_classes: pitch_class_set(notes),
+            chord: recognize_chord(notes),
+            key: self.key_estimator.update(time, notes),
+        };
+        self.snapshot
+    }
+
+    pub fn snapshot(&self) -> HarmonySnapshot {
+        self.snapshot
+    }
+}
+
+fn pearson(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12_f32;
+    let mean_b = b.iter().sum::<f32>() / 12_f32;
+    let mut covariance = 0_f32;
+    let mut variance_a = 0_f32;
+    let mut variance_b = 0_f32;
+    for i in 0..12 {
+        let da = a[i] - mean_a;
+        let db = b[i] - mean_b;
+        covariance += da * db;
+        variance_a += da * da;
+        variance_b += db * db;
+    }
+    let denominator = (variance_a * variance_b).sqrt();
+    if denominator > 0_f32 {
+        covariance / denominator
+    } else {
+        0_f32
+    }
+}
@@ -0,0 +1,227 @@
+use crate::audio_analysis::beat::BeatGrid;
+use crate::midi::util::{sample_to_i16, write_samples_to_wav_bytes, DEFAULT_US_PER_QN};
+use std::f32::consts::TAU;
+
+pub const DEFAULT_COUNT_IN_BARS: u32 = 1;
+pub const DEFAULT_CLICK_GAIN: f32 = 0.5;
+pub const DEFAULT_SONG_GAIN: f32 = 0.8;
+const CLICK_SECS: f32 = 0.04;
+const CLICK_ATTACK_SECS: f32 = 0.001;
+// Decay time constant as a fraction of the click, so the tail is inaudible by the time it is cut.
+const CLICK_DECAY_RATIO: f32 = 1.0 / 6.0;
+const ACCENT_HZ: f32 = 1760.0;
+const BEAT_HZ: f32 = 1320.0;
+
+/// How loud one beat of the bar clicks. Written in accent patterns as `X`, `x`, `.` and `-`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClickAccent {
+    Strong,
+    Normal,
+    Weak,
+    Silent,
+}
+
+impl ClickAccent {
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            'X' => Some(ClickAccent::Strong),
+            'x' => Some(ClickAccent::Normal),
+            '.' => Some(ClickAccent::Weak),
+            '-' => Some(ClickAccent::Silent),
+            _ => None,
+        }
+    }
+
+    /// Parses one accent per beat, e.g. "X.x." for a strong downbeat and a lighter third beat. Whitespace is
+    /// ignored; any other unknown character fails the whole pattern.
+    pub fn pattern_from_str(pattern: &str) -> Option<Vec<Self>> {
+        pattern
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(Self::from_char)
+            .collect()
+    }
+
+    // (frequency, gain) of the click, None for silence.
+    fn voice(self) -> Option<(f32, f32)> {
+        match self {
+            ClickAccent::Strong => Some((ACCENT_HZ, 1_f32)),
+            ClickAccent::Normal => Some((BEAT_HZ, 0.6_f32)),
+            ClickAccent::Weak => Some((BEAT_HZ, 0.3_f32)),
+            ClickAccent::Silent => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetronomeSettings {
+    /// Accent of each beat of the bar, repeating from every downbeat. Empty accents the downbeat only.
+    pub accents: Vec<ClickAccent>,
+    /// Bars clicked before the first beat of the grid.
+    pub count_in_bars: u32,
+    pub click_gain: f32,
+    /// Level of the song under the clicks when mixing.
+    pub song_gain: f32,
+}
+
+impl Default for MetronomeSettings {
+    fn default() -> Self {
+        Self {
+            accents: Vec::new(),
+            count_in_bars: DEFAULT_COUNT_IN_BARS,
+            click_gain: DEFAULT_CLICK_GAIN,
+            song_gain: DEFAULT_SONG_GAIN,
+        }
+    }
+}
+
+impl MetronomeSettings {
+    fn accent_of(&self, beat_in_bar: u32) -> ClickAccent {
+        if self.accents.is_empty() {
+            if beat_in_bar == 0 {
+                ClickAccent::Strong
+            } else {
+                ClickAccent::Normal
+            }
+        } else {
+            self.accents[beat_in_bar as usize % self.accents.len()]
+        }
+    }
+}
+
+/// Stereo frames ready for `write_samples_to_wav_bytes`.
+#[derive(Debug, Clone, Default)]
+pub struct ClickTrack {
+    pub sample_rate: i32,
+    pub samples: Vec<(i16, i16)>,
+    /// Song time 0 is this far into `samples`; the count-in plays before it.
+    pub lead_in_secs: f32,
+}
+
+impl ClickTrack {
+    pub fn to_wav_bytes(&self, channels: u16) -> Result<Vec<u8>, hound::Error> {
+        write_samples_to_wav_bytes(self.sample_rate, channels, &self.samples)
+    }
+}
+
+/// Song time and accent of every click: the count-in bars, then one click per beat of `grid`. Count-in
+/// clicks repeat the grid's first beat interval and may fall before song time 0. Build the grid with
+/// `BeatGrid::from_bpm` to click a constant tempo.
+pub fn click_times(grid: &BeatGrid, settings: &MetronomeSettings) -> Vec<(f32, ClickAccent)> {
+    let Some(&first_beat) = grid.beats.first() else {
+        return Vec::new();
+    };
+    let per_bar = grid.beats_per_bar.max(1) as i32;
+    let period = match grid.beats.get(1) {
+        Some(&second_beat) => second_beat - first_beat,
+        None => DEFAULT_US_PER_QN / 1_000_000_f32,
+    };
+    let count_in = settings.count_in_bars as i32 * per_bar;
+    let beat_in_bar = |index: i32| (index - grid.first_downbeat as i32).rem_euclid(per_bar) as u32;
+    let count_in_clicks = (-count_in..0).map(|index| (first_beat + index as f32 * period, index));
+    let grid_clicks = grid.beats.iter().enumerate().map(|(index, &time)| (time, index as i32));
+    count_in_clicks
+        .chain(grid_clicks)
+        .map(|(time, index)| (time, settings.accent_of(beat_in_bar(index))))
+        .collect()
+}
+
+/// Clicks alone, from the start of the count-in to `duration_secs` of song time.
+pub fn render_click_track(
+    grid: &BeatGrid,
+    settings: &MetronomeSettings,
+    sample_rate: i32,
+    duration_secs: f32,
+) -> ClickTrack {
+    let clicks = click_times(grid, settings);
+    let lead_in_secs = lead_in_secs(&clicks);
+    let frame_count = ((lead_in_secs + duration_secs).max(0_f32) * sample_rate as f32).ceil() as usize;
+    let mut mix = vec![0_f32; frame_count];
+    add_clicks(&mut mix, &clicks, lead_in_secs, sample_rate, settings.click_gain);
+    ClickTrack {
+        sample_rate,
+        samples: mix
+            .iter()
+            .map(|&sample| (sample_to_i16(sample), sample_to_i16(sample)))
+            .collect(),
+        lead_in_secs,
+    }
+}
+
+/// `song` at `song_gain` with the clicks on top. Silence is prepended for the count-in.
+pub fn mix_click_track(
+    song: &[(i16, i16)],
+    sample_rate: i32,
+    grid: &BeatGrid,
+    settings: &MetronomeSettings,
+) -> ClickTrack {
+    let clicks = click_times(grid, settings);
+    let lead_in_secs = lead_in_secs(&clicks);
+    let lead_in_frames = (lead_in_secs * sample_rate as f32).round() as usize;
+    let mut clicks_mix = vec![0_f32; lead_in_frames + song.len()];
+    add_clicks(&mut clicks_mix, &clicks, lead_in_secs, sample_rate, settings.click_gain);
+    let to_f32 = |sample: i16| sample as f32 / i16::MAX as f32;
+    let samples = clicks_mix
+        .iter()
+        .enumerate()
+        .map(|(frame, &click)| {
+            let (left, right) = frame
+                .checked_sub(lead_in_frames)
+                .and_then(|song_frame| song.get(song_frame))
+                .map_or((0_f32, 0_f32), |&(left, right)| (to_f32(left), to_f32(right)));
+            (
+                sample_to_i16(left * settings.song_gain + click),
+                sample_to_i16(right * settings.song_gain + click),
+            )
+        })
+        .collect();
+    ClickTrack {
+        sample_rate,
+        samples,
+        lead_in_secs,
+    }
+}
+
+pub fn render_click_track_to_wav_bytes(
+    sample_rate: i32,
+    channels: u16,
+    grid: &BeatGrid,
+    settings: &MetronomeSettings,
+    duration_secs: f32,
+) -> Result<Vec<u8>, hound::Error> {
+    render_click_track(grid, settings, sample_rate, duration_secs).to_wav_bytes(channels)
+}
+
+pub fn mix_click_track_to_wav_bytes(
+    sample_rate: i32,
+    channels: u16,
+    song: &[(i16, i16)],
+    grid: &BeatGrid,
+    settings: &MetronomeSettings,
+) -> Result<Vec<u8>, hound::Error> {
+    mix_click_track(song, sample_rate, grid, settings).to_wav_bytes(channels)
+}
+
+fn lead_in_secs(clicks: &[(f32, ClickAccent)]) -> f32 {
+    clicks.first().map_or(0_f32, |&(time, _)| (-time).max(0_f32))
+}
+
+// Adds a decaying sine burst per click into `mix`, where frame 0 is song time `-lead_in_secs`.
+fn add_clicks(mix: &mut [f32], clicks: &[(f32, ClickAccent)], lead_in_secs: f32, sample_rate: i32, gain: f32) {
+    let sample_rate = sample_rate as f32;
+    let click_frames = (CLICK_SECS * sample_rate) as usize;
+    let attack_frames = (CLICK_ATTACK_SECS * sample_rate).max(1_f32);
+    let decay_secs = CLICK_SECS * CLICK_DECAY_RATIO;
+    for &(time, accent) in clicks {
+        let Some((hz, accent_gain)) = accent.voice() else {
+            continue;
+        };
+        let start = ((time + lead_in_secs) * sample_rate).round() as usize;
+        let end = (start + click_frames).min(mix.len());
+        for (i, sample) in mix.iter_mut().enumerate().take(end).skip(start) {
+            let t = (i - start) as f32 / sample_rate;
+            let envelope = ((i - start) as f32 / attack_frames).min(1_f32) * (-t / decay_secs).exp();
+            *sample += (TAU * hz * t).sin() * envelope * accent_gain * gain;
+        }
+    }
+}
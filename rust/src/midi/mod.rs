@@ -1,6 +1,17 @@
 pub mod util;
 
+pub mod cache;
+pub mod calibration;
+pub mod chart;
+pub mod clock;
 #[cfg(feature = "tests-only")]
 pub mod debug;
+pub mod harmony;
+pub mod metronome;
 pub mod pitch;
+pub mod player;
+pub mod program;
 pub mod rhythm;
+pub mod rhythm_format;
+pub mod timeline;
+pub mod writer;
@@ -1,28 +1,45 @@
+use crate::midi::cache::{load_or_render_wav_cache, WavCacheError, WavCacheKey};
+use crate::midi::clock::MusicalClock;
+use crate::midi::harmony::{HarmonyAnalyzer, HarmonySnapshot};
+use crate::midi::program::{ChannelProgram, ProgramMap};
+use crate::midi::timeline::NoteTimeline;
 use crate::midi::util::{
     midi_note_to_hsv, parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes, render_midi_to_wav_bytes,
-    sample_active_notes_at_time, update_note_log_history, MidiNote,
+    update_note_log_history, MidiError,
 };
+use midly::num::u4;
+use midly::Smf;
 use std::path::Path;
-use std::{collections::HashMap, fs, string::String, vec::Vec};
-
-type NoteBuffer = HashMap<MidiNote, Vec<(f32, f32)>>;
+use std::{string::String, vec::Vec};
 
 #[derive(Default)]
 pub struct PitchDimension {
-    note_buffer: NoteBuffer,
+    note_timeline: NoteTimeline,
+    musical_clock: MusicalClock,
+    harmony: HarmonyAnalyzer,
     last_active_notes: Vec<u8>,
     note_log_history: Vec<String>,
     hsv_buffer: Vec<[f32; 3]>,
+    program_map: ProgramMap,
 }
 
-const TARGET_CHANNEL: u8 = 0;
-const PROGRAM: u8 = 0;
-
 pub const HSV_BUFFER_LEN: usize = 6;
 
 impl PitchDimension {
-    pub fn resolve_payload_to_midi_buffer(&mut self, midi_bytes: &[u8]) {
-        self.note_buffer = parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes(midi_bytes);
+    pub fn set_program_override(&mut self, channel: u4, program: ChannelProgram) {
+        self.program_map.set_override(channel, program);
+    }
+
+    pub fn clear_program_override(&mut self, channel: u4) {
+        self.program_map.clear_override(channel);
+    }
+
+    pub fn resolve_payload_to_midi_buffer(&mut self, midi_bytes: &[u8]) -> Result<(), MidiError> {
+        let note_buffer =
+            parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes(midi_bytes, &self.program_map)?;
+        self.note_timeline = NoteTimeline::from_note_buffer(&note_buffer);
+        self.musical_clock = MusicalClock::from_smf(&Smf::parse(midi_bytes)?)?;
+        Ok(())
     }
 
     pub fn resolve_payload_to_pcm_buffer(
@@ -32,10 +49,12 @@ impl PitchDimension {
         midi_bytes: &[u8],
         sf2_bytes: &[u8],
     ) -> Vec<u8> {
-        render_midi_to_wav_bytes(sample_rate, channels, midi_bytes, sf2_bytes, TARGET_CHANNEL, PROGRAM)
+        render_midi_to_wav_bytes(sample_rate, channels, midi_bytes, sf2_bytes, &self.program_map)
             .expect("Failed to render MIDI to WAV")
     }
 
+    /// Serves the WAV at `cache_path` while its sidecar still matches the MIDI, soundfont, program overrides
+    /// and render settings, re-rendering and rewriting both otherwise.
     pub fn resolve_payload_to_pcm_buffer_cache(
         &self,
         sample_rate: i32,
@@ -43,21 +62,17 @@ impl PitchDimension {
         midi_bytes: &[u8],
         sf2_bytes: &[u8],
         cache_path: &str,
-    ) -> Vec<u8> {
-        match fs::read(cache_path) {
-            Ok(bytes) => bytes,
-            Err(_) => {
-                let max_time = self
-                    .note_buffer
-                    .values()
-                    .flat_map(|events| events.iter().map(|&(_on, off)| off))
-                    .fold(0.0_f32, f32::max);
+    ) -> Result<Vec<u8>, WavCacheError> {
+        let key = WavCacheKey::new(sample_rate, channels, midi_bytes, sf2_bytes, &self.program_map);
+        load_or_render_wav_cache(Path::new(cache_path), key, || {
+            let max_time = self.note_timeline.end_time();
 
-                let est_bytes = (max_time * sample_rate as f32 * channels as f32 * 2.0) as f64;
-                let est_mb = est_bytes / (1024.0 * 1024.0);
+            let est_bytes = (max_time * sample_rate as f32 * channels as f32 * 2.0) as f64;
+            let est_mb = est_bytes / (1024.0 * 1024.0);
 
-                println!(
-                    "Generating WAV cache... \n\
+            println!(
+                "Generating WAV cache... \n\
+                 • cache key:            {:016x}\n\
                  • midi size:            {} bytes\n\
                  • soundfont size:       {} bytes\n\
                  • sample rate:          {} Hz\n\
@@ -65,28 +80,24 @@ impl PitchDimension {
                  • estimated duration:   {:.2} sec\n\
                  • estimated WAV size:   {:.2} MB\n\
                  → writing to:           {}\n",
-                    midi_bytes.len(),
-                    sf2_bytes.len(),
-                    sample_rate,
-                    channels,
-                    max_time,
-                    est_mb,
-                    cache_path,
-                );
-                let bytes = self.resolve_payload_to_pcm_buffer(sample_rate, channels, midi_bytes, sf2_bytes);
-                let actual_mb = bytes.len() as f64 / 1024.0 / 1024.0;
-                println!("→ actual WAV size on disk: {:.2} MB", actual_mb);
-                if let Some(parent_dir) = Path::new(cache_path).parent() {
-                    let _ = fs::create_dir_all(parent_dir);
-                }
-                fs::write(cache_path, &bytes).expect("Failed to write WAV cache");
-                bytes
-            },
-        }
+                key.hash,
+                midi_bytes.len(),
+                sf2_bytes.len(),
+                sample_rate,
+                channels,
+                max_time,
+                est_mb,
+                cache_path,
+            );
+            let bytes = render_midi_to_wav_bytes(sample_rate, channels, midi_bytes, sf2_bytes, &self.program_map)?;
+            let actual_mb = bytes.len() as f64 / 1024.0 / 1024.0;
+            println!("→ actual WAV size on disk: {:.2} MB", actual_mb);
+            Ok(bytes)
+        })
     }
 
     pub fn update_hsv_buffer(&mut self, time: f32) -> Vec<u8> {
-        let notes = sample_active_notes_at_time(&self.note_buffer, time);
+        let notes = self.note_timeline.active_notes_at(time);
         self.hsv_buffer.clear();
         let polyphony = notes.len();
         for note in notes.iter().take(HSV_BUFFER_LEN) {
@@ -96,11 +107,24 @@ impl PitchDimension {
         while self.hsv_buffer.len() < HSV_BUFFER_LEN {
             self.hsv_buffer.push([0.0, 0.0, 0.0]);
         }
+        self.harmony.update(time, &notes);
         update_note_log_history(time, &notes, &mut self.last_active_notes, &mut self.note_log_history);
         notes
     }
 
+    pub fn note_timeline(&self) -> &NoteTimeline {
+        &self.note_timeline
+    }
+
+    pub fn musical_clock(&self) -> &MusicalClock {
+        &self.musical_clock
+    }
+
     pub fn get_hsv_buffer(&self) -> Vec<[f32; 3]> {
         self.hsv_buffer.clone()
     }
+
+    pub fn get_harmony(&self) -> HarmonySnapshot {
+        self.harmony.snapshot()
+    }
 }
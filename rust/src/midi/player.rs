@@ -0,0 +1,195 @@
+use crate::midi::program::{ChannelProgramState, ProgramMap};
+use crate::midi::util::{
+    forward_midi_message, prepare_events, send_program, EventClock, RENDER_BLOCK_SIZE, TAIL_RELEASE_SECS,
+};
+use midly::{MidiMessage, Smf, TrackEventKind};
+use rustysynth::{SoundFont, Synthesizer, SynthesizerSettings};
+use std::error::Error;
+use std::io::Cursor;
+use std::sync::Arc;
+
+struct TimedMessage {
+    secs: f64,
+    channel: u8,
+    message: MidiMessage,
+}
+
+/// Streaming counterpart of `render_midi_to_wav_bytes`: renders PCM on demand into caller buffers
+/// so playback can start immediately, seek, loop a region and change tempo while running.
+pub struct MidiPlayer {
+    synth: Synthesizer,
+    sample_rate: i32,
+    channels: u16,
+    messages: Vec<TimedMessage>,
+    program_map: ProgramMap,
+    programs: ChannelProgramState,
+    cursor: usize,
+    position_secs: f64,
+    tempo_scale: f64,
+    loop_region: Option<(f64, f64)>,
+    duration_secs: f64,
+    left: [f32; RENDER_BLOCK_SIZE],
+    right: [f32; RENDER_BLOCK_SIZE],
+}
+
+impl MidiPlayer {
+    pub fn new(
+        sample_rate: i32,
+        channels: u16,
+        midi_bytes: &[u8],
+        sf2_bytes: &[u8],
+        program_map: &ProgramMap,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut sf2_cursor = Cursor::new(sf2_bytes);
+        let soundfont = Arc::new(SoundFont::new(&mut sf2_cursor)?);
+        let synth = Synthesizer::new(&soundfont, &SynthesizerSettings::new(sample_rate))?;
+        let smf = Smf::parse(midi_bytes)?;
+        let mut clock = EventClock::from_smf(&smf)?;
+        let mut messages = Vec::new();
+        for (tick, event) in prepare_events(&smf) {
+            clock.advance(tick, &event);
+            if let TrackEventKind::Midi { channel, message } = event {
+                messages.push(TimedMessage {
+                    secs: clock.elapsed_secs(),
+                    channel: channel.as_int(),
+                    message,
+                });
+            }
+        }
+        let duration_secs = clock.elapsed_secs();
+        let mut player = Self {
+            synth,
+            sample_rate,
+            channels,
+            messages,
+            program_map: program_map.clone(),
+            programs: ChannelProgramState::new(program_map),
+            cursor: 0,
+            position_secs: 0_f64,
+            tempo_scale: 1_f64,
+            loop_region: None,
+            duration_secs,
+            left: [0_f32; RENDER_BLOCK_SIZE],
+            right: [0_f32; RENDER_BLOCK_SIZE],
+        };
+        player.seek(0_f32);
+        Ok(player)
+    }
+
+    /// Fills `out` with interleaved frames (`channels` samples per frame, mono is an L/R average)
+    /// and returns the number of frames written.
+    pub fn fill(&mut self, out: &mut [f32]) -> usize {
+        let channels = self.channels.max(1) as usize;
+        let frame_count = out.len() / channels;
+        let mut written = 0_usize;
+        while written < frame_count {
+            if let Some((start, end)) = self.loop_region {
+                if self.position_secs >= end {
+                    self.seek(start as f32);
+                }
+            }
+            self.dispatch_due_messages();
+            let block_len = self
+                .frames_until_next_boundary()
+                .min(frame_count - written)
+                .min(RENDER_BLOCK_SIZE);
+            self.synth
+                .render(&mut self.left[..block_len], &mut self.right[..block_len]);
+            for i in 0..block_len {
+                let frame = &mut out[(written + i) * channels..(written + i + 1) * channels];
+                if channels == 1 {
+                    frame[0] = (self.left[i] + self.right[i]) * 0.5_f32;
+                } else {
+                    frame[0] = self.left[i];
+                    frame[1] = self.right[i];
+                }
+            }
+            written += block_len;
+            self.position_secs += block_len as f64 * self.tempo_scale / self.sample_rate as f64;
+        }
+        written
+    }
+
+    /// Jumps to `secs` in song time. Sounding notes are cut, programs and controllers are
+    /// chased up to the new position so the next notes play with the right patch and levels.
+    pub fn seek(&mut self, secs: f32) {
+        let target = (secs.max(0_f32) as f64).min(self.duration_secs);
+        self.synth.reset();
+        self.programs = ChannelProgramState::new(&self.program_map);
+        for (channel, program) in self.program_map.overrides() {
+            send_program(&mut self.synth, channel, program);
+        }
+        self.cursor = 0;
+        while self.cursor < self.messages.len() && self.messages[self.cursor].secs < target {
+            let TimedMessage { channel, message, .. } = self.messages[self.cursor];
+            if !matches!(message, MidiMessage::NoteOn { .. } | MidiMessage::NoteOff { .. }) {
+                self.dispatch(channel, message);
+            }
+            self.cursor += 1;
+        }
+        self.position_secs = target;
+    }
+
+    pub fn set_loop(&mut self, start_secs: f32, end_secs: f32) {
+        if end_secs > start_secs {
+            self.loop_region = Some((start_secs.max(0_f32) as f64, end_secs as f64));
+        }
+    }
+
+    pub fn clear_loop(&mut self) {
+        self.loop_region = None;
+    }
+
+    /// Playback speed relative to the file's own tempo map, 1.0 is as written.
+    pub fn set_tempo_scale(&mut self, tempo_scale: f32) {
+        self.tempo_scale = tempo_scale.max(f32::EPSILON) as f64;
+    }
+
+    pub fn tempo_scale(&self) -> f32 {
+        self.tempo_scale as f32
+    }
+
+    pub fn position(&self) -> f32 {
+        self.position_secs as f32
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.duration_secs as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.loop_region.is_none()
+            && self.cursor >= self.messages.len()
+            && self.position_secs >= self.duration_secs + TAIL_RELEASE_SECS as f64
+    }
+
+    fn dispatch_due_messages(&mut self) {
+        while self.cursor < self.messages.len() && self.messages[self.cursor].secs <= self.position_secs {
+            let TimedMessage { channel, message, .. } = self.messages[self.cursor];
+            self.dispatch(channel, message);
+            self.cursor += 1;
+        }
+    }
+
+    fn dispatch(&mut self, channel: u8, message: MidiMessage) {
+        if let Some(program) = self.programs.apply(channel, &message) {
+            send_program(&mut self.synth, channel, program);
+        }
+        forward_midi_message(&mut self.synth, channel, &message);
+    }
+
+    fn frames_until_next_boundary(&self) -> usize {
+        let mut boundary = f64::INFINITY;
+        if let Some(next) = self.messages.get(self.cursor) {
+            boundary = next.secs;
+        }
+        if let Some((_, end)) = self.loop_region {
+            boundary = boundary.min(end);
+        }
+        if boundary.is_infinite() {
+            return RENDER_BLOCK_SIZE;
+        }
+        let frames = ((boundary - self.position_secs) * self.sample_rate as f64 / self.tempo_scale).ceil();
+        frames.max(1_f64) as usize
+    }
+}
@@ -0,0 +1,115 @@
+use midly::num::u4;
+use midly::MidiMessage;
+
+pub const MIDI_CHANNEL_COUNT: usize = 16_usize;
+pub const CC_BANK_SELECT_MSB: u8 = 0;
+pub const CC_BANK_SELECT_LSB: u8 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ChannelProgram {
+    pub bank_msb: u8,
+    pub bank_lsb: u8,
+    pub program: u8,
+}
+
+impl ChannelProgram {
+    pub fn new(bank_msb: u8, bank_lsb: u8, program: u8) -> Self {
+        Self {
+            bank_msb,
+            bank_lsb,
+            program,
+        }
+    }
+
+    pub fn bank(&self) -> u16 {
+        ((self.bank_msb as u16) << 7) | self.bank_lsb as u16
+    }
+}
+
+/// Channel -> (bank, program) mapping. Channels without an override follow the
+/// ProgramChange and bank select (CC0/CC32) events found in the MIDI file itself.
+#[derive(Debug, Clone, Default)]
+pub struct ProgramMap {
+    overrides: [Option<ChannelProgram>; MIDI_CHANNEL_COUNT],
+}
+
+impl ProgramMap {
+    pub fn with_override(mut self, channel: u4, program: ChannelProgram) -> Self {
+        self.set_override(channel, program);
+        self
+    }
+
+    pub fn set_override(&mut self, channel: u4, program: ChannelProgram) {
+        self.overrides[channel.as_int() as usize] = Some(program);
+    }
+
+    pub fn clear_override(&mut self, channel: u4) {
+        self.overrides[channel.as_int() as usize] = None;
+    }
+
+    /// None for channels without an override, including anything past the 16 MIDI channels.
+    pub fn override_for(&self, channel: u8) -> Option<ChannelProgram> {
+        self.overrides.get(channel as usize).copied().flatten()
+    }
+
+    pub fn overrides(&self) -> impl Iterator<Item = (u8, ChannelProgram)> + '_ {
+        self.overrides
+            .iter()
+            .enumerate()
+            .filter_map(|(channel, program)| program.map(|p| (channel as u8, p)))
+    }
+}
+
+/// Per-channel program state while walking a tick-sorted event stream.
+pub struct ChannelProgramState {
+    map: ProgramMap,
+    pending_bank: [(u8, u8); MIDI_CHANNEL_COUNT],
+    current: [ChannelProgram; MIDI_CHANNEL_COUNT],
+}
+
+impl ChannelProgramState {
+    pub fn new(map: &ProgramMap) -> Self {
+        let mut current = [ChannelProgram::default(); MIDI_CHANNEL_COUNT];
+        for (channel, program) in map.overrides() {
+            current[channel as usize] = program;
+        }
+        Self {
+            map: map.clone(),
+            pending_bank: [(0, 0); MIDI_CHANNEL_COUNT],
+            current,
+        }
+    }
+
+    /// Feeds one channel message through the state. Bank selects only latch, the
+    /// program resolves on the next ProgramChange. Returns the new program when it changed.
+    pub fn apply(&mut self, channel: u8, message: &MidiMessage) -> Option<ChannelProgram> {
+        let ch = channel as usize;
+        match *message {
+            MidiMessage::Controller { controller, value } if controller.as_int() == CC_BANK_SELECT_MSB => {
+                self.pending_bank[ch].0 = value.as_int();
+                None
+            },
+            MidiMessage::Controller { controller, value } if controller.as_int() == CC_BANK_SELECT_LSB => {
+                self.pending_bank[ch].1 = value.as_int();
+                None
+            },
+            MidiMessage::ProgramChange { program } => {
+                if self.map.override_for(channel).is_some() {
+                    return None;
+                }
+                let (bank_msb, bank_lsb) = self.pending_bank[ch];
+                let resolved = ChannelProgram::new(bank_msb, bank_lsb, program.as_int());
+                if resolved == self.current[ch] {
+                    return None;
+                }
+                self.current[ch] = resolved;
+                Some(resolved)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn program(&self, channel: u8) -> ChannelProgram {
+        self.current[channel as usize]
+    }
+}
@@ -1,180 +1,1126 @@
 extern crate alloc;
-use crate::audio_analysis::util::detect_bpm_aubio_ogg;
+use crate::audio_analysis::beat::{track_beats, BeatGrid, DEFAULT_BEATS_PER_BAR};
+use crate::audio_analysis::util::{decode_ogg_to_mono, decode_ogg_to_stereo, detect_bpm_ogg, BpmDetector};
+use crate::midi::calibration::{
+    apply_calibration, CalibrationCue, CalibrationSession, OffsetEstimate, DEFAULT_CALIBRATION_LEAD_IN_BEATS,
+};
+use crate::midi::chart::{
+    generate_chart_from_audio, generate_chart_from_midi, AudioChartRules, ChartLanes, MidiChartRules,
+};
+use crate::midi::clock::MusicalClock;
+use crate::midi::metronome::{mix_click_track, ClickTrack, MetronomeSettings};
+use crate::midi::program::ProgramMap;
+use crate::midi::rhythm_format::{
+    decode_rhythm_data, encode_rhythm_data, rhythm_data_from_text, rhythm_data_to_text, RhythmDataError,
+};
+use crate::midi::util::{MidiError, DEFAULT_US_PER_QN};
 use alloc::vec::Vec;
 use asset_payload::payloads::SHADERTOY_EXPERIMENT_OGG;
-use asset_payload::CACHED_RHYTHM_DATA_PATH;
-use std::fs;
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use asset_payload::{CACHED_RHYTHM_DATA_PATH, CACHED_RHYTHM_DATA_V0_PATH};
+use std::path::{Path, PathBuf};
+use std::{fmt, fs};
 
-#[derive(Default)]
+/// Measured device latency in seconds, see `midi::calibration`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RhythmOffsets {
+    /// How long after its scheduled song time a sound is heard.
+    pub audio_secs: f32,
+    /// How long after the physical press an input timestamp is taken.
+    pub input_secs: f32,
+}
+
+impl RhythmOffsets {
+    /// Chart time the player is hearing at clock time `song_time`.
+    pub fn audible_time(&self, song_time: f32) -> f32 {
+        song_time - self.audio_secs
+    }
+
+    /// Chart time the player was reacting to when an input stamped `input_time` arrived.
+    pub fn input_chart_time(&self, input_time: f32) -> f32 {
+        input_time - self.audio_secs - self.input_secs
+    }
+}
+
+pub const LANE_UKI: &str = "uki";
+pub const LANE_SHIZUMI: &str = "shizumi";
+/// (name, key) of the lanes every chart starts with, and that data written before named lanes loads into.
+pub const DEFAULT_LANES: [(&str, &str); 2] = [(LANE_UKI, "F"), (LANE_SHIZUMI, "J")];
+
+/// How the notes of a lane are played and graded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoteKind {
+    /// Graded on press only.
+    Tap,
+    /// Graded on press, and also on release for notes at least `JudgementWindows::hold_min_secs` long.
+    #[default]
+    Hold,
+    /// Graded on release only, however early the key went down.
+    Release,
+}
+
+impl NoteKind {
+    pub const ALL: [NoteKind; 3] = [NoteKind::Tap, NoteKind::Hold, NoteKind::Release];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NoteKind::Tap => "tap",
+            NoteKind::Hold => "hold",
+            NoteKind::Release => "release",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        NoteKind::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RhythmLane {
+    pub name: String,
+    /// Key the lane is played with, by name ("F", "Space").
+    pub key: String,
+    pub kind: NoteKind,
+    /// [press, release] in song seconds, sorted by press.
+    pub onsets: Vec<[f32; 2]>,
+}
+
+impl RhythmLane {
+    /// Panics if `name` is not a valid lane name, see `is_valid_name`.
+    pub fn new(name: &str, key: &str, kind: NoteKind) -> Self {
+        assert!(Self::is_valid_name(name), "lane name {:?} must be one word", name);
+        Self {
+            name: name.to_string(),
+            key: key.to_string(),
+            kind,
+            onsets: Vec::new(),
+        }
+    }
+
+    /// Lane names are a single non-empty word so the text export can put them in a section header.
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && !name.contains(char::is_whitespace)
+    }
+
+    /// Pairs up a flat [press, release, press, release, ..] list; a trailing lone press becomes a zero
+    /// length note.
+    pub fn pairs_from_flat(flat: &[f32]) -> Vec<[f32; 2]> {
+        flat.chunks(2)
+            .map(|chunk| match *chunk {
+                [press, release] => [press, release],
+                [press] => [press, press],
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+}
+
+pub fn default_lanes() -> Vec<RhythmLane> {
+    DEFAULT_LANES
+        .iter()
+        .map(|(name, key)| RhythmLane::new(name, key, NoteKind::default()))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct RhythmData {
     pub bpm: f32,
-    pub uki: Vec<f32>,
-    pub shizumi: Vec<f32>,
+    pub lanes: Vec<RhythmLane>,
+    pub beat_grid: BeatGrid,
+    pub offsets: RhythmOffsets,
+}
+
+impl Default for RhythmData {
+    fn default() -> Self {
+        Self {
+            bpm: 0_f32,
+            lanes: default_lanes(),
+            beat_grid: BeatGrid::default(),
+            offsets: RhythmOffsets::default(),
+        }
+    }
 }
 
 impl RhythmData {
-    pub fn load_from_file(path: &str) -> Option<Self> {
-        let bytes = fs::read(path).ok()?;
+    pub fn lane(&self, name: &str) -> Option<&RhythmLane> {
+        self.lanes.iter().find(|lane| lane.name == name)
+    }
+
+    /// The lane called `name`, appended (with its default key, if it has one) when missing. Panics if a lane
+    /// would be created with an invalid name, see `RhythmLane::is_valid_name`.
+    pub fn lane_mut_or_insert(&mut self, name: &str) -> &mut RhythmLane {
+        let index = match self.lanes.iter().position(|lane| lane.name == name) {
+            Some(index) => index,
+            None => {
+                let key = DEFAULT_LANES
+                    .iter()
+                    .find(|(default_name, _)| *default_name == name)
+                    .map_or("", |(_, key)| key);
+                self.lanes.push(RhythmLane::new(name, key, NoteKind::default()));
+                self.lanes.len() - 1
+            },
+        };
+        &mut self.lanes[index]
+    }
+
+    pub fn load_from_file(path: &str) -> Result<Self, RhythmDataError> {
+        let bytes = fs::read(path).map_err(|e| RhythmDataError::Io(PathBuf::from(path), e))?;
         RhythmData::deserialize(&bytes)
     }
 
-    pub fn save_rhythm_data(&self, path: &str) {
-        let bytes = self.serialize();
-        let mut file = File::create(path).expect("Failed to create file");
-        file.write_all(&bytes).expect("Failed to write");
+    pub fn save_rhythm_data(&self, path: &str) -> Result<(), RhythmDataError> {
+        write_creating_parent(path, &self.serialize()?)
     }
 
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        bytes.extend_from_slice(&self.bpm.to_le_bytes());
-        let uki_len = self.uki.len() as u32;
-        bytes.extend_from_slice(&uki_len.to_le_bytes());
-        for val in &self.uki {
-            bytes.extend_from_slice(&val.to_le_bytes());
-        }
-        let shizumi_len = self.shizumi.len() as u32;
-        bytes.extend_from_slice(&shizumi_len.to_le_bytes());
-        for val in &self.shizumi {
-            bytes.extend_from_slice(&val.to_le_bytes());
-        }
-        bytes
+    pub fn serialize(&self) -> Result<Vec<u8>, RhythmDataError> {
+        encode_rhythm_data(self)
     }
 
-    pub fn deserialize(bytes: &[u8]) -> Option<Self> {
-        let mut offset = 0;
-        fn read_f32(bytes: &[u8], offset: &mut usize) -> Option<f32> {
-            if *offset + 4 > bytes.len() {
-                return None;
-            }
-            let val = f32::from_le_bytes(bytes[*offset..*offset + 4].try_into().unwrap());
-            *offset += 4;
-            Some(val)
-        }
+    /// Accepts the current container and the unversioned v0 layout.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, RhythmDataError> {
+        decode_rhythm_data(bytes)
+    }
 
-        fn read_u32(bytes: &[u8], offset: &mut usize) -> Option<u32> {
-            if *offset + 4 > bytes.len() {
-                return None;
-            }
-            let val = u32::from_le_bytes(bytes[*offset..*offset + 4].try_into().unwrap());
-            *offset += 4;
-            Some(val)
-        }
-        let bpm = read_f32(bytes, &mut offset)?;
-        let uki_len = read_u32(bytes, &mut offset)? as usize;
-        let mut uki = Vec::with_capacity(uki_len);
-        for _ in 0..uki_len {
-            uki.push(read_f32(bytes, &mut offset)?);
+    pub fn to_text(&self) -> String {
+        rhythm_data_to_text(self)
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, RhythmDataError> {
+        rhythm_data_from_text(text)
+    }
+
+    pub fn export_text(&self, path: &str) -> Result<(), RhythmDataError> {
+        write_creating_parent(path, self.to_text().as_bytes())
+    }
+
+    pub fn import_text(path: &str) -> Result<Self, RhythmDataError> {
+        let text = fs::read_to_string(path).map_err(|e| RhythmDataError::Io(PathBuf::from(path), e))?;
+        RhythmData::from_text(&text)
+    }
+}
+
+// The cache used to live at the unversioned v0 path; a readable file there is migrated once and re-saved in
+// the current format. Anything unreadable is reported and regenerated rather than trusted.
+fn load_cached_rhythm_data() -> (RhythmData, bool) {
+    for (path, migrated) in [(CACHED_RHYTHM_DATA_PATH, false), (CACHED_RHYTHM_DATA_V0_PATH, true)] {
+        if !Path::new(path).exists() {
+            continue;
         }
-        let shizumi_len = read_u32(bytes, &mut offset)? as usize;
-        let mut shizumi = Vec::with_capacity(shizumi_len);
-        for _ in 0..shizumi_len {
-            shizumi.push(read_f32(bytes, &mut offset)?);
+        match RhythmData::load_from_file(path) {
+            Ok(data) => return (data, migrated),
+            Err(e) => println!("Ignoring cached RhythmData: {}", e),
         }
-        Some(Self { bpm, uki, shizumi })
     }
+    (RhythmData::default(), false)
+}
+
+fn write_creating_parent(path: &str, bytes: &[u8]) -> Result<(), RhythmDataError> {
+    let path = Path::new(path);
+    let io_err = |path: &Path| {
+        let path = path.to_path_buf();
+        move |e| RhythmDataError::Io(path, e)
+    };
+    if let Some(parent_dir) = path.parent() {
+        fs::create_dir_all(parent_dir).map_err(io_err(parent_dir))?;
+    }
+    fs::write(path, bytes).map_err(io_err(path))
 }
 
 #[derive(Default)]
 pub struct RhythmDimension {
     pub rhythm_data: RhythmData,
     pub bpm: f32,
-    pub f_onsets_flat_buffer: Vec<[f32; 2]>,
-    pub j_onsets_flat_buffer: Vec<[f32; 2]>,
-    pub f_onset_count: usize,
-    pub j_onset_count: usize,
+    /// Working copy of `rhythm_data.lanes` that snapping and judgement run on.
+    pub lanes: Vec<RhythmLane>,
     pub time_of_next_click: f32,
+    pub clock: MusicalClock,
+    pub judgement: JudgementEngine,
+    pub calibration: Option<CalibrationSession>,
 }
 
 impl RhythmDimension {
     pub fn new() -> Self {
         let mut rhythm = Self::default();
-        rhythm.rhythm_data = if Path::new(CACHED_RHYTHM_DATA_PATH).exists() {
-            RhythmData::load_from_file(CACHED_RHYTHM_DATA_PATH).expect("Failed to load cached RhythmData")
-        } else {
-            RhythmData::default()
-        };
+        let (rhythm_data, migrated) = load_cached_rhythm_data();
+        rhythm.rhythm_data = rhythm_data;
 
+        let mut dirty = migrated;
         if rhythm.rhythm_data.bpm <= 0.0 {
             let audio_bytes = SHADERTOY_EXPERIMENT_OGG();
-            rhythm.bpm = detect_bpm_aubio_ogg(audio_bytes);
+            rhythm.bpm = detect_bpm_ogg(audio_bytes, BpmDetector::default());
             println!("Offline BPM detection → {}", rhythm.bpm);
             rhythm.rhythm_data.bpm = rhythm.bpm;
-            rhythm.rhythm_data.save_rhythm_data(CACHED_RHYTHM_DATA_PATH);
+            dirty = true;
         } else {
             rhythm.bpm = rhythm.rhythm_data.bpm;
             println!("Using cached BPM → {}", rhythm.bpm);
         }
+        if rhythm.rhythm_data.beat_grid.is_empty() {
+            if let Some((samples, sample_rate)) = decode_ogg_to_mono(SHADERTOY_EXPERIMENT_OGG()) {
+                rhythm.rhythm_data.beat_grid = track_beats(&samples, sample_rate, DEFAULT_BEATS_PER_BAR);
+                println!(
+                    "Offline beat tracking → {} beats",
+                    rhythm.rhythm_data.beat_grid.beats.len()
+                );
+                dirty = true;
+            }
+        }
+        if dirty {
+            if let Err(e) = rhythm.rhythm_data.save_rhythm_data(CACHED_RHYTHM_DATA_PATH) {
+                println!("Failed to cache RhythmData: {}", e);
+            }
+        }
 
+        rhythm.clock = MusicalClock::from_bpm(rhythm.bpm, 4, 4);
         rhythm.load_custom_onsets();
         rhythm
     }
 
-    pub fn load_custom_onsets(&mut self) {
-        self.f_onsets_flat_buffer.clear();
-        self.j_onsets_flat_buffer.clear();
+    pub fn seconds_until_next_downbeat(&self, song_time: f32) -> f32 {
+        let song_time = self.rhythm_data.offsets.audible_time(song_time);
+        match self
+            .rhythm_data
+            .beat_grid
+            .downbeats()
+            .find(|&downbeat| downbeat > song_time)
+        {
+            Some(downbeat) => downbeat - song_time,
+            None => self.clock.seconds_until_next_downbeat(song_time),
+        }
+    }
 
-        let uki = &self.rhythm_data.uki;
-        let shizumi = &self.rhythm_data.shizumi;
+    /// Next beat of the tracked grid, or of the constant BPM clock past the end of the grid.
+    pub fn next_click_after(&self, song_time: f32) -> f32 {
+        match self.rhythm_data.beat_grid.next_beat_after(song_time) {
+            Some(beat) => beat,
+            None => song_time + self.clock.seconds_until_next_beat(song_time),
+        }
+    }
 
-        for chunk in uki.chunks(2) {
-            if let [press, release] = *chunk {
-                self.f_onsets_flat_buffer.push([press, release]);
-            }
+    /// Grid the metronome clicks along: the tracked one, or the song BPM over `duration_secs` without one.
+    pub fn metronome_grid(&self, duration_secs: f32) -> BeatGrid {
+        if self.rhythm_data.beat_grid.is_empty() {
+            BeatGrid::from_bpm(
+                self.bpm,
+                0_f32,
+                duration_secs,
+                self.clock.time_signature_at(0_f32).0 as u32,
+            )
+        } else {
+            self.rhythm_data.beat_grid.clone()
         }
-        self.f_onset_count = self.f_onsets_flat_buffer.len();
+    }
 
-        for chunk in shizumi.chunks(2) {
-            if let [press, release] = *chunk {
-                self.j_onsets_flat_buffer.push([press, release]);
-            }
+    /// `ogg_bytes` with a click on every beat, to check chart alignment by ear. None if it cannot be decoded.
+    pub fn render_song_with_clicks(&self, ogg_bytes: &[u8], settings: &MetronomeSettings) -> Option<ClickTrack> {
+        let (song, sample_rate) = decode_ogg_to_stereo(ogg_bytes)?;
+        let grid = self.metronome_grid(song.len() as f32 / sample_rate as f32);
+        Some(mix_click_track(&song, sample_rate as i32, &grid, settings))
+    }
+
+    /// Moves authored press/release times onto the nearest 1/`subdivision` beat of the tracked grid.
+    pub fn snap_onsets_to_grid(&mut self, subdivision: u32) {
+        let grid = &self.rhythm_data.beat_grid;
+        for onset in self.lanes.iter_mut().flat_map(|lane| lane.onsets.iter_mut()) {
+            let [press, release] = *onset;
+            let snapped_press = grid.snap(press, subdivision);
+            *onset = [snapped_press, grid.snap(release, subdivision).max(snapped_press)];
+        }
+        self.rebuild_judgement();
+    }
+
+    /// Replaces the uki/shizumi lanes with ones generated from `midi_bytes` and saves them to the rhythm cache.
+    pub fn generate_chart_from_midi(
+        &mut self,
+        midi_bytes: &[u8],
+        program_map: &ProgramMap,
+        rules: &MidiChartRules,
+    ) -> Result<(), MidiError> {
+        let lanes = generate_chart_from_midi(midi_bytes, program_map, rules)?;
+        self.apply_chart(lanes);
+        Ok(())
+    }
+
+    /// Replaces the uki/shizumi lanes with ones generated from the onsets of `ogg_bytes` and saves them to
+    /// the rhythm cache. Returns false and leaves the lanes alone if the audio cannot be decoded.
+    pub fn generate_chart_from_audio(&mut self, ogg_bytes: &[u8], rules: &AudioChartRules) -> bool {
+        let Some((samples, sample_rate)) = decode_ogg_to_mono(ogg_bytes) else {
+            return false;
+        };
+        self.apply_chart(generate_chart_from_audio(&samples, sample_rate, rules));
+        true
+    }
+
+    fn apply_chart(&mut self, lanes: ChartLanes) {
+        println!(
+            "Generated chart → {} uki, {} shizumi",
+            lanes.uki.len(),
+            lanes.shizumi.len()
+        );
+        self.rhythm_data.lane_mut_or_insert(LANE_UKI).onsets = lanes.uki;
+        self.rhythm_data.lane_mut_or_insert(LANE_SHIZUMI).onsets = lanes.shizumi;
+        if let Err(e) = self.rhythm_data.save_rhythm_data(CACHED_RHYTHM_DATA_PATH) {
+            println!("Failed to cache RhythmData: {}", e);
         }
-        self.j_onset_count = self.j_onsets_flat_buffer.len();
+        self.load_custom_onsets();
     }
 
-    pub fn update(&self, delta: f32, song_time: &mut f32) {
+    pub fn load_custom_onsets(&mut self) {
+        self.lanes = self.rhythm_data.lanes.clone();
+        self.rebuild_judgement();
+    }
+
+    /// Index of the lane bound to `key`, compared case-insensitively.
+    pub fn lane_for_key(&self, key: &str) -> Option<usize> {
+        self.lanes.iter().position(|lane| lane.key.eq_ignore_ascii_case(key))
+    }
+
+    /// Density and difficulty of the working lanes, treating holds the way judgement does.
+    pub fn chart_stats(&self) -> ChartStats {
+        analyze_chart(
+            &self.lanes,
+            &ChartAnalysisSettings {
+                hold_min_secs: self.judgement.windows.hold_min_secs,
+                ..ChartAnalysisSettings::default()
+            },
+        )
+    }
+
+    // Judgement starts over whenever the lanes change underneath it.
+    fn rebuild_judgement(&mut self) {
+        self.judgement = JudgementEngine::new(&self.lanes, self.judgement.windows);
+    }
+
+    pub fn update(&mut self, delta: f32, song_time: &mut f32) {
         self.debug_custom_onsets_ascii(delta, song_time);
+        let offsets = self.rhythm_data.offsets;
+        // Clock time at which the next click will actually be heard.
+        self.time_of_next_click = self.next_click_after(offsets.audible_time(*song_time)) + offsets.audio_secs;
+        self.judgement.advance(offsets.input_chart_time(*song_time));
+    }
+
+    /// `input.lane` indexes `lanes`. `input.time` is clock time and gets the latency offsets taken off before
+    /// judging.
+    pub fn judge_input(&mut self, mut input: LaneInput) -> Option<Judgement> {
+        input.time = self.rhythm_data.offsets.input_chart_time(input.time);
+        self.judgement.input(input)
+    }
+
+    /// Schedules `click_count` cues at the song BPM after a `DEFAULT_CALIBRATION_LEAD_IN_BEATS` beat lead-in
+    /// from `song_time`.
+    pub fn start_calibration(&mut self, cue: CalibrationCue, song_time: f32, click_count: usize) {
+        // Without a detected tempo the SMF default of 120 bpm is as good a pulse as any.
+        let bpm = if self.bpm > 0_f32 {
+            self.bpm
+        } else {
+            60_000_000_f32 / DEFAULT_US_PER_QN
+        };
+        self.calibration = Some(CalibrationSession::new(
+            cue,
+            bpm,
+            song_time,
+            DEFAULT_CALIBRATION_LEAD_IN_BEATS,
+            click_count,
+        ));
+    }
+
+    /// Raw clock time of a tap; calibration measures the latency, so no offsets are applied here.
+    pub fn calibration_tap(&mut self, time: f32) {
+        if let Some(session) = self.calibration.as_mut() {
+            session.tap(time);
+        }
+    }
+
+    /// Ends the running session and, when enough taps agree, stores the new offsets in the rhythm cache.
+    pub fn finish_calibration(&mut self) -> Option<OffsetEstimate> {
+        let session = self.calibration.take()?;
+        let estimate = session.estimate()?;
+        apply_calibration(&mut self.rhythm_data.offsets, session.cue, &estimate);
+        println!(
+            "Calibrated {:?} offset → {:.1}ms (±{:.1}ms, {} taps, {} rejected)",
+            session.cue,
+            estimate.offset_secs * 1000_f32,
+            estimate.spread_secs * 1000_f32,
+            estimate.accepted,
+            estimate.rejected
+        );
+        if let Err(e) = self.rhythm_data.save_rhythm_data(CACHED_RHYTHM_DATA_PATH) {
+            println!("Failed to cache RhythmData: {}", e);
+        }
+        Some(estimate)
     }
 
     fn debug_custom_onsets_ascii(&self, delta: f32, song_time: &mut f32) {
         let prev_time = *song_time;
         *song_time += delta;
 
-        let f_char = ' ';
-        let mut j_char = ' ';
-        let f_press_fmt = String::new();
-        let f_rel_fmt = String::new();
-        let mut j_press_fmt = String::new();
-        let mut j_rel_fmt = String::new();
-
-        for v in &self.f_onsets_flat_buffer {
-            let [start, end] = *v;
-            if prev_time < start && *song_time >= start {
-                j_char = 'J';
-                j_press_fmt = format!("J_PRS:[{:.3},      ]", start);
+        let mut status_body = String::new();
+        let mut event_body = String::new();
+        for lane in &self.lanes {
+            let mut lane_char = ' ';
+            for &[start, end] in &lane.onsets {
+                if prev_time < start && *song_time >= start {
+                    lane_char = lane.key.chars().next().unwrap_or('?');
+                    event_body.push_str(&format!("{}_PRS:[{:.3},      ]", lane.key, start));
+                }
+                if prev_time < end && *song_time >= end {
+                    event_body.push_str(&format!("{}_REL:[{:.3}, {:.3}]", lane.key, start, end));
+                }
             }
-            if prev_time < end && *song_time >= end {
-                j_rel_fmt = format!("J_REL:[{:.3}, {:.3}]", start, end);
+            status_body.push_str(&format!("[{}] ", lane_char));
+        }
+        if !event_body.is_empty() {
+            println!("{}  {}", status_body, event_body);
+        }
+    }
+}
+
+pub const DEFAULT_PERFECT_WINDOW_SECS: f32 = 0.03;
+pub const DEFAULT_GREAT_WINDOW_SECS: f32 = 0.06;
+pub const DEFAULT_GOOD_WINDOW_SECS: f32 = 0.1;
+pub const DEFAULT_HOLD_MIN_SECS: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    Perfect,
+    Great,
+    Good,
+    Miss,
+}
+
+impl Judgement {
+    pub const ALL: [Judgement; 4] = [Judgement::Perfect, Judgement::Great, Judgement::Good, Judgement::Miss];
+
+    pub fn points(self) -> u32 {
+        match self {
+            Judgement::Perfect => 300,
+            Judgement::Great => 200,
+            Judgement::Good => 100,
+            Judgement::Miss => 0,
+        }
+    }
+
+    /// Contribution to accuracy, where 1.0 is a perfect.
+    pub fn weight(self) -> f32 {
+        self.points() as f32 / Judgement::Perfect.points() as f32
+    }
+
+    pub fn is_hit(self) -> bool {
+        self != Judgement::Miss
+    }
+}
+
+impl fmt::Display for Judgement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Judgement::Perfect => "Perfect",
+            Judgement::Great => "Great",
+            Judgement::Good => "Good",
+            Judgement::Miss => "Miss",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Half-widths of each grade around a note time. Presses earlier than the good window are ignored rather than
+/// punished; notes whose good window passes untouched are missed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JudgementWindows {
+    pub perfect_secs: f32,
+    pub great_secs: f32,
+    pub good_secs: f32,
+    /// Notes held at least this long are holds and also graded on release, shorter ones are taps.
+    pub hold_min_secs: f32,
+}
+
+impl Default for JudgementWindows {
+    fn default() -> Self {
+        Self {
+            perfect_secs: DEFAULT_PERFECT_WINDOW_SECS,
+            great_secs: DEFAULT_GREAT_WINDOW_SECS,
+            good_secs: DEFAULT_GOOD_WINDOW_SECS,
+            hold_min_secs: DEFAULT_HOLD_MIN_SECS,
+        }
+    }
+}
+
+impl JudgementWindows {
+    /// Grade for an input `offset` seconds away from its note, None outside the good window.
+    pub fn judge(&self, offset: f32) -> Option<Judgement> {
+        let distance = offset.abs();
+        if distance <= self.perfect_secs {
+            Some(Judgement::Perfect)
+        } else if distance <= self.great_secs {
+            Some(Judgement::Great)
+        } else if distance <= self.good_secs {
+            Some(Judgement::Good)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaneInputKind {
+    Press,
+    Release,
+}
+
+/// A key going down or up on `lane` at `time` seconds of song time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaneInput {
+    pub lane: usize,
+    pub kind: LaneInputKind,
+    pub time: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteResult {
+    pub lane: usize,
+    pub note_index: usize,
+    /// None for release notes, which are not graded on press.
+    pub press: Option<Judgement>,
+    /// Input time minus note time, None when the note was never pressed.
+    pub press_offset: Option<f32>,
+    /// Holds and release notes are graded on release; stays None while a hold is still down.
+    pub release: Option<Judgement>,
+    pub release_offset: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct LaneState {
+    kind: NoteKind,
+    notes: Vec<[f32; 2]>,
+    next_note: usize,
+    /// Index into `results` of the hold currently held down.
+    active_hold: Option<usize>,
+}
+
+/// Grades lane inputs against [press, release] notes and keeps the running combo, score and per-note log.
+#[derive(Debug, Clone, Default)]
+pub struct JudgementEngine {
+    pub windows: JudgementWindows,
+    lanes: Vec<LaneState>,
+    results: Vec<NoteResult>,
+    combo: u32,
+    max_combo: u32,
+    score: u32,
+    counts: [u32; 4],
+    weight_sum: f32,
+    last_judgement: Option<Judgement>,
+}
+
+impl JudgementEngine {
+    pub fn new(lanes: &[RhythmLane], windows: JudgementWindows) -> Self {
+        Self {
+            windows,
+            lanes: lanes
+                .iter()
+                .map(|lane| LaneState {
+                    kind: lane.kind,
+                    notes: lane.onsets.clone(),
+                    ..LaneState::default()
+                })
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Clears all progress but keeps the chart and windows.
+    pub fn reset(&mut self) {
+        let lanes = std::mem::take(&mut self.lanes)
+            .into_iter()
+            .map(|lane| LaneState {
+                kind: lane.kind,
+                notes: lane.notes,
+                ..LaneState::default()
+            })
+            .collect();
+        *self = Self {
+            windows: self.windows,
+            lanes,
+            ..Self::default()
+        };
+    }
+
+    fn is_hold(&self, kind: NoteKind, [press, release]: [f32; 2]) -> bool {
+        kind == NoteKind::Hold && release - press >= self.windows.hold_min_secs
+    }
+
+    /// Judges one input, returning the grade it earned. Inputs that hit nothing (early presses, releases of
+    /// taps, presses on release lanes) return None.
+    pub fn input(&mut self, input: LaneInput) -> Option<Judgement> {
+        self.advance(input.time);
+        let lane = self.lanes.get(input.lane)?;
+        let kind = lane.kind;
+        match (input.kind, kind) {
+            (LaneInputKind::Press, NoteKind::Release) => None,
+            (LaneInputKind::Press, _) => {
+                let note_index = lane.next_note;
+                let note = *lane.notes.get(note_index)?;
+                let offset = input.time - note[0];
+                let judgement = self.windows.judge(offset)?;
+                self.results.push(NoteResult {
+                    lane: input.lane,
+                    note_index,
+                    press: Some(judgement),
+                    press_offset: Some(offset),
+                    release: None,
+                    release_offset: None,
+                });
+                let is_hold = self.is_hold(kind, note);
+                let lane = &mut self.lanes[input.lane];
+                lane.next_note += 1;
+                if is_hold {
+                    lane.active_hold = Some(self.results.len() - 1);
+                }
+                self.record(judgement);
+                Some(judgement)
+            },
+            (LaneInputKind::Release, NoteKind::Release) => {
+                let note_index = lane.next_note;
+                let offset = input.time - lane.notes.get(note_index)?[1];
+                let judgement = self.windows.judge(offset)?;
+                self.results.push(NoteResult {
+                    lane: input.lane,
+                    note_index,
+                    press: None,
+                    press_offset: None,
+                    release: Some(judgement),
+                    release_offset: Some(offset),
+                });
+                self.lanes[input.lane].next_note += 1;
+                self.record(judgement);
+                Some(judgement)
+            },
+            (LaneInputKind::Release, _) => {
+                let result_index = self.lanes[input.lane].active_hold.take()?;
+                let release = self.lanes[input.lane].notes[self.results[result_index].note_index][1];
+                let offset = input.time - release;
+                // Letting go before the release window counts as dropping the hold.
+                let judgement = self.windows.judge(offset).unwrap_or(Judgement::Miss);
+                self.results[result_index].release = Some(judgement);
+                self.results[result_index].release_offset = Some(offset);
+                self.record(judgement);
+                Some(judgement)
+            },
+        }
+    }
+
+    /// Misses every note whose good window closed before `song_time` and completes holds kept down through
+    /// their release. Call once per frame; `input` also calls it before judging.
+    pub fn advance(&mut self, song_time: f32) {
+        for lane_index in 0..self.lanes.len() {
+            if let Some(result_index) = self.lanes[lane_index].active_hold {
+                let release = self.lanes[lane_index].notes[self.results[result_index].note_index][1];
+                if song_time >= release {
+                    self.lanes[lane_index].active_hold = None;
+                    self.results[result_index].release = Some(Judgement::Perfect);
+                    self.results[result_index].release_offset = Some(0_f32);
+                    self.record(Judgement::Perfect);
+                }
             }
+            let kind = self.lanes[lane_index].kind;
+            while let Some(&note) = self.lanes[lane_index].notes.get(self.lanes[lane_index].next_note) {
+                // Release notes are due at their release, everything else at its press.
+                let due = if kind == NoteKind::Release { note[1] } else { note[0] };
+                if song_time <= due + self.windows.good_secs {
+                    break;
+                }
+                let is_hold = self.is_hold(kind, note);
+                self.results.push(NoteResult {
+                    lane: lane_index,
+                    note_index: self.lanes[lane_index].next_note,
+                    press: (kind != NoteKind::Release).then_some(Judgement::Miss),
+                    press_offset: None,
+                    release: (is_hold || kind == NoteKind::Release).then_some(Judgement::Miss),
+                    release_offset: None,
+                });
+                self.lanes[lane_index].next_note += 1;
+                self.record(Judgement::Miss);
+                if is_hold {
+                    self.record(Judgement::Miss);
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, judgement: Judgement) {
+        if judgement.is_hit() {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        } else {
+            self.combo = 0;
+        }
+        self.score += judgement.points();
+        self.counts[judgement as usize] += 1;
+        self.weight_sum += judgement.weight();
+        self.last_judgement = Some(judgement);
+    }
+
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    pub fn max_combo(&self) -> u32 {
+        self.max_combo
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    pub fn count(&self, judgement: Judgement) -> u32 {
+        self.counts[judgement as usize]
+    }
+
+    /// Mean judgement weight so far, 1.0 before anything was judged.
+    pub fn accuracy(&self) -> f32 {
+        let judged: u32 = self.counts.iter().sum();
+        if judged == 0 {
+            1_f32
+        } else {
+            self.weight_sum / judged as f32
         }
+    }
+
+    pub fn last_judgement(&self) -> Option<Judgement> {
+        self.last_judgement
+    }
+
+    /// One entry per judged note, in the order the notes were first judged.
+    pub fn results(&self) -> &[NoteResult] {
+        &self.results
+    }
 
-        for v in &self.j_onsets_flat_buffer {
-            let [start, end] = *v;
-            if prev_time < start && *song_time >= start {
-                j_char = 'J';
-                j_press_fmt = format!("J_PRS:[{:.3},      ]", start);
+    /// True once every note in every lane has been judged and no hold is still down.
+    pub fn is_finished(&self) -> bool {
+        self.lanes
+            .iter()
+            .all(|lane| lane.next_note >= lane.notes.len() && lane.active_hold.is_none())
+    }
+}
+
+pub const DEFAULT_DENSITY_WINDOW_SECS: f32 = 1.0;
+pub const DEFAULT_DENSITY_STEP_SECS: f32 = 0.25;
+pub const DEFAULT_PEAK_WINDOW_COUNT: usize = 3;
+// A 16th note at 75 bpm; anything faster in one lane has to be hammered with the same finger.
+pub const DEFAULT_JACK_MAX_GAP_SECS: f32 = 0.2;
+pub const DEFAULT_TRILL_MAX_GAP_SECS: f32 = 0.15;
+pub const DEFAULT_MIN_JACK_NOTES: usize = 3;
+pub const DEFAULT_MIN_TRILL_NOTES: usize = 4;
+// Notes closer than this in different lanes are a chord and break a trill.
+const CHORD_SECS: f32 = 0.005;
+// The rating averages this share of the density curve, densest first.
+const SUSTAINED_DENSITY_FRACTION: f32 = 0.2;
+const MIN_DENSITY_WINDOW_SECS: f32 = 1e-3;
+// Extra weight per note, relative to the note count, for notes inside jacks, trills or under another lane's hold.
+const JACK_DIFFICULTY_WEIGHT: f32 = 0.5;
+const TRILL_DIFFICULTY_WEIGHT: f32 = 0.25;
+const HOLD_DIFFICULTY_WEIGHT: f32 = 0.35;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChartAnalysisSettings {
+    /// Width of the sliding window notes per second are counted over.
+    pub window_secs: f32,
+    pub step_secs: f32,
+    pub peak_window_count: usize,
+    pub jack_max_gap_secs: f32,
+    pub trill_max_gap_secs: f32,
+    pub min_jack_notes: usize,
+    pub min_trill_notes: usize,
+    /// Hold notes shorter than this play as taps, matching `JudgementWindows::hold_min_secs`.
+    pub hold_min_secs: f32,
+}
+
+impl Default for ChartAnalysisSettings {
+    fn default() -> Self {
+        Self {
+            window_secs: DEFAULT_DENSITY_WINDOW_SECS,
+            step_secs: DEFAULT_DENSITY_STEP_SECS,
+            peak_window_count: DEFAULT_PEAK_WINDOW_COUNT,
+            jack_max_gap_secs: DEFAULT_JACK_MAX_GAP_SECS,
+            trill_max_gap_secs: DEFAULT_TRILL_MAX_GAP_SECS,
+            min_jack_notes: DEFAULT_MIN_JACK_NOTES,
+            min_trill_notes: DEFAULT_MIN_TRILL_NOTES,
+            hold_min_secs: DEFAULT_HOLD_MIN_SECS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DensityWindow {
+    pub start_secs: f32,
+    pub end_secs: f32,
+    pub notes: usize,
+    pub nps: f32,
+}
+
+/// A run of notes in `lanes`: the same lane twice for a jack, the two alternating lanes for a trill.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChartPattern {
+    pub lanes: [usize; 2],
+    pub start_secs: f32,
+    pub end_secs: f32,
+    pub notes: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChartStats {
+    pub note_count: usize,
+    /// First to last note.
+    pub start_secs: f32,
+    pub duration_secs: f32,
+    pub average_nps: f32,
+    /// [window center, notes per second] every `step_secs` across the chart.
+    pub density: Vec<[f32; 2]>,
+    /// Densest non-overlapping windows, densest first.
+    pub peak_windows: Vec<DensityWindow>,
+    pub jacks: Vec<ChartPattern>,
+    pub trills: Vec<ChartPattern>,
+    /// Time spent with two or more holds down at once.
+    pub hold_overlap_secs: f32,
+    /// Notes that land while a hold in another lane is down.
+    pub notes_under_holds: usize,
+    /// Roughly the notes per second of the chart's densest stretches, raised by how many notes sit in jacks,
+    /// trills or under holds. Only comparable between charts analysed with the same settings.
+    pub difficulty: f32,
+}
+
+impl ChartStats {
+    pub fn peak_nps(&self) -> f32 {
+        self.density.iter().map(|&[_, nps]| nps).fold(0_f32, f32::max)
+    }
+
+    /// Density across the chart as `width` R8 pixels scaled to the peak. Each pixel keeps the densest sample
+    /// it covers so short bursts survive a narrow strip.
+    pub fn density_strip(&self, width: usize) -> Vec<u8> {
+        let peak = self.peak_nps();
+        let samples = self.density.len();
+        if peak <= 0_f32 || samples == 0 {
+            return vec![0; width];
+        }
+        (0..width)
+            .map(|x| {
+                let start = (x * samples / width).min(samples - 1);
+                let end = ((x + 1) * samples / width).clamp(start + 1, samples);
+                let nps = self.density[start..end]
+                    .iter()
+                    .map(|&[_, nps]| nps)
+                    .fold(0_f32, f32::max);
+                (nps / peak * 255_f32).round() as u8
+            })
+            .collect()
+    }
+}
+
+// Release notes are played when letting go, everything else on the press.
+fn played_time(kind: NoteKind, [press, release]: [f32; 2]) -> f32 {
+    if kind == NoteKind::Release {
+        release
+    } else {
+        press
+    }
+}
+
+pub fn analyze_chart(lanes: &[RhythmLane], settings: &ChartAnalysisSettings) -> ChartStats {
+    // Zero or negative windows and steps would divide by zero or never advance.
+    let settings = &ChartAnalysisSettings {
+        window_secs: settings.window_secs.max(MIN_DENSITY_WINDOW_SECS),
+        step_secs: settings.step_secs.max(MIN_DENSITY_WINDOW_SECS),
+        ..*settings
+    };
+    let mut notes: Vec<(f32, usize)> = lanes
+        .iter()
+        .enumerate()
+        .flat_map(|(index, lane)| {
+            lane.onsets
+                .iter()
+                .map(move |&note| (played_time(lane.kind, note), index))
+        })
+        .collect();
+    notes.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    let (Some(&(start_secs, _)), Some(&(end_secs, _))) = (notes.first(), notes.last()) else {
+        return ChartStats::default();
+    };
+    let times: Vec<f32> = notes.iter().map(|&(time, _)| time).collect();
+    let duration_secs = end_secs - start_secs;
+    let density = density_curve(&times, start_secs, duration_secs, settings);
+    let peak_windows = peak_windows(&density, &times, settings);
+    let jacks = find_jacks(lanes, settings);
+    let trills = find_trills(&notes, settings);
+    let holds: Vec<(usize, [f32; 2])> = lanes
+        .iter()
+        .enumerate()
+        .filter(|(_, lane)| lane.kind == NoteKind::Hold)
+        .flat_map(|(index, lane)| lane.onsets.iter().map(move |&note| (index, note)))
+        .filter(|&(_, [press, release])| release - press >= settings.hold_min_secs)
+        .collect();
+    let notes_under_holds = notes
+        .iter()
+        .filter(|&&(time, lane)| {
+            holds
+                .iter()
+                .any(|&(hold_lane, [press, release])| hold_lane != lane && press < time && time < release)
+        })
+        .count();
+
+    let mut stats = ChartStats {
+        note_count: notes.len(),
+        start_secs,
+        duration_secs,
+        average_nps: notes.len() as f32 / duration_secs.max(settings.window_secs),
+        density,
+        peak_windows,
+        jacks,
+        trills,
+        hold_overlap_secs: hold_overlap_secs(&holds),
+        notes_under_holds,
+        difficulty: 0_f32,
+    };
+    stats.difficulty = difficulty(&stats);
+    stats
+}
+
+fn density_curve(
+    times: &[f32],
+    start_secs: f32,
+    duration_secs: f32,
+    settings: &ChartAnalysisSettings,
+) -> Vec<[f32; 2]> {
+    let half_window = settings.window_secs * 0.5_f32;
+    let steps = (duration_secs / settings.step_secs).floor() as usize + 1;
+    (0..steps)
+        .map(|step| {
+            let center = start_secs + step as f32 * settings.step_secs;
+            let notes = notes_in(times, center - half_window, center + half_window);
+            [center, notes as f32 / settings.window_secs]
+        })
+        .collect()
+}
+
+// Notes in [from, to) of sorted `times`.
+fn notes_in(times: &[f32], from: f32, to: f32) -> usize {
+    times.partition_point(|&time| time < to) - times.partition_point(|&time| time < from)
+}
+
+fn peak_windows(density: &[[f32; 2]], times: &[f32], settings: &ChartAnalysisSettings) -> Vec<DensityWindow> {
+    let half_window = settings.window_secs * 0.5_f32;
+    let mut candidates = density.to_vec();
+    // Stable, so of equally dense windows the earliest wins.
+    candidates.sort_by(|a, b| b[1].total_cmp(&a[1]));
+    let mut peaks: Vec<DensityWindow> = Vec::new();
+    for [center, nps] in candidates {
+        if peaks.len() >= settings.peak_window_count || nps <= 0_f32 {
+            break;
+        }
+        let start_secs = center - half_window;
+        let end_secs = center + half_window;
+        if peaks
+            .iter()
+            .any(|peak| start_secs < peak.end_secs && peak.start_secs < end_secs)
+        {
+            continue;
+        }
+        peaks.push(DensityWindow {
+            start_secs,
+            end_secs,
+            notes: notes_in(times, start_secs, end_secs),
+            nps,
+        });
+    }
+    peaks
+}
+
+fn find_jacks(lanes: &[RhythmLane], settings: &ChartAnalysisSettings) -> Vec<ChartPattern> {
+    let mut jacks = Vec::new();
+    for (index, lane) in lanes.iter().enumerate() {
+        let mut times: Vec<f32> = lane.onsets.iter().map(|&note| played_time(lane.kind, note)).collect();
+        times.sort_by(f32::total_cmp);
+        let mut run_start = 0;
+        for i in 1..=times.len() {
+            if i < times.len() && times[i] - times[i - 1] <= settings.jack_max_gap_secs {
+                continue;
             }
-            if prev_time < end && *song_time >= end {
-                j_rel_fmt = format!("J_REL:[{:.3}, {:.3}]", start, end);
+            if i - run_start >= settings.min_jack_notes {
+                jacks.push(ChartPattern {
+                    lanes: [index, index],
+                    start_secs: times[run_start],
+                    end_secs: times[i - 1],
+                    notes: i - run_start,
+                });
             }
+            run_start = i;
         }
+    }
+    jacks.sort_by(|a, b| a.start_secs.total_cmp(&b.start_secs));
+    jacks
+}
 
-        let event_body = format!("{}{}{}{}", f_press_fmt, f_rel_fmt, j_press_fmt, j_rel_fmt);
-        if !event_body.is_empty() {
-            let status_body = format!("[{}] [{}]   {}", f_char, j_char, event_body);
-            println!("{}", status_body);
+// Runs across the time-sorted notes of all lanes that alternate between exactly two lanes.
+fn find_trills(notes: &[(f32, usize)], settings: &ChartAnalysisSettings) -> Vec<ChartPattern> {
+    let steps_between = |a: (f32, usize), b: (f32, usize)| {
+        a.1 != b.1 && b.0 - a.0 > CHORD_SECS && b.0 - a.0 <= settings.trill_max_gap_secs
+    };
+    let mut trills = Vec::new();
+    let mut run_start = 0;
+    for i in 1..=notes.len() {
+        let continues = i < notes.len()
+            && steps_between(notes[i - 1], notes[i])
+            && (i - run_start < 2 || notes[i].1 == notes[i - 2].1);
+        if continues {
+            continue;
+        }
+        if i - run_start >= settings.min_trill_notes.max(2) {
+            trills.push(ChartPattern {
+                lanes: [notes[run_start].1, notes[run_start + 1].1],
+                start_secs: notes[run_start].0,
+                end_secs: notes[i - 1].0,
+                notes: i - run_start,
+            });
+        }
+        // A third lane may still start a new trill with the note before it.
+        run_start = if i < notes.len() && steps_between(notes[i - 1], notes[i]) {
+            i - 1
+        } else {
+            i
+        };
+    }
+    trills
+}
+
+fn hold_overlap_secs(holds: &[(usize, [f32; 2])]) -> f32 {
+    let mut edges: Vec<(f32, i32)> = holds
+        .iter()
+        .flat_map(|&(_, [press, release])| [(press, 1), (release, -1)])
+        .collect();
+    // Releases sort before presses at the same time, so back to back holds do not count as overlapping.
+    edges.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    let mut held = 0;
+    let mut overlap = 0_f32;
+    let mut previous = 0_f32;
+    for (time, change) in edges {
+        if held >= 2 {
+            overlap += time - previous;
         }
+        held += change;
+        previous = time;
     }
+    overlap
+}
+
+fn difficulty(stats: &ChartStats) -> f32 {
+    let mut densities: Vec<f32> = stats.density.iter().map(|&[_, nps]| nps).collect();
+    densities.sort_by(|a, b| b.total_cmp(a));
+    let sustained_count = ((densities.len() as f32 * SUSTAINED_DENSITY_FRACTION).ceil() as usize).max(1);
+    let sustained = densities.iter().take(sustained_count).sum::<f32>() / sustained_count as f32;
+    let share = |notes: usize| notes as f32 / stats.note_count as f32;
+    let jack_notes = stats.jacks.iter().map(|jack| jack.notes).sum();
+    let trill_notes = stats.trills.iter().map(|trill| trill.notes).sum();
+    sustained
+        * (1_f32
+            + JACK_DIFFICULTY_WEIGHT * share(jack_notes)
+            + TRILL_DIFFICULTY_WEIGHT * share(trill_notes)
+            + HOLD_DIFFICULTY_WEIGHT * share(stats.notes_under_holds))
 }
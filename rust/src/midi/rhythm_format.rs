@@ -0,0 +1,547 @@
+use crate::audio_analysis::beat::BeatGrid;
+use crate::midi::rhythm::{NoteKind, RhythmData, RhythmLane, RhythmOffsets, LANE_SHIZUMI, LANE_UKI};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::{fmt, io};
+
+// Binary layout (little endian):
+//   magic[8] version:u16 section_count:u16
+//   section_count * (tag[4] payload_len:u32 payload)
+//   crc32 of everything above:u32
+// Files without the magic are the unversioned v0 layout: bpm, uki, shizumi and an optional beat grid.
+// v1 lanes were (name, values); v2 adds each lane's key and note kind.
+pub const RHYTHM_DATA_MAGIC: [u8; 8] = *b"BATHRHY\0";
+pub const RHYTHM_DATA_VERSION: u16 = 2;
+pub const RHYTHM_TEXT_HEADER: &str = "bath-rhythm";
+const SECTION_BPM: [u8; 4] = *b"BPM ";
+const SECTION_LANES: [u8; 4] = *b"LANE";
+const SECTION_BEAT_GRID: [u8; 4] = *b"GRID";
+const SECTION_OFFSETS: [u8; 4] = *b"OFFS";
+const CRC32_POLYNOMIAL: u32 = 0xedb8_8320;
+
+#[derive(Debug)]
+pub enum RhythmDataError {
+    Io(PathBuf, io::Error),
+    /// Ran out of bytes or had bytes left over while reading the named part of the file.
+    Malformed(&'static str),
+    UnsupportedVersion(u16),
+    ChecksumMismatch {
+        stored: u32,
+        computed: u32,
+    },
+    /// 1-based line number of the text export and what was wrong with it.
+    Text(usize, String),
+    /// A lane name or key longer than the u16 length prefix can describe.
+    StringTooLong(&'static str, usize),
+}
+
+impl fmt::Display for RhythmDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RhythmDataError::Io(path, e) => write!(f, "RhythmData i/o failed for {}: {}", path.display(), e),
+            RhythmDataError::Malformed(part) => write!(f, "malformed RhythmData {}", part),
+            RhythmDataError::UnsupportedVersion(version) => write!(
+                f,
+                "RhythmData version {} is newer than supported version {}",
+                version, RHYTHM_DATA_VERSION
+            ),
+            RhythmDataError::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "RhythmData checksum mismatch (stored {:08x}, computed {:08x})",
+                stored, computed
+            ),
+            RhythmDataError::Text(line, message) => write!(f, "RhythmData text line {}: {}", line, message),
+            RhythmDataError::StringTooLong(part, len) => write!(
+                f,
+                "RhythmData {} is {} bytes, longer than the {} a string can hold",
+                part,
+                len,
+                u16::MAX
+            ),
+        }
+    }
+}
+
+impl Error for RhythmDataError {}
+
+/// Fails only when a lane name or key is too long for its length prefix.
+pub fn encode_rhythm_data(data: &RhythmData) -> Result<Vec<u8>, RhythmDataError> {
+    let mut sections: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+
+    let mut bpm = Vec::new();
+    put_f32(&mut bpm, data.bpm);
+    sections.push((SECTION_BPM, bpm));
+
+    let mut lanes = Vec::new();
+    put_u32(&mut lanes, data.lanes.len() as u32);
+    for lane in &data.lanes {
+        put_str(&mut lanes, &lane.name, "lane name")?;
+        put_str(&mut lanes, &lane.key, "lane key")?;
+        lanes.push(note_kind_to_byte(lane.kind));
+        put_f32s(&mut lanes, lane.onsets.as_flattened());
+    }
+    sections.push((SECTION_LANES, lanes));
+
+    let mut grid = Vec::new();
+    put_u32(&mut grid, data.beat_grid.beats_per_bar);
+    put_u32(&mut grid, data.beat_grid.first_downbeat);
+    put_f32s(&mut grid, &data.beat_grid.beats);
+    put_u32(&mut grid, data.beat_grid.tempo_curve.len() as u32);
+    for &[time, bpm] in &data.beat_grid.tempo_curve {
+        put_f32(&mut grid, time);
+        put_f32(&mut grid, bpm);
+    }
+    sections.push((SECTION_BEAT_GRID, grid));
+
+    let mut offsets = Vec::new();
+    put_f32(&mut offsets, data.offsets.audio_secs);
+    put_f32(&mut offsets, data.offsets.input_secs);
+    sections.push((SECTION_OFFSETS, offsets));
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&RHYTHM_DATA_MAGIC);
+    put_u16(&mut bytes, RHYTHM_DATA_VERSION);
+    put_u16(&mut bytes, sections.len() as u16);
+    for (tag, payload) in &sections {
+        bytes.extend_from_slice(tag);
+        put_u32(&mut bytes, payload.len() as u32);
+        bytes.extend_from_slice(payload);
+    }
+    let crc = crc32(&bytes);
+    put_u32(&mut bytes, crc);
+    Ok(bytes)
+}
+
+/// Decodes either layout; v0 files come back migrated and are written as the current version on next save.
+pub fn decode_rhythm_data(bytes: &[u8]) -> Result<RhythmData, RhythmDataError> {
+    if bytes.starts_with(&RHYTHM_DATA_MAGIC) {
+        decode_versioned(bytes)
+    } else {
+        decode_v0(bytes)
+    }
+}
+
+fn decode_versioned(bytes: &[u8]) -> Result<RhythmData, RhythmDataError> {
+    let body_len = bytes
+        .len()
+        .checked_sub(4)
+        .filter(|&len| len >= RHYTHM_DATA_MAGIC.len() + 4)
+        .ok_or(RhythmDataError::Malformed("header"))?;
+    let (body, crc_bytes) = bytes.split_at(body_len);
+    let mut header = Reader::new(&body[RHYTHM_DATA_MAGIC.len()..], "header");
+    let version = header.u16()?;
+    // A newer writer may have changed any section, so its checksum is not worth reporting over the version.
+    if version > RHYTHM_DATA_VERSION {
+        return Err(RhythmDataError::UnsupportedVersion(version));
+    }
+    let stored = u32::from_le_bytes(crc_bytes.try_into().unwrap());
+    let computed = crc32(body);
+    if stored != computed {
+        return Err(RhythmDataError::ChecksumMismatch { stored, computed });
+    }
+    let section_count = header.u16()?;
+    let mut data = RhythmData::default();
+    for _ in 0..section_count {
+        let tag: [u8; 4] = header.take(4)?.try_into().unwrap();
+        let len = header.u32()? as usize;
+        let payload = header.take(len)?;
+        match tag {
+            SECTION_BPM => {
+                let mut section = Reader::new(payload, "bpm section");
+                data.bpm = section.f32()?;
+                section.finish()?;
+            },
+            SECTION_LANES if version < 2 => {
+                let mut section = Reader::new(payload, "lanes section");
+                for _ in 0..section.u32()? {
+                    let name = lane_name(section.string()?)?;
+                    let values = section.f32s()?;
+                    data.lane_mut_or_insert(&name).onsets = RhythmLane::pairs_from_flat(&values);
+                }
+                section.finish()?;
+            },
+            SECTION_LANES => {
+                let mut section = Reader::new(payload, "lanes section");
+                data.lanes.clear();
+                for _ in 0..section.u32()? {
+                    let name = lane_name(section.string()?)?;
+                    let key = section.string()?;
+                    let kind =
+                        note_kind_from_byte(section.take(1)?[0]).ok_or(RhythmDataError::Malformed("lane kind"))?;
+                    let mut lane = RhythmLane::new(&name, &key, kind);
+                    lane.onsets = RhythmLane::pairs_from_flat(&section.f32s()?);
+                    data.lanes.push(lane);
+                }
+                section.finish()?;
+            },
+            SECTION_BEAT_GRID => {
+                let mut section = Reader::new(payload, "beat grid section");
+                data.beat_grid = read_beat_grid(&mut section)?;
+                section.finish()?;
+            },
+            SECTION_OFFSETS => {
+                let mut section = Reader::new(payload, "offsets section");
+                data.offsets = RhythmOffsets {
+                    audio_secs: section.f32()?,
+                    input_secs: section.f32()?,
+                };
+                section.finish()?;
+            },
+            // Sections added by later versions are skipped so older builds still read what they understand.
+            _ => {},
+        }
+    }
+    header.finish()?;
+    Ok(data)
+}
+
+fn decode_v0(bytes: &[u8]) -> Result<RhythmData, RhythmDataError> {
+    let mut reader = Reader::new(bytes, "v0 layout");
+    let mut data = RhythmData {
+        bpm: reader.f32()?,
+        ..RhythmData::default()
+    };
+    data.lane_mut_or_insert(LANE_UKI).onsets = RhythmLane::pairs_from_flat(&reader.f32s()?);
+    data.lane_mut_or_insert(LANE_SHIZUMI).onsets = RhythmLane::pairs_from_flat(&reader.f32s()?);
+    if !reader.is_empty() {
+        data.beat_grid = read_beat_grid(&mut reader)?;
+    }
+    reader.finish()?;
+    Ok(data)
+}
+
+fn lane_name(name: String) -> Result<String, RhythmDataError> {
+    if RhythmLane::is_valid_name(&name) {
+        Ok(name)
+    } else {
+        Err(RhythmDataError::Malformed("lane name"))
+    }
+}
+
+fn read_beat_grid(reader: &mut Reader) -> Result<BeatGrid, RhythmDataError> {
+    let beats_per_bar = reader.u32()?;
+    let first_downbeat = reader.u32()?;
+    let beats = reader.f32s()?;
+    let curve_len = reader.u32()? as usize;
+    let mut tempo_curve = Vec::with_capacity(curve_len.min(reader.remaining() / 8));
+    for _ in 0..curve_len {
+        tempo_curve.push([reader.f32()?, reader.f32()?]);
+    }
+    Ok(BeatGrid {
+        beats,
+        beats_per_bar,
+        first_downbeat,
+        tempo_curve,
+    })
+}
+
+/// Line-based export meant for diffing charts in git: one press/release pair or beat per line. Floats use
+/// the shortest representation that parses back to the same value, so a round trip is lossless. A lane's key
+/// is quoted with `\\`, `\"` and line breaks escaped, so empty keys and leading or trailing spaces survive too.
+pub fn rhythm_data_to_text(data: &RhythmData) -> String {
+    let mut text = String::new();
+    writeln!(text, "{} {}", RHYTHM_TEXT_HEADER, RHYTHM_DATA_VERSION).unwrap();
+    writeln!(text, "\n[bpm]\n{}", data.bpm).unwrap();
+    writeln!(
+        text,
+        "\n[offsets]\naudio {}\ninput {}",
+        data.offsets.audio_secs, data.offsets.input_secs
+    )
+    .unwrap();
+    for lane in &data.lanes {
+        writeln!(
+            text,
+            "\n[lane {}]\nkey={}\nkind {}",
+            lane.name,
+            quote_key(&lane.key),
+            lane.kind.name()
+        )
+        .unwrap();
+        for [press, release] in &lane.onsets {
+            writeln!(text, "{} {}", press, release).unwrap();
+        }
+    }
+    let grid = &data.beat_grid;
+    writeln!(
+        text,
+        "\n[grid]\nbeats_per_bar {}\nfirst_downbeat {}",
+        grid.beats_per_bar, grid.first_downbeat
+    )
+    .unwrap();
+    for beat in &grid.beats {
+        writeln!(text, "beat {}", beat).unwrap();
+    }
+    for [time, bpm] in &grid.tempo_curve {
+        writeln!(text, "tempo {} {}", time, bpm).unwrap();
+    }
+    text
+}
+
+pub fn rhythm_data_from_text(text: &str) -> Result<RhythmData, RhythmDataError> {
+    #[derive(Clone, Copy)]
+    enum Section {
+        None,
+        Bpm,
+        Offsets,
+        Lane(usize),
+        Grid,
+    }
+    let mut data = RhythmData {
+        lanes: Vec::new(),
+        ..RhythmData::default()
+    };
+    let mut section = Section::None;
+    let mut seen_header = false;
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| RhythmDataError::Text(line_number, message);
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !seen_header {
+            let version = line
+                .strip_prefix(RHYTHM_TEXT_HEADER)
+                .and_then(|rest| rest.trim().parse::<u16>().ok())
+                .ok_or_else(|| error(format!("expected `{} <version>`", RHYTHM_TEXT_HEADER)))?;
+            if version > RHYTHM_DATA_VERSION {
+                return Err(RhythmDataError::UnsupportedVersion(version));
+            }
+            seen_header = true;
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            section = match name.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["bpm"] => Section::Bpm,
+                ["offsets"] => Section::Offsets,
+                ["lane", lane_name] => {
+                    if !RhythmLane::is_valid_name(lane_name) {
+                        return Err(error(format!("bad lane name `{}`", lane_name)));
+                    }
+                    if data.lane(lane_name).is_some() {
+                        return Err(error(format!("lane `{}` appears twice", lane_name)));
+                    }
+                    data.lane_mut_or_insert(lane_name);
+                    Section::Lane(data.lanes.len() - 1)
+                },
+                ["grid"] => Section::Grid,
+                ["lane", ..] => return Err(error(format!("lane names are one word, got [{}]", name))),
+                _ => return Err(error(format!("unknown section [{}]", name))),
+            };
+            continue;
+        }
+        if let (Section::Lane(lane), Some(key)) = (section, raw_line.trim_start().strip_prefix("key=")) {
+            data.lanes[lane].key = unquote_key(key).map_err(error)?;
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let number = |field: &str| {
+            field
+                .parse::<f32>()
+                .map_err(|_| error(format!("`{}` is not a number", field)))
+        };
+        let count = |field: &str| {
+            field
+                .parse::<u32>()
+                .map_err(|_| error(format!("`{}` is not a count", field)))
+        };
+        match (section, fields.as_slice()) {
+            (Section::Bpm, [bpm]) => data.bpm = number(bpm)?,
+            (Section::Offsets, ["audio", secs]) => data.offsets.audio_secs = number(secs)?,
+            (Section::Offsets, ["input", secs]) => data.offsets.input_secs = number(secs)?,
+            // Exports before `key=` wrote `key <key>`.
+            (Section::Lane(lane), ["key", key @ ..]) => data.lanes[lane].key = key.join(" "),
+            (Section::Lane(lane), ["kind", kind]) => {
+                data.lanes[lane].kind =
+                    NoteKind::from_name(kind).ok_or_else(|| error(format!("unknown note kind `{}`", kind)))?;
+            },
+            (Section::Lane(lane), [press, release]) => data.lanes[lane].onsets.push([number(press)?, number(release)?]),
+            // v1 exports wrote a lane's odd trailing press on its own.
+            (Section::Lane(lane), [press]) => {
+                let press = number(press)?;
+                data.lanes[lane].onsets.push([press, press]);
+            },
+            (Section::Grid, ["beats_per_bar", value]) => data.beat_grid.beats_per_bar = count(value)?,
+            (Section::Grid, ["first_downbeat", value]) => data.beat_grid.first_downbeat = count(value)?,
+            (Section::Grid, ["beat", time]) => data.beat_grid.beats.push(number(time)?),
+            (Section::Grid, ["tempo", time, bpm]) => data.beat_grid.tempo_curve.push([number(time)?, number(bpm)?]),
+            _ => return Err(error(format!("unexpected `{}`", line))),
+        }
+    }
+    if !seen_header {
+        return Err(RhythmDataError::Text(
+            0,
+            format!("missing `{}` header", RHYTHM_TEXT_HEADER),
+        ));
+    }
+    Ok(data)
+}
+
+fn quote_key(key: &str) -> String {
+    let mut quoted = String::with_capacity(key.len() + 2);
+    quoted.push('"');
+    for c in key.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// The value after `key=`, untrimmed. Unquoted values are taken verbatim as earlier exports wrote them.
+fn unquote_key(value: &str) -> Result<String, String> {
+    let Some(quoted) = value.strip_prefix('"') else {
+        return Ok(value.to_string());
+    };
+    let mut key = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let rest = chars.as_str();
+                if !rest.trim().is_empty() {
+                    return Err(format!("unexpected `{}` after the quoted key", rest));
+                }
+                return Ok(key);
+            },
+            '\\' => match chars.next() {
+                Some('"') => key.push('"'),
+                Some('\\') => key.push('\\'),
+                Some('n') => key.push('\n'),
+                Some('r') => key.push('\r'),
+                Some(other) => return Err(format!("unknown escape `\\{}` in key", other)),
+                None => return Err("key ends in a lone `\\`".to_string()),
+            },
+            _ => key.push(c),
+        }
+    }
+    Err("key is missing its closing quote".to_string())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    part: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], part: &'static str) -> Self {
+        Self { bytes, offset: 0, part }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], RhythmDataError> {
+        if len > self.remaining() {
+            return Err(RhythmDataError::Malformed(self.part));
+        }
+        let slice = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, RhythmDataError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, RhythmDataError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, RhythmDataError> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// u16 length followed by that many bytes of UTF-8.
+    fn string(&mut self) -> Result<String, RhythmDataError> {
+        let len = self.u16()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| RhythmDataError::Malformed(self.part))
+    }
+
+    /// u32 count followed by that many f32s.
+    fn f32s(&mut self) -> Result<Vec<f32>, RhythmDataError> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len.checked_mul(4).ok_or(RhythmDataError::Malformed(self.part))?)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.offset
+    }
+
+    fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    fn finish(&self) -> Result<(), RhythmDataError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(RhythmDataError::Malformed(self.part))
+        }
+    }
+}
+
+fn put_u16(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_f32(bytes: &mut Vec<u8>, value: f32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_str(bytes: &mut Vec<u8>, value: &str, part: &'static str) -> Result<(), RhythmDataError> {
+    let len = u16::try_from(value.len()).map_err(|_| RhythmDataError::StringTooLong(part, value.len()))?;
+    put_u16(bytes, len);
+    bytes.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn note_kind_to_byte(kind: NoteKind) -> u8 {
+    match kind {
+        NoteKind::Tap => 0,
+        NoteKind::Hold => 1,
+        NoteKind::Release => 2,
+    }
+}
+
+fn note_kind_from_byte(byte: u8) -> Option<NoteKind> {
+    match byte {
+        0 => Some(NoteKind::Tap),
+        1 => Some(NoteKind::Hold),
+        2 => Some(NoteKind::Release),
+        _ => None,
+    }
+}
+
+fn put_f32s(bytes: &mut Vec<u8>, values: &[f32]) {
+    put_u32(bytes, values.len() as u32);
+    for &value in values {
+        put_f32(bytes, value);
+    }
+}
+
+// CRC-32 (IEEE, as in zip and png), bitwise since rhythm files are tiny.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (CRC32_POLYNOMIAL & mask);
+        }
+    }
+    !crc
+}
@@ -0,0 +1,119 @@
+use crate::midi::util::MidiNote;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteInterval {
+    pub onset: f32,
+    pub release: f32,
+    pub note: MidiNote,
+}
+
+/// Note buffer flattened into onset-sorted intervals. `max_release` augments the array as an implicit
+/// balanced tree (node = midpoint of its range) so stabbing queries skip whole subtrees that ended before t.
+#[derive(Default)]
+pub struct NoteTimeline {
+    intervals: Vec<NoteInterval>,
+    max_release: Vec<f32>,
+}
+
+impl NoteTimeline {
+    pub fn from_note_buffer(buffer: &HashMap<MidiNote, Vec<(f32, f32)>>) -> Self {
+        let mut intervals: Vec<NoteInterval> = buffer
+            .iter()
+            .flat_map(|(note, segments)| {
+                segments.iter().map(move |&(onset, release)| NoteInterval {
+                    onset,
+                    release,
+                    note: note.clone(),
+                })
+            })
+            .collect();
+        intervals.sort_by(|a, b| {
+            a.onset
+                .total_cmp(&b.onset)
+                .then(a.note.midi_note.cmp(&b.note.midi_note))
+        });
+        let mut max_release = vec![f32::NEG_INFINITY; intervals.len()];
+        build_max_release(&intervals, &mut max_release, 0, intervals.len());
+        Self { intervals, max_release }
+    }
+
+    pub fn intervals(&self) -> &[NoteInterval] {
+        &self.intervals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn end_time(&self) -> f32 {
+        self.max_release
+            .get(self.intervals.len() / 2)
+            .copied()
+            .unwrap_or(0_f32)
+            .max(0_f32)
+    }
+
+    /// Same result as `sample_active_notes_at_time`: sorted midi notes with `onset <= t < release`, once per
+    /// note and instrument even when retriggered segments overlap.
+    pub fn active_notes_at(&self, t: f32) -> Vec<u8> {
+        let mut notes = Vec::new();
+        self.visit_active(0, self.intervals.len(), t, &mut |interval| {
+            notes.push((interval.note.midi_note, interval.note.instrument_id))
+        });
+        notes.sort_unstable();
+        notes.dedup();
+        notes.into_iter().map(|(midi_note, _)| midi_note).collect()
+    }
+
+    pub fn active_intervals_at(&self, t: f32) -> Vec<&NoteInterval> {
+        let mut active = Vec::new();
+        self.visit_active(0, self.intervals.len(), t, &mut |interval| active.push(interval));
+        active
+    }
+
+    /// First onset strictly after `t`.
+    pub fn next_onset_after(&self, t: f32) -> Option<f32> {
+        let idx = self.intervals.partition_point(|interval| interval.onset <= t);
+        self.intervals.get(idx).map(|interval| interval.onset)
+    }
+
+    /// Intervals whose onset falls in `[t0, t1)`, in onset order.
+    pub fn onsets_in_window(&self, t0: f32, t1: f32) -> &[NoteInterval] {
+        let start = self.intervals.partition_point(|interval| interval.onset < t0);
+        let end = self
+            .intervals
+            .partition_point(|interval| interval.onset < t1)
+            .max(start);
+        &self.intervals[start..end]
+    }
+
+    fn visit_active<'a>(&'a self, lo: usize, hi: usize, t: f32, visit: &mut impl FnMut(&'a NoteInterval)) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_release[mid] <= t {
+            return;
+        }
+        self.visit_active(lo, mid, t, visit);
+        let interval = &self.intervals[mid];
+        if interval.onset <= t {
+            if t < interval.release {
+                visit(interval);
+            }
+            self.visit_active(mid + 1, hi, t, visit);
+        }
+    }
+}
+
+fn build_max_release(intervals: &[NoteInterval], max_release: &mut [f32], lo: usize, hi: usize) -> f32 {
+    if lo >= hi {
+        return f32::NEG_INFINITY;
+    }
+    let mid = lo + (hi - lo) / 2;
+    let left = build_max_release(intervals, max_release, lo, mid);
+    let right = build_max_release(intervals, max_release, mid + 1, hi);
+    max_release[mid] = intervals[mid].release.max(left).max(right);
+    max_release[mid]
+}
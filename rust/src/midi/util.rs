@@ -1,26 +1,143 @@
+use crate::midi::program::{
+    ChannelProgram, ChannelProgramState, ProgramMap, CC_BANK_SELECT_LSB, CC_BANK_SELECT_MSB, MIDI_CHANNEL_COUNT,
+};
 use hound::SampleFormat::Int;
 use hound::{WavSpec, WavWriter};
 use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
 use rustysynth::{SoundFont, Synthesizer, SynthesizerSettings};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::error::Error;
 use std::f32::consts::TAU;
+use std::fmt;
 use std::io::{stdout, Cursor, Write};
 use std::sync::Arc;
 
+pub const DEFAULT_US_PER_QN: f32 = 500_000_f32;
+pub const RENDER_BLOCK_SIZE: usize = 1024_usize;
+pub const TAIL_RELEASE_SECS: f32 = 2_f32;
+pub const CONTROL_CHANGE: i32 = 0xB0;
+pub const PROGRAM_CHANGE: i32 = 0xC0;
+pub const PITCH_BEND: i32 = 0xE0;
+pub const CC_SUSTAIN: u8 = 64;
+pub const SUSTAIN_ON_THRESHOLD: u8 = 64;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MidiNote {
     pub midi_note: u8,
     pub instrument_id: u8,
 }
 
+#[derive(Debug)]
+pub enum MidiError {
+    Parse(midly::Error),
+    InvalidTiming(Timing),
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiError::Parse(e) => write!(f, "failed to parse SMF: {}", e),
+            MidiError::InvalidTiming(timing) => write!(f, "invalid MIDI header timing: {:?}", timing),
+        }
+    }
+}
+
+impl Error for MidiError {}
+
+impl From<midly::Error> for MidiError {
+    fn from(e: midly::Error) -> Self {
+        MidiError::Parse(e)
+    }
+}
+
+/// How a tick in the SMF header maps onto wall-clock time.
+/// Metrical ticks depend on the current tempo, SMPTE timecode ticks are absolute.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TickClock {
+    Metrical { ticks_per_quarter: f32 },
+    Timecode { ticks_per_second: f32 },
+}
+
+impl TickClock {
+    pub fn from_timing(timing: Timing) -> Result<Self, MidiError> {
+        match timing {
+            Timing::Metrical(tpq) if tpq.as_int() > 0 => Ok(TickClock::Metrical {
+                ticks_per_quarter: tpq.as_int() as f32,
+            }),
+            Timing::Timecode(fps, ticks_per_frame) if ticks_per_frame > 0 => Ok(TickClock::Timecode {
+                ticks_per_second: fps.as_f32() * ticks_per_frame as f32,
+            }),
+            _ => Err(MidiError::InvalidTiming(timing)),
+        }
+    }
+
+    pub fn delta_secs(&self, delta_ticks: u32, us_per_qn: f32) -> f32 {
+        match *self {
+            TickClock::Metrical { ticks_per_quarter } => {
+                (delta_ticks as f32 / ticks_per_quarter) * (us_per_qn / 1_000_000_f32)
+            },
+            TickClock::Timecode { ticks_per_second } => delta_ticks as f32 / ticks_per_second,
+        }
+    }
+
+    /// Ticks per quarter note, SMPTE files are treated as if they ran at the default 120 bpm.
+    pub fn ticks_per_quarter(&self) -> u16 {
+        match *self {
+            TickClock::Metrical { ticks_per_quarter } => ticks_per_quarter as u16,
+            TickClock::Timecode { ticks_per_second } => {
+                (ticks_per_second * DEFAULT_US_PER_QN / 1_000_000_f32).round() as u16
+            },
+        }
+    }
+}
+
+/// Running tick -> seconds conversion over a tick-sorted event stream, tracking tempo changes.
+pub struct EventClock {
+    tick_clock: TickClock,
+    us_per_qn: f32,
+    last_tick: u32,
+    elapsed_secs: f64,
+}
+
+impl EventClock {
+    pub fn new(tick_clock: TickClock) -> Self {
+        Self {
+            tick_clock,
+            us_per_qn: DEFAULT_US_PER_QN,
+            last_tick: 0,
+            elapsed_secs: 0_f64,
+        }
+    }
+
+    pub fn from_smf(smf: &Smf) -> Result<Self, MidiError> {
+        Ok(Self::new(TickClock::from_timing(smf.header.timing)?))
+    }
+
+    pub fn advance(&mut self, tick: u32, event: &TrackEventKind<'_>) -> f32 {
+        let delta_ticks = tick - self.last_tick;
+        self.elapsed_secs += self.tick_clock.delta_secs(delta_ticks, self.us_per_qn) as f64;
+        self.last_tick = tick;
+        if let TrackEventKind::Meta(MetaMessage::Tempo(us)) = event {
+            self.us_per_qn = us.as_int() as f32; //TODO: idk what is best idiomatic to make these type casts clearer and intuitve
+        }
+        self.elapsed_secs as f32
+    }
+
+    pub fn elapsed_secs(&self) -> f64 {
+        self.elapsed_secs
+    }
+
+    pub fn sample_position(&self, sample_rate: i32) -> usize {
+        (self.elapsed_secs * sample_rate as f64).round() as usize
+    }
+}
+
 pub fn render_midi_to_wav_bytes(
     sample_rate: i32,
     channels: u16,
     midi_bytes: &[u8],
     sf2_bytes: &[u8],
-    target_channel: u8,
-    program: u8,
+    program_map: &ProgramMap,
 ) -> Result<Vec<u8>, Box<dyn Error>> {
     //TODO: the Box dyn is to allow for whatever hound Error or soundfont error or synth error i think
     let mut sf2_cursor = Cursor::new(sf2_bytes.to_vec());
@@ -28,46 +145,31 @@ pub fn render_midi_to_wav_bytes(
     let soundfont = Arc::new(sf);
     let mut synth = Synthesizer::new(&soundfont, &SynthesizerSettings::new(sample_rate))?;
     let smf = Smf::parse(midi_bytes)?;
-    let mut events = prepare_events(&smf);
-    events = inject_program_change(events, target_channel, program);
+    let events = prepare_events(&smf);
+    let mut programs = ChannelProgramState::new(program_map);
+    for (channel, program) in program_map.overrides() {
+        send_program(&mut synth, channel, program);
+    }
+    let mut clock = EventClock::from_smf(&smf)?;
     let mut samples = Vec::new();
-    let mut active_notes = HashSet::new();
-    let mut time_cursor = 0_f32;
-    let step_secs = 1_f32 / (sample_rate as f32);
-
-    process_midi_events_with_timing(events, &smf, |event_time, event, ch| {
-        while time_cursor < event_time {
-            samples.push(render_one_frame(&mut synth));
-            time_cursor += step_secs;
+    let mut rendered_frames = 0_usize;
+    for (tick, event) in events {
+        clock.advance(tick, &event);
+        let event_frame = clock.sample_position(sample_rate);
+        if event_frame > rendered_frames {
+            render_frames(&mut synth, event_frame - rendered_frames, &mut samples);
+            rendered_frames = event_frame;
         }
-        if let Some(channel) = ch {
-            if let TrackEventKind::Midi { message, .. } = event {
-                match message {
-                    MidiMessage::NoteOn { key, vel } => {
-                        let note = key.as_int();
-                        let velocity = vel.as_int();
-                        if velocity > 0 {
-                            synth.note_on(channel as i32, note as i32, velocity as i32);
-                            active_notes.insert((channel, note));
-                        } else {
-                            synth.note_off(channel as i32, note as i32);
-                            active_notes.remove(&(channel, note));
-                        }
-                    },
-                    MidiMessage::NoteOff { key, .. } => {
-                        let note = key.as_int();
-                        synth.note_off(channel as i32, note as i32);
-                        active_notes.remove(&(channel, note));
-                    },
-                    _ => {},
-                }
+        if let TrackEventKind::Midi { channel, message } = event {
+            if let Some(program) = programs.apply(channel.as_int(), &message) {
+                send_program(&mut synth, channel.as_int(), program);
             }
+            forward_midi_message(&mut synth, channel.as_int(), &message);
         }
-    });
-    while !active_notes.is_empty() {
-        samples.push(render_one_frame(&mut synth));
-        time_cursor += step_secs;
     }
+    synth.note_off_all(false);
+    let tail_frames = (TAIL_RELEASE_SECS * sample_rate as f32) as usize;
+    render_frames(&mut synth, tail_frames, &mut samples);
     Ok(write_samples_to_wav_bytes(sample_rate, channels, &samples)?)
 }
 
@@ -84,30 +186,77 @@ pub fn prepare_events(smf: &Smf) -> Vec<(u32, TrackEventKind<'static>)> {
     events
 }
 
-pub fn inject_program_change(
-    mut events: Vec<(u32, TrackEventKind<'static>)>,
-    channel: u8,
-    program: u8,
-) -> Vec<(u32, TrackEventKind<'static>)> {
-    let pc = TrackEventKind::Midi {
-        channel: midly::num::u4::from(channel),
-        message: MidiMessage::ProgramChange {
-            program: midly::num::u7::from(program),
+/// Forwards note, controller (sustain, volume, expression, modulation, pan, ...) and pitch bend
+/// messages to the synth. Bank select and program changes go through `send_program` instead.
+pub fn forward_midi_message(synth: &mut Synthesizer, channel: u8, message: &MidiMessage) {
+    let channel = channel as i32;
+    match *message {
+        MidiMessage::NoteOn { key, vel } => {
+            if vel.as_int() > 0 {
+                synth.note_on(channel, key.as_int() as i32, vel.as_int() as i32);
+            } else {
+                synth.note_off(channel, key.as_int() as i32);
+            }
         },
-    };
-    events.insert(0, (0, pc));
-    events
+        MidiMessage::NoteOff { key, .. } => {
+            synth.note_off(channel, key.as_int() as i32);
+        },
+        MidiMessage::Controller { controller, value } => {
+            let controller = controller.as_int();
+            if controller != CC_BANK_SELECT_MSB && controller != CC_BANK_SELECT_LSB {
+                synth.process_midi_message(channel, CONTROL_CHANGE, controller as i32, value.as_int() as i32);
+            }
+        },
+        MidiMessage::PitchBend { bend } => {
+            let raw = bend.0.as_int() as i32;
+            synth.process_midi_message(channel, PITCH_BEND, raw & 0x7F, raw >> 7);
+        },
+        _ => {},
+    }
 }
 
-pub fn render_one_frame(synth: &mut Synthesizer) -> (i16, i16) {
-    let mut left = [0_f32; 1];
-    let mut right = [0_f32; 1];
-    synth.render(&mut left, &mut right);
-    let l_i = (left[0].clamp(-1_f32, 1_f32) * i16::MAX as f32) as i16;
-    let r_i = (left[0].clamp(-1_f32, 1_f32) * i16::MAX as f32) as i16;
-    (l_i, r_i)
+pub fn send_program(synth: &mut Synthesizer, channel: u8, program: ChannelProgram) {
+    let channel = channel as i32;
+    synth.process_midi_message(
+        channel,
+        CONTROL_CHANGE,
+        CC_BANK_SELECT_MSB as i32,
+        program.bank_msb as i32,
+    );
+    synth.process_midi_message(
+        channel,
+        CONTROL_CHANGE,
+        CC_BANK_SELECT_LSB as i32,
+        program.bank_lsb as i32,
+    );
+    synth.process_midi_message(channel, PROGRAM_CHANGE, program.program as i32, 0);
 }
 
+/// Renders `frame_count` frames through the synth's block API, `RENDER_BLOCK_SIZE` frames at a time.
+pub fn render_frames(synth: &mut Synthesizer, frame_count: usize, samples: &mut Vec<(i16, i16)>) {
+    let mut left = [0_f32; RENDER_BLOCK_SIZE];
+    let mut right = [0_f32; RENDER_BLOCK_SIZE];
+    let mut remaining = frame_count;
+    samples.reserve(frame_count);
+    while remaining > 0 {
+        let block_len = remaining.min(RENDER_BLOCK_SIZE);
+        synth.render(&mut left[..block_len], &mut right[..block_len]);
+        for i in 0..block_len {
+            samples.push((sample_to_i16(left[i]), sample_to_i16(right[i])));
+        }
+        remaining -= block_len;
+    }
+}
+
+pub fn downmix_to_mono(left: i16, right: i16) -> i16 {
+    ((left as i32 + right as i32) / 2_i32) as i16
+}
+
+pub fn sample_to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1_f32, 1_f32) * i16::MAX as f32) as i16
+}
+
+/// Writes interleaved 16-bit PCM. Stereo keeps both channels, mono averages L/R into one downmixed channel.
 pub fn write_samples_to_wav_bytes(
     sample_rate: i32,
     channels: u16,
@@ -123,9 +272,11 @@ pub fn write_samples_to_wav_bytes(
     let mut cursor = Cursor::new(Vec::new());
     let mut writer = WavWriter::new(&mut cursor, spec)?;
     for &(left, right) in samples {
-        writer.write_sample(left)?;
         if channels == 2_u16 {
+            writer.write_sample(left)?;
             writer.write_sample(right)?;
+        } else {
+            writer.write_sample(downmix_to_mono(left, right))?;
         }
     }
     writer.finalize()?;
@@ -136,24 +287,10 @@ pub fn process_midi_events_with_timing(
     events: Vec<(u32, TrackEventKind<'static>)>,
     smf: &Smf,
     mut on_event: impl FnMut(f32, &TrackEventKind<'_>, Option<u8>),
-) {
-    let tpq = match smf.header.timing {
-        Timing::Metrical(t) => t.as_int(),
-        _ => panic!(),
-    };
-    let tpq_arithmetic = tpq as f32;
-    let mut us_per_qn = 500_000_f32;
-    let mut time_sec = 0_f32;
-    let mut last_tick = 0_u32;
+) -> Result<(), MidiError> {
+    let mut clock = EventClock::from_smf(smf)?;
     for (tick, event) in events {
-        let delta_ticks = tick - last_tick;
-        let delta_ticks_arithmetic = delta_ticks as f32;
-        let delta_secs = (delta_ticks_arithmetic / tpq_arithmetic) * (us_per_qn / 1_000_000_f32);
-        time_sec += delta_secs;
-        last_tick = tick;
-        if let TrackEventKind::Meta(MetaMessage::Tempo(us)) = &event {
-            us_per_qn = us.as_int() as f32; //TODO: idk what is best idiomatic to make these type casts clearer and intuitve
-        }
+        let time_sec = clock.advance(tick, &event);
         let channel = if let TrackEventKind::Midi { channel, .. } = event {
             Some(channel.as_int())
         } else {
@@ -161,69 +298,86 @@ pub fn process_midi_events_with_timing(
         };
         on_event(time_sec, &event, channel);
     }
+    Ok(())
 }
 
-fn inner_parse_note_on_off<T>(
-    midi_bytes: &[u8],
+// Note offs that arrive while the sustain pedal (CC64) is down are held back until the pedal lifts,
+// the same note is struck again, or the stream ends, so releases line up with what the synth plays.
+fn inner_parse_note_on_off<T: Copy>(
+    smf: &Smf,
+    program_map: &ProgramMap,
     mut time_fn: impl FnMut(u32, &TrackEventKind<'_>) -> T,
-    mut handle_note_fn: impl FnMut(u8, u8, u8, T, &[u8; 16]),
+    mut handle_note_fn: impl FnMut(u8, u8, u8, T, &ChannelProgramState),
 ) {
-    let smf = Smf::parse(midi_bytes).unwrap_or_else(|e| panic!("Failed to parse SMF from bytes: {}", e));
-    let mut current_instrument_for_channel = [0u8; 16];
-    let mut events: Vec<(u32, TrackEventKind<'static>)> = Vec::new();
-    for track in &smf.tracks {
-        let mut abs_tick = 0_u32;
-        for e in track {
-            abs_tick += e.delta.as_int();
-            events.push((abs_tick, e.kind.clone().to_static()));
-        }
-    }
-    events.sort_unstable_by_key(|(tick, _)| *tick);
-    for (tick, kind) in events {
+    let mut programs = ChannelProgramState::new(program_map);
+    let mut sustain_down = [false; MIDI_CHANNEL_COUNT];
+    let mut sustained_notes: [Vec<u8>; MIDI_CHANNEL_COUNT] = Default::default();
+    let mut last_time_value = None;
+    for (tick, kind) in prepare_events(smf) {
         let time_value = time_fn(tick, &kind);
+        last_time_value = Some(time_value);
         if let TrackEventKind::Midi { channel, message } = kind {
             let ch = channel.as_int();
+            let held = &mut sustained_notes[ch as usize];
+            programs.apply(ch, &message);
             match message {
-                MidiMessage::ProgramChange { program } => {
-                    current_instrument_for_channel[ch as usize] = program.as_int();
+                MidiMessage::Controller { controller, value } if controller.as_int() == CC_SUSTAIN => {
+                    let down = value.as_int() >= SUSTAIN_ON_THRESHOLD;
+                    if !down {
+                        for note in held.drain(..) {
+                            handle_note_fn(ch, note, 0, time_value, &programs);
+                        }
+                    }
+                    sustain_down[ch as usize] = down;
                 },
-                MidiMessage::NoteOn { key, vel } => {
-                    handle_note_fn(
-                        ch,
-                        key.as_int(),
-                        vel.as_int(),
-                        time_value,
-                        &current_instrument_for_channel,
-                    );
+                MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                    let note = key.as_int();
+                    if let Some(pos) = held.iter().position(|&n| n == note) {
+                        held.swap_remove(pos);
+                        handle_note_fn(ch, note, 0, time_value, &programs);
+                    }
+                    handle_note_fn(ch, note, vel.as_int(), time_value, &programs);
                 },
-                MidiMessage::NoteOff { key, .. } => {
-                    handle_note_fn(ch, key.as_int(), 0, time_value, &current_instrument_for_channel);
+                MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                    let note = key.as_int();
+                    if sustain_down[ch as usize] {
+                        if !held.contains(&note) {
+                            held.push(note);
+                        }
+                    } else {
+                        handle_note_fn(ch, note, 0, time_value, &programs);
+                    }
                 },
                 _ => {},
             }
         }
     }
+    if let Some(time_value) = last_time_value {
+        for (ch, held) in sustained_notes.iter_mut().enumerate() {
+            for note in held.drain(..) {
+                handle_note_fn(ch as u8, note, 0, time_value, &programs);
+            }
+        }
+    }
 }
 
 pub fn parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes(
     midi_bytes: &[u8],
-) -> HashMap<MidiNote, Vec<(u32, u32)>> {
-    let mut active_note_on: HashMap<(u8, u8), u32> = HashMap::new();
+    program_map: &ProgramMap,
+) -> Result<HashMap<MidiNote, Vec<(u32, u32)>>, MidiError> {
+    let mut active_note_on: HashMap<(u8, u8), (u32, u8)> = HashMap::new();
     let mut final_buffer: HashMap<MidiNote, Vec<(u32, u32)>> = HashMap::new();
-    let smf = Smf::parse(midi_bytes).unwrap();
-    let ticks_per_quarter = match smf.header.timing {
-        Timing::Metrical(tpq) => tpq.as_int(),
-        _ => panic!("Unsupported MIDI timing format"),
-    };
+    let smf = Smf::parse(midi_bytes)?;
+    let ticks_per_quarter = TickClock::from_timing(smf.header.timing)?.ticks_per_quarter();
     inner_parse_note_on_off(
-        midi_bytes,
+        &smf,
+        program_map,
         |tick, _kind| tick,
-        |ch, note, vel, tick_value, current_instr_table| {
+        |ch, note, vel, tick_value, programs| {
             let key = (ch, note);
             if vel > 0 {
-                active_note_on.insert(key, tick_value);
-            } else if let Some(onset_tick) = active_note_on.remove(&key) {
-                let instrument_id = current_instr_table[ch as usize];
+                active_note_on.insert(key, (tick_value, programs.program(ch).program));
+            } else if let Some((onset_tick, instrument_id)) = active_note_on.remove(&key) {
                 let midi_note = MidiNote {
                     midi_note: note,
                     instrument_id,
@@ -236,42 +390,26 @@ pub fn parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes(
         },
     );
     debug_midi_note_onset_buffer(&final_buffer, ticks_per_quarter);
-    final_buffer
+    Ok(final_buffer)
 }
 
 pub fn parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes(
     midi_bytes: &[u8],
-) -> HashMap<MidiNote, Vec<(f32, f32)>> {
-    let mut active_note_on: HashMap<(u8, u8), f32> = HashMap::new();
+    program_map: &ProgramMap,
+) -> Result<HashMap<MidiNote, Vec<(f32, f32)>>, MidiError> {
+    let mut active_note_on: HashMap<(u8, u8), (f32, u8)> = HashMap::new();
     let mut final_buffer: HashMap<MidiNote, Vec<(f32, f32)>> = HashMap::new();
-    let smf = Smf::parse(midi_bytes).unwrap();
-    let tpq = match smf.header.timing {
-        Timing::Metrical(tpq) => tpq.as_int() as f32,
-        _ => panic!("Unsupported MIDI timing format"),
-    };
+    let smf = Smf::parse(midi_bytes)?;
+    let mut clock = EventClock::from_smf(&smf)?;
     inner_parse_note_on_off(
-        midi_bytes,
-        {
-            let mut current_us_per_qn = 500_000_f32; // initial default microseconds per quarter note
-            let mut last_tick = 0_u32;
-            let mut elapsed_secs = 0_f32;
-            move |tick, kind| {
-                let delta_ticks = (tick - last_tick) as f32;
-                let delta_secs = (delta_ticks / tpq) * (current_us_per_qn / 1_000_000_f32);
-                elapsed_secs += delta_secs;
-                last_tick = tick;
-                if let TrackEventKind::Meta(MetaMessage::Tempo(us)) = kind {
-                    current_us_per_qn = us.as_int() as f32;
-                }
-                elapsed_secs
-            }
-        },
-        |ch, note, vel, time_value, current_instr_table| {
+        &smf,
+        program_map,
+        |tick, kind| clock.advance(tick, kind),
+        |ch, note, vel, time_value, programs| {
             let key = (ch, note);
             if vel > 0 {
-                active_note_on.insert(key, time_value);
-            } else if let Some(onset_sec) = active_note_on.remove(&key) {
-                let instrument_id = current_instr_table[ch as usize];
+                active_note_on.insert(key, (time_value, programs.program(ch).program));
+            } else if let Some((onset_sec, instrument_id)) = active_note_on.remove(&key) {
                 let midi_note = MidiNote {
                     midi_note: note,
                     instrument_id,
@@ -281,7 +419,52 @@ pub fn parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes(
         },
     );
 
-    final_buffer
+    Ok(final_buffer)
+}
+
+/// A single sounded note with the channel, program and velocity it was struck with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelNote {
+    pub channel: u8,
+    pub program: u8,
+    pub midi_note: u8,
+    pub velocity: u8,
+    pub onset: f32,
+    pub release: f32,
+}
+
+/// Like `parse_midi_events_into_note_on_off_event_buffer_seconds_from_bytes` but keeps channel and velocity,
+/// sorted by onset.
+pub fn parse_midi_events_into_channel_notes_from_bytes(
+    midi_bytes: &[u8],
+    program_map: &ProgramMap,
+) -> Result<Vec<ChannelNote>, MidiError> {
+    let mut active_note_on: HashMap<(u8, u8), (f32, u8, u8)> = HashMap::new();
+    let mut notes = Vec::new();
+    let smf = Smf::parse(midi_bytes)?;
+    let mut clock = EventClock::from_smf(&smf)?;
+    inner_parse_note_on_off(
+        &smf,
+        program_map,
+        |tick, kind| clock.advance(tick, kind),
+        |ch, note, vel, time_value, programs| {
+            let key = (ch, note);
+            if vel > 0 {
+                active_note_on.insert(key, (time_value, vel, programs.program(ch).program));
+            } else if let Some((onset, velocity, program)) = active_note_on.remove(&key) {
+                notes.push(ChannelNote {
+                    channel: ch,
+                    program,
+                    midi_note: note,
+                    velocity,
+                    onset,
+                    release: time_value,
+                });
+            }
+        },
+    );
+    notes.sort_by(|a, b| a.onset.total_cmp(&b.onset).then(a.midi_note.cmp(&b.midi_note)));
+    Ok(notes)
 }
 
 pub fn debug_midi_note_onset_buffer(buffer: &HashMap<MidiNote, Vec<(u32, u32)>>, ticks_per_quarter: u16) {
@@ -0,0 +1,218 @@
+use crate::midi::program::MIDI_CHANNEL_COUNT;
+use crate::midi::util::MidiNote;
+use midly::num::{u15, u24, u28, u4, u7};
+use midly::{Format, Header, MetaMessage, MidiMessage, Smf, Timing, Track, TrackEvent, TrackEventKind};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+pub const DEFAULT_VELOCITY: u8 = 100;
+const DRUM_CHANNEL: u8 = 9;
+
+/// Writes a tick based note buffer (as returned by
+/// `parse_midi_events_into_note_on_off_event_buffer_ticks_from_bytes`) back out as a Standard MIDI File.
+/// `Format::SingleTrack` puts everything in one track, `Format::Parallel` puts the tempo in a conductor track
+/// followed by one track per instrument. Each program gets its own channel, so more than 15 programs, a
+/// tempo outside what a tempo event can hold, a ticks per quarter of 0 or above 32767, a gap between events
+/// longer than a delta time can span or `Format::Sequential` are rejected with `InvalidInput`.
+pub fn write_note_buffer_ticks_to_smf_bytes(
+    buffer: &HashMap<MidiNote, Vec<(u32, u32)>>,
+    ticks_per_quarter: u16,
+    bpm: f32,
+    format: Format,
+) -> io::Result<Vec<u8>> {
+    if format == Format::Sequential {
+        return Err(invalid_input(
+            "sequential (format 2) files are not supported".to_string(),
+        ));
+    }
+    if !(bpm.is_finite() && bpm > 0_f32) {
+        return Err(invalid_input(format!("{} bpm is not a positive tempo", bpm)));
+    }
+    let timing = u15::try_from(ticks_per_quarter)
+        .filter(|tpq| tpq.as_int() > 0)
+        .ok_or_else(|| {
+            invalid_input(format!(
+                "{} ticks per quarter does not fit a MIDI header",
+                ticks_per_quarter
+            ))
+        })?;
+    let us_per_qn = (60_000_000_f32 / bpm).round() as u32;
+    let tempo = u24::try_from(us_per_qn)
+        .ok_or_else(|| invalid_input(format!("{} bpm does not fit a MIDI tempo event", bpm)))?;
+    let channels = assign_channels(buffer.keys())?;
+    let mut tempo_track = vec![(0_u32, TrackEventKind::Meta(MetaMessage::Tempo(tempo)))];
+    let mut instrument_tracks: Vec<Vec<(u32, TrackEventKind<'static>)>> = Vec::new();
+    for &(instrument_id, channel) in &channels {
+        let mut events = vec![(
+            0_u32,
+            TrackEventKind::Midi {
+                channel: u4::new(channel),
+                message: MidiMessage::ProgramChange {
+                    program: u7::new(instrument_id),
+                },
+            },
+        )];
+        for (note, segments) in buffer.iter().filter(|(note, _)| note.instrument_id == instrument_id) {
+            for &(onset, release) in segments {
+                events.push((onset, note_event(channel, note.midi_note, DEFAULT_VELOCITY)));
+                events.push((release.max(onset), note_event(channel, note.midi_note, 0)));
+            }
+        }
+        instrument_tracks.push(events);
+    }
+
+    let tracks = match format {
+        Format::SingleTrack => {
+            for events in instrument_tracks {
+                tempo_track.extend(events);
+            }
+            vec![into_track(tempo_track)?]
+        },
+        Format::Parallel => {
+            let mut tracks = vec![into_track(tempo_track)?];
+            for events in instrument_tracks {
+                tracks.push(into_track(events)?);
+            }
+            tracks
+        },
+        Format::Sequential => {
+            return Err(invalid_input(
+                "sequential (format 2) files are not supported".to_string(),
+            ));
+        },
+    };
+    let header = Header::new(format, Timing::Metrical(timing));
+    let smf = Smf { header, tracks };
+    let mut bytes = Vec::new();
+    smf.write_std(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Seconds based variant, onsets and releases are quantized to the tick grid implied by `bpm`
+/// and `ticks_per_quarter`.
+pub fn write_note_buffer_seconds_to_smf_bytes(
+    buffer: &HashMap<MidiNote, Vec<(f32, f32)>>,
+    ticks_per_quarter: u16,
+    bpm: f32,
+    format: Format,
+) -> io::Result<Vec<u8>> {
+    let ticks_per_second = ticks_per_quarter as f32 * bpm / 60_f32;
+    let to_tick = |secs: f32| (secs.max(0_f32) * ticks_per_second).round() as u32;
+    let tick_buffer: HashMap<MidiNote, Vec<(u32, u32)>> = buffer
+        .iter()
+        .map(|(note, segments)| {
+            let ticks = segments
+                .iter()
+                .map(|&(onset, release)| (to_tick(onset), to_tick(release)))
+                .collect();
+            (note.clone(), ticks)
+        })
+        .collect();
+    write_note_buffer_ticks_to_smf_bytes(&tick_buffer, ticks_per_quarter, bpm, format)
+}
+
+/// Collects notes played live (e.g. from the `midi::debug` keyboard) into a seconds based note buffer.
+#[derive(Default)]
+pub struct NoteRecorder {
+    pub instrument_id: u8,
+    held: HashMap<u8, f32>,
+    buffer: HashMap<MidiNote, Vec<(f32, f32)>>,
+}
+
+impl NoteRecorder {
+    pub fn press(&mut self, midi_note: u8, time_secs: f32) {
+        self.held.entry(midi_note).or_insert(time_secs);
+    }
+
+    pub fn release(&mut self, midi_note: u8, time_secs: f32) {
+        if let Some(onset) = self.held.remove(&midi_note) {
+            let key = MidiNote {
+                midi_note,
+                instrument_id: self.instrument_id,
+            };
+            self.buffer.entry(key).or_default().push((onset, time_secs));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty() && self.held.is_empty()
+    }
+
+    /// Releases anything still held at `time_secs` and hands back the recorded buffer.
+    pub fn finish(mut self, time_secs: f32) -> HashMap<MidiNote, Vec<(f32, f32)>> {
+        let held: Vec<u8> = self.held.keys().copied().collect();
+        for midi_note in held {
+            self.release(midi_note, time_secs);
+        }
+        self.buffer
+    }
+}
+
+fn assign_channels<'a>(notes: impl Iterator<Item = &'a MidiNote>) -> io::Result<Vec<(u8, u8)>> {
+    let programs: BTreeSet<u8> = notes.map(|note| note.instrument_id).collect();
+    let melodic_channels: Vec<u8> = (0_u8..MIDI_CHANNEL_COUNT as u8)
+        .filter(|&channel| channel != DRUM_CHANNEL)
+        .collect();
+    if programs.len() > melodic_channels.len() {
+        return Err(invalid_input(format!(
+            "{} programs but only {} melodic channels",
+            programs.len(),
+            melodic_channels.len()
+        )));
+    }
+    Ok(programs.into_iter().zip(melodic_channels).collect())
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn note_event(channel: u8, key: u8, vel: u8) -> TrackEventKind<'static> {
+    let message = if vel > 0 {
+        MidiMessage::NoteOn {
+            key: u7::new(key),
+            vel: u7::new(vel),
+        }
+    } else {
+        MidiMessage::NoteOff {
+            key: u7::new(key),
+            vel: u7::new(0),
+        }
+    };
+    TrackEventKind::Midi {
+        channel: u4::new(channel),
+        message,
+    }
+}
+
+// Sorts by tick with note offs ahead of everything else on the same tick so re-struck notes survive,
+// then converts absolute ticks into deltas and terminates the track.
+fn into_track(mut events: Vec<(u32, TrackEventKind<'static>)>) -> io::Result<Track<'static>> {
+    events.sort_by_key(|(tick, kind)| {
+        let is_note_off = matches!(
+            kind,
+            TrackEventKind::Midi {
+                message: MidiMessage::NoteOff { .. },
+                ..
+            }
+        );
+        (*tick, !is_note_off)
+    });
+    let mut track = Vec::with_capacity(events.len() + 1);
+    let mut last_tick = 0_u32;
+    for (tick, kind) in events {
+        let delta = u28::try_from(tick - last_tick).ok_or_else(|| {
+            invalid_input(format!(
+                "{} ticks between events do not fit a delta time",
+                tick - last_tick
+            ))
+        })?;
+        track.push(TrackEvent { delta, kind });
+        last_tick = tick;
+    }
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    Ok(track)
+}
@@ -175,7 +175,6 @@ impl Renderer for RaylibRenderer {
     }
     fn set_uniform_int(&mut self, shader: &mut Self::Shader, uniform_name: &str, value: i32) {
         let location = shader.get_shader_location(uniform_name);
-        println!("{} uniform location = {}", uniform_name, location);
         shader.set_shader_value(location, value);
     }
 
@@ -1,80 +1,109 @@
 use crate::godot_nodes::audio::audio_bus::AudioBus;
 use crate::godot_nodes::audio::audio_bus::BUS::MUSIC;
 use crate::sound_render::sound_renderer::{
-    FFTTexture, WaveformTexture, BUFFER_SIZE, DEAD_CHANNEL, FFT_ROW, HZ_STEP, INVERSE_DECIBEL_RANGE,
-    MDN_MIN_AUDIO_DECIBEL, TEXTURE_HEIGHT,
+    FFTTexture, WaveformTexture, BUFFER_SIZE, DEAD_CHANNEL, FFT_ROW, TEXTURE_HEIGHT,
 };
-use crate::sound_render::util::compute_smooth_energy;
+use crate::sound_render::spectrogram::SpectrogramRing;
+use crate::sound_render::spectrum_analyzer::SpectrumAnalyzer;
+use crate::sound_render::spectrum_bands::{BandLayout, BandScale};
 use godot::builtin::PackedFloat32Array;
-use godot::classes::audio_effect_spectrum_analyzer_instance::MagnitudeMode;
 use godot::classes::image::Format;
-use godot::classes::{AudioEffectCapture, AudioEffectSpectrumAnalyzerInstance, AudioServer, Image, Node};
-use godot::global::linear_to_db;
-use godot::obj::{Base, Gd, NewGd, WithBaseField};
+use godot::classes::{AudioEffectCapture, AudioServer, Image, Node};
+use godot::obj::{Base, Gd, NewGd};
 use godot::prelude::{Color, GodotClass};
 
 #[derive(GodotClass)]
 #[class(init, base=Node)]
 pub struct GodotFFTTexture {
     base: Base<Node>,
+    analyzer: SpectrumAnalyzer,
+    bands: BandLayout,
+    spectrogram: Option<SpectrogramRing>,
+    capture: Option<Gd<AudioEffectCapture>>,
+}
+
+impl GodotFFTTexture {
+    pub fn analyzer_mut(&mut self) -> &mut SpectrumAnalyzer {
+        &mut self.analyzer
+    }
+
+    pub fn bands(&self) -> &BandLayout {
+        &self.bands
+    }
+
+    /// Takes effect on the next update; the texture has to be recreated with `init_audio_texture` when the
+    /// band count changes.
+    pub fn set_band_layout(&mut self, scale: BandScale, band_count: usize) {
+        self.bands = BandLayout::for_analyzer(scale, band_count, &self.analyzer);
+    }
+
+    pub fn spectrogram(&self) -> Option<&SpectrogramRing> {
+        self.spectrogram.as_ref()
+    }
+
+    /// A spectrogram texture of `rows` rows, one per analysis, or the single spectrum row for 0. Recreate the
+    /// texture with `init_audio_texture` afterwards.
+    pub fn set_spectrogram_rows(&mut self, rows: usize) {
+        self.spectrogram = (rows > 0).then(|| SpectrogramRing::new(rows));
+    }
 }
 
 impl FFTTexture for GodotFFTTexture {
     type Image = Gd<Image>;
     type FFTData = PackedFloat32Array;
-    type AudioEffect = Gd<AudioEffectSpectrumAnalyzerInstance>;
+    type AudioEffect = Gd<AudioEffectCapture>;
 
     fn resize_buffer(&mut self, fft_data: &mut PackedFloat32Array) {
         fft_data.resize(BUFFER_SIZE);
     }
 
     fn init_audio_texture(&mut self) -> Self::Image {
-        Image::create_empty(BUFFER_SIZE as i32, TEXTURE_HEIGHT, false, Format::RGBA8).unwrap()
+        let height = self.spectrogram.map_or(TEXTURE_HEIGHT, |ring| ring.rows() as i32);
+        Image::create_empty(self.bands.band_count() as i32, height, false, Format::RGBA8).unwrap()
     }
 
+    /// Captures the music bus PCM so the spectrum goes through the same `SpectrumAnalyzer` as the raylib
+    /// backend instead of Godot's own spectrum effect.
     fn fetch_spectrum_analyzer(&mut self) -> Self::AudioEffect {
-        let scene_tree = self.base().get_tree().unwrap();
-        let root_window = scene_tree.get_root().unwrap();
-        let music_dimensions_manager = root_window.get_node_as::<Node>("MusicDimensionsManager");
-        let spectrum_analyzer = music_dimensions_manager
-            .get("spectrum_analyzer_instance")
-            .try_to::<Self::AudioEffect>()
-            .unwrap();
-        spectrum_analyzer
-
-        // TODO: I need to be done with singletons, but this is sitll fucking singletons in the core godot design, im done with this, i cant deal with it, its too much
-        // let mut spectrum_analyzer_effect = AudioEffectSpectrumAnalyzer::new_gd();
-        // spectrum_analyzer_effect.set_fft_size(FftSize::SIZE_2048);
-        // AudioServer::singleton().add_bus_effect(AudioBus::get_bus_index_rust(MUSIC), &spectrum_analyzer_effect);
-        // let effect_index = AudioServer::singleton().get_bus_effect_count(AudioBus::get_bus_index_rust(MUSIC)) - 1;
-        // AudioServer::singleton()
-        //     .get_bus_effect_instance(AudioBus::get_bus_index_rust(MUSIC), effect_index)
-        //     .unwrap()
-        //     .cast()
+        let capture = AudioEffectCapture::new_gd();
+        let mut audio_server: Gd<AudioServer> = AudioServer::singleton();
+        audio_server.add_bus_effect(AudioBus::get_bus_index_rust(MUSIC), &capture);
+        self.analyzer.sample_rate = audio_server.get_mix_rate();
+        self.set_band_layout(self.bands.scale(), self.bands.band_count());
+        self.capture = Some(capture.clone());
+        capture
     }
 
+    /// Runs the captured audio through `SpectrumAnalyzer::process`, so the analyzer's hop size decides how
+    /// often a spectrum is drawn. Every analysis gets its own spectrogram row; the single spectrum row keeps
+    /// the newest.
     fn update_audio_texture(&mut self, fft_data: &mut Self::FFTData, audio_texture: &mut Self::Image) {
-        let fft_data_slice = fft_data.as_mut_slice();
-        for bin_index in 0..BUFFER_SIZE {
-            let bin_index_f = bin_index as f32;
-            let from_hz = bin_index_f * HZ_STEP;
-            let to_hz = (bin_index_f + 1.0) * HZ_STEP;
-            // http://github.com/godotengine/godot/blob/master/servers/audio/effects/audio_effect_spectrum_analyzer.cpp
-            let spectrum = self.fetch_spectrum_analyzer();
-            let stereo_magnitude = spectrum
-                .get_magnitude_for_frequency_range_ex(from_hz, to_hz)
-                .mode(MagnitudeMode::AVERAGE)
-                .done();
-
-            let linear_magnitude = (stereo_magnitude.x + stereo_magnitude.y) / 2_f32;
-            let db = linear_to_db(linear_magnitude as f64) as f32;
-            let normalized = ((db - MDN_MIN_AUDIO_DECIBEL) * INVERSE_DECIBEL_RANGE).clamp(0_f32, 1_f32);
-            let previous_smooth_energy = fft_data_slice[bin_index];
-            let smooth_energy = compute_smooth_energy(previous_smooth_energy, normalized);
-            fft_data_slice[bin_index] = smooth_energy;
-            let color = Color::from_rgba(smooth_energy, DEAD_CHANNEL, DEAD_CHANNEL, DEAD_CHANNEL);
-            audio_texture.set_pixel(bin_index as i32, FFT_ROW, color);
+        let Some(capture) = self.capture.as_mut() else {
+            return;
+        };
+        let frames = capture.get_frames_available();
+        if frames == 0 {
+            return;
         }
+        let stereo_frames = capture.get_buffer(frames);
+        let mono: Vec<f32> = stereo_frames
+            .as_slice()
+            .iter()
+            .map(|frame| (frame.x + frame.y) / 2_f32)
+            .collect();
+        let bands = &self.bands;
+        let spectrogram = &mut self.spectrogram;
+        let fft_data_slice = fft_data.as_mut_slice();
+        let mut band_energies = vec![0_f32; bands.band_count()];
+        self.analyzer.process(&mono, |analyzer| {
+            let row = spectrogram.as_mut().map_or(FFT_ROW, |ring| ring.advance() as i32);
+            analyzer.normalized_frequency_data(fft_data_slice);
+            bands.apply(fft_data_slice, &mut band_energies);
+            for (bin_index, &smooth_energy) in band_energies.iter().enumerate() {
+                let color = Color::from_rgba(smooth_energy, DEAD_CHANNEL, DEAD_CHANNEL, DEAD_CHANNEL);
+                audio_texture.set_pixel(bin_index as i32, row, color);
+            }
+        });
     }
 }
 
@@ -5,4 +5,7 @@ pub mod godot;
 pub mod raylib;
 
 pub mod sound_renderer;
-mod util;
+pub mod spectrogram;
+pub mod spectrum_analyzer;
+pub mod spectrum_bands;
+pub mod spectrum_history;
@@ -20,6 +20,7 @@ pub const HALF_SAMPLE_RATE: f32 = SAMPLE_RATE / 2_f32;
 pub const HZ_STEP: f32 = HALF_SAMPLE_RATE / MDN_BINS_F;
 pub const MDN_MIN_AUDIO_DECIBEL: f32 = -100.0; //match WebAudio defaults
 pub const MDN_MAX_AUDIO_DECIBEL: f32 = -30.0; //match WebAudio defaults
+pub const MDN_SMOOTHING: f32 = 0.8; //match WebAudio defaults
 pub const INVERSE_DECIBEL_RANGE: f32 = 1_f32 / (MDN_MAX_AUDIO_DECIBEL - MDN_MIN_AUDIO_DECIBEL);
 
 pub const K: f64 = 20_f64 / std::f64::consts::LN_10;
@@ -0,0 +1,89 @@
+use crate::sound_render::spectrum_history::SpectrumHistory;
+
+pub const DEFAULT_SPECTROGRAM_ROWS: usize = 256;
+/// Shader uniform holding `SpectrogramRing::write_row`.
+pub const WRITE_ROW_UNIFORM: &str = "writeRow";
+
+/// Row bookkeeping for a spectrogram (waterfall) texture: one analysis frame per row, written round robin
+/// so only one row is touched per frame. Shaders get `write_row` as the `writeRow` uniform and find the
+/// frame `age` rows old at row `(writeRow - age + rows) % rows`, the same as `row_for_age`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrogramRing {
+    rows: usize,
+    write_row: usize,
+    len: usize,
+    /// Timestamp of the newest history entry written by `take_new`.
+    written_secs: f64,
+}
+
+impl Default for SpectrogramRing {
+    fn default() -> Self {
+        Self::new(DEFAULT_SPECTROGRAM_ROWS)
+    }
+}
+
+impl SpectrogramRing {
+    pub fn new(rows: usize) -> Self {
+        let rows = rows.max(1);
+        Self {
+            rows,
+            write_row: rows - 1,
+            len: 0,
+            written_secs: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Texture height.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// The row holding the newest frame.
+    pub fn write_row(&self) -> usize {
+        self.write_row
+    }
+
+    /// Rows written so far, up to `rows()`; the rest of the texture is still blank.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Moves on to the next row and returns it; write the new frame there.
+    pub fn advance(&mut self) -> usize {
+        self.write_row = (self.write_row + 1) % self.rows;
+        self.len = (self.len + 1).min(self.rows);
+        self.write_row
+    }
+
+    /// Row of the frame `age` frames older than the newest, None if it was never written or is overwritten.
+    pub fn row_for_age(&self, age: usize) -> Option<usize> {
+        (age < self.len).then(|| (self.write_row + self.rows - age) % self.rows)
+    }
+
+    /// Advances over every spectrum of `history` newer than the last call and stamped no later than
+    /// `until_secs`, oldest first, returning each with the row to write it to. Frames that would be
+    /// overwritten within the same call are skipped.
+    pub fn take_new<'a>(&mut self, history: &'a SpectrumHistory, until_secs: f64) -> Vec<(usize, &'a [f32])> {
+        let new: Vec<(f64, &[f32])> = (0..history.len())
+            .filter_map(|age| history.get(age))
+            .skip_while(|&(time, _)| time > until_secs)
+            .take_while(|&(time, _)| time > self.written_secs)
+            .take(self.rows)
+            .collect();
+        if let Some(&(newest, _)) = new.first() {
+            self.written_secs = newest;
+        }
+        new.into_iter()
+            .rev()
+            .map(|(_, spectrum)| (self.advance(), spectrum))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new(self.rows);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::audio_analysis::fft::{fft_in_place, WindowFunction};
+use crate::sound_render::sound_renderer::{
+    FFT_WINDOW_SIZE, MDN_MAX_AUDIO_DECIBEL, MDN_MIN_AUDIO_DECIBEL, MDN_SMOOTHING, SAMPLE_RATE,
+};
+
+/// Backend independent port of the WebAudio AnalyserNode frequency analysis
+/// (https://webaudio.github.io/web-audio-api/#fft-windowing-and-smoothing-over-time): the newest `fft_size`
+/// samples are windowed, transformed and scaled by 1/N, the magnitudes smoothed over time with
+/// `smoothing_time_constant`, then converted to decibels and mapped onto `min_decibels..max_decibels`.
+/// Push PCM with `push_samples`, call `analyze` once per frame and read the bins with the `*_frequency_data`
+/// getters, exactly like `getByteFrequencyData` in the browser. For a fixed analysis rate instead, feed new
+/// samples through `process`, which analyses once every `hop_size` samples.
+///
+/// The window defaults to the spec's Blackman; other windows trade main lobe width for leakage.
+#[derive(Debug, Clone)]
+pub struct SpectrumAnalyzer {
+    pub sample_rate: f32,
+    pub smoothing_time_constant: f32,
+    pub min_decibels: f32,
+    pub max_decibels: f32,
+    fft_size: usize,
+    window_function: WindowFunction,
+    window: Vec<f32>,
+    hop_size: usize,
+    /// Samples pushed through `process` since its last analysis.
+    pending: usize,
+    /// Samples pushed since creation or the last `set_sample_position`.
+    sample_position: u64,
+    /// The newest `fft_size` samples, oldest first.
+    time_domain: Vec<f32>,
+    /// Smoothed linear magnitudes of bins `0..fft_size / 2`.
+    smoothed: Vec<f32>,
+    re: Vec<f32>,
+    im: Vec<f32>,
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self {
+        Self::new(FFT_WINDOW_SIZE, SAMPLE_RATE)
+    }
+}
+
+impl SpectrumAnalyzer {
+    /// `fft_size` must be a power of two. Smoothing and the decibel range start at the WebAudio defaults.
+    pub fn new(fft_size: usize, sample_rate: f32) -> Self {
+        assert!(fft_size.is_power_of_two(), "fft size must be a power of two");
+        let window_function = WindowFunction::default();
+        Self {
+            sample_rate,
+            smoothing_time_constant: MDN_SMOOTHING,
+            min_decibels: MDN_MIN_AUDIO_DECIBEL,
+            max_decibels: MDN_MAX_AUDIO_DECIBEL,
+            fft_size,
+            window_function,
+            window: window_function.generate(fft_size),
+            hop_size: fft_size,
+            pending: 0,
+            sample_position: 0,
+            time_domain: vec![0_f32; fft_size],
+            smoothed: vec![0_f32; fft_size / 2],
+            re: vec![0_f32; fft_size],
+            im: vec![0_f32; fft_size],
+        }
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    /// `frequencyBinCount`: half the FFT size.
+    pub fn frequency_bin_count(&self) -> usize {
+        self.fft_size / 2
+    }
+
+    pub fn window_function(&self) -> WindowFunction {
+        self.window_function
+    }
+
+    pub fn set_window_function(&mut self, window_function: WindowFunction) {
+        self.window_function = window_function;
+        self.window = window_function.generate(self.fft_size);
+    }
+
+    pub fn hop_size(&self) -> usize {
+        self.hop_size
+    }
+
+    /// New samples between analyses in `process`, clamped to `1..=fft_size`. Half the FFT size is the usual
+    /// 50% overlap; the default, the full size, analyses each sample once.
+    pub fn set_hop_size(&mut self, hop_size: usize) {
+        self.hop_size = hop_size.clamp(1, self.fft_size);
+        self.pending = self.pending.min(self.hop_size - 1);
+    }
+
+    pub fn bin_frequency(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate / self.fft_size as f32
+    }
+
+    /// Stream position of the newest pushed sample, counting every sample ever pushed.
+    pub fn sample_position(&self) -> u64 {
+        self.sample_position
+    }
+
+    /// Moves the stream position, e.g. after seeking. Usually paired with `reset`.
+    pub fn set_sample_position(&mut self, sample_position: u64) {
+        self.sample_position = sample_position;
+    }
+
+    pub fn position_secs(&self) -> f64 {
+        self.sample_position as f64 / self.sample_rate as f64
+    }
+
+    /// Appends mono samples in [-1, 1]; only the newest `fft_size` are kept.
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        self.sample_position += samples.len() as u64;
+        let n = self.fft_size;
+        if samples.len() >= n {
+            self.time_domain.copy_from_slice(&samples[samples.len() - n..]);
+        } else {
+            self.time_domain.rotate_left(samples.len());
+            self.time_domain[n - samples.len()..].copy_from_slice(samples);
+        }
+    }
+
+    /// Streams new samples in, running `analyze` and then `on_analysis` each time another `hop_size` of them
+    /// has arrived. Returns how many analyses ran.
+    pub fn process(&mut self, samples: &[f32], mut on_analysis: impl FnMut(&Self)) -> usize {
+        let mut analyses = 0;
+        let mut rest = samples;
+        while !rest.is_empty() {
+            let take = (self.hop_size - self.pending).min(rest.len());
+            self.push_samples(&rest[..take]);
+            self.pending += take;
+            rest = &rest[take..];
+            if self.pending == self.hop_size {
+                self.pending = 0;
+                self.analyze();
+                on_analysis(self);
+                analyses += 1;
+            }
+        }
+        analyses
+    }
+
+    /// Runs one analysis over the current samples and folds it into the smoothed magnitudes.
+    pub fn analyze(&mut self) {
+        for ((re, sample), window) in self.re.iter_mut().zip(&self.time_domain).zip(&self.window) {
+            *re = sample * window;
+        }
+        self.im.fill(0_f32);
+        fft_in_place(&mut self.re, &mut self.im);
+        let scale = 1_f32 / self.fft_size as f32;
+        let tau = self.smoothing_time_constant;
+        for (k, smoothed) in self.smoothed.iter_mut().enumerate() {
+            let magnitude = (self.re[k] * self.re[k] + self.im[k] * self.im[k]).sqrt() * scale;
+            let next = tau * *smoothed + (1_f32 - tau) * magnitude;
+            // The spec resets a bin whose history has gone non-finite instead of letting it stick.
+            *smoothed = if next.is_finite() { next } else { 0_f32 };
+        }
+    }
+
+    /// Forgets the buffered samples and the smoothing history. The sample position keeps counting.
+    pub fn reset(&mut self) {
+        self.time_domain.fill(0_f32);
+        self.smoothed.fill(0_f32);
+        self.pending = 0;
+    }
+
+    /// `getFloatFrequencyData`: smoothed magnitudes in decibels, negative infinity for silent bins.
+    pub fn float_frequency_data(&self, out: &mut [f32]) {
+        for (out, &magnitude) in out.iter_mut().zip(&self.smoothed) {
+            *out = 20_f32 * magnitude.log10();
+        }
+    }
+
+    /// `getByteFrequencyData`: decibels mapped so `min_decibels` is 0 and `max_decibels` is 255.
+    pub fn byte_frequency_data(&self, out: &mut [u8]) {
+        let scale = u8::MAX as f32 / (self.max_decibels - self.min_decibels);
+        for (out, &magnitude) in out.iter_mut().zip(&self.smoothed) {
+            let db = 20_f32 * magnitude.log10();
+            *out = (scale * (db - self.min_decibels)).floor().clamp(0_f32, u8::MAX as f32) as u8;
+        }
+    }
+
+    /// The byte mapping as unquantised floats in [0, 1], for textures that store floats.
+    pub fn normalized_frequency_data(&self, out: &mut [f32]) {
+        let inverse_range = 1_f32 / (self.max_decibels - self.min_decibels);
+        for (out, &magnitude) in out.iter_mut().zip(&self.smoothed) {
+            let db = 20_f32 * magnitude.log10();
+            *out = ((db - self.min_decibels) * inverse_range).clamp(0_f32, 1_f32);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::sound_render::sound_renderer::{BUFFER_SIZE, SAMPLE_RATE};
+use crate::sound_render::spectrum_analyzer::SpectrumAnalyzer;
+
+/// Lowest band edge of the non-linear scales; below it a 1024 point FFT has nothing to resolve anyway.
+pub const DEFAULT_MIN_BAND_HZ: f32 = 20.0;
+const OCTAVE_REFERENCE_HZ: f32 = 1000.0;
+// In bins, so an edge computed a rounding error past a bin's frequency still includes that bin.
+const EDGE_TOLERANCE: f32 = 1e-3;
+
+/// How texels are spread over frequency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BandScale {
+    /// Equal frequency steps. With as many bands as bins these are the plain FFT bins.
+    #[default]
+    Linear,
+    /// Equal frequency ratios per texel.
+    Logarithmic,
+    /// Equal steps on the mel scale (O'Shaughnessy, 2595 log10(1 + f / 700)).
+    Mel,
+    /// Equal steps on the bark scale (Traunmüller's approximation).
+    Bark,
+    /// Fractional octave bands centred on the 1 kHz grid, as many per octave as fit the band count.
+    Octave,
+}
+
+impl BandScale {
+    pub const ALL: [BandScale; 5] = [
+        BandScale::Linear,
+        BandScale::Logarithmic,
+        BandScale::Mel,
+        BandScale::Bark,
+        BandScale::Octave,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BandScale::Linear => "linear",
+            BandScale::Logarithmic => "logarithmic",
+            BandScale::Mel => "mel",
+            BandScale::Bark => "bark",
+            BandScale::Octave => "octave",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scale| scale.name() == name)
+    }
+
+    // Hz onto an axis the scale spaces evenly; `unwarp` goes back.
+    fn warp(self, hz: f32) -> f32 {
+        match self {
+            BandScale::Linear => hz,
+            BandScale::Logarithmic | BandScale::Octave => hz.log2(),
+            BandScale::Mel => 2595_f32 * (1_f32 + hz / 700_f32).log10(),
+            BandScale::Bark => 26.81_f32 * hz / (1960_f32 + hz) - 0.53_f32,
+        }
+    }
+
+    fn unwarp(self, value: f32) -> f32 {
+        match self {
+            BandScale::Linear => value,
+            BandScale::Logarithmic | BandScale::Octave => value.exp2(),
+            BandScale::Mel => 700_f32 * (10_f32.powf(value / 2595_f32) - 1_f32),
+            BandScale::Bark => 1960_f32 * (value + 0.53_f32) / (26.28_f32 - value),
+        }
+    }
+}
+
+/// Maps the linear bins of a spectrum onto texels. Each band takes the loudest bin whose frequency falls
+/// inside it; bands narrower than a bin interpolate the spectrum at their center instead, so the bass is
+/// spread smoothly over many texels rather than drawn as steps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandLayout {
+    scale: BandScale,
+    bin_count: usize,
+    bin_hz: f32,
+    /// `band_count + 1` ascending band edges in Hz.
+    edges: Vec<f32>,
+    centers: Vec<f32>,
+}
+
+impl Default for BandLayout {
+    fn default() -> Self {
+        Self::new(BandScale::Linear, BUFFER_SIZE, BUFFER_SIZE, SAMPLE_RATE)
+    }
+}
+
+impl BandLayout {
+    /// `band_count` bands over the `bin_count` bins of a spectrum at `sample_rate`. Linear bands span 0 Hz to
+    /// Nyquist, the others start at `DEFAULT_MIN_BAND_HZ`. Octave layouts round to whole bands per octave,
+    /// so `band_count()` can differ from the count asked for.
+    pub fn new(scale: BandScale, band_count: usize, bin_count: usize, sample_rate: f32) -> Self {
+        let nyquist = sample_rate / 2_f32;
+        let min_hz = match scale {
+            BandScale::Linear => 0_f32,
+            _ => DEFAULT_MIN_BAND_HZ.min(nyquist / 2_f32),
+        };
+        Self::with_range(scale, band_count, bin_count, sample_rate, min_hz, nyquist)
+    }
+
+    /// Like `new` with explicit lower and upper frequencies. Non-linear scales start at 1 Hz at the lowest.
+    pub fn with_range(
+        scale: BandScale,
+        band_count: usize,
+        bin_count: usize,
+        sample_rate: f32,
+        min_hz: f32,
+        max_hz: f32,
+    ) -> Self {
+        let band_count = band_count.max(1);
+        let min_hz = match scale {
+            BandScale::Linear => min_hz,
+            _ => min_hz.max(1_f32),
+        };
+        let edges = match scale {
+            BandScale::Octave => octave_edges(band_count, min_hz, max_hz),
+            _ => {
+                let low = scale.warp(min_hz);
+                let high = scale.warp(max_hz);
+                (0..=band_count)
+                    .map(|i| scale.unwarp(low + (high - low) * i as f32 / band_count as f32))
+                    .collect()
+            },
+        };
+        let centers = edges
+            .windows(2)
+            .map(|edge| scale.unwarp((scale.warp(edge[0]) + scale.warp(edge[1])) / 2_f32))
+            .collect();
+        Self {
+            scale,
+            bin_count,
+            bin_hz: sample_rate / (2 * bin_count.max(1)) as f32,
+            edges,
+            centers,
+        }
+    }
+
+    /// Bands over the bins `analyzer` produces.
+    pub fn for_analyzer(scale: BandScale, band_count: usize, analyzer: &SpectrumAnalyzer) -> Self {
+        Self::new(scale, band_count, analyzer.frequency_bin_count(), analyzer.sample_rate)
+    }
+
+    pub fn scale(&self) -> BandScale {
+        self.scale
+    }
+
+    /// Texels per row.
+    pub fn band_count(&self) -> usize {
+        self.centers.len()
+    }
+
+    pub fn bin_count(&self) -> usize {
+        self.bin_count
+    }
+
+    /// Center frequency of each texel in Hz, on the layout's own scale (the geometric mean for logarithmic
+    /// and octave bands).
+    pub fn center_frequencies(&self) -> &[f32] {
+        &self.centers
+    }
+
+    pub fn center_frequency(&self, band: usize) -> f32 {
+        self.centers[band]
+    }
+
+    /// Lower and upper edge of `band` in Hz.
+    pub fn band_range(&self, band: usize) -> (f32, f32) {
+        (self.edges[band], self.edges[band + 1])
+    }
+
+    /// Fills `bands` (`band_count()` long) from `bins` (`bin_count()` long), both in the same units.
+    pub fn apply(&self, bins: &[f32], bands: &mut [f32]) {
+        let bin_count = bins.len().min(self.bin_count);
+        if bin_count == 0 {
+            bands.fill(0_f32);
+            return;
+        }
+        for (band, out) in bands.iter_mut().enumerate().take(self.band_count()) {
+            let (low, high) = self.band_range(band);
+            let first_bin = |hz: f32| ((hz / self.bin_hz - EDGE_TOLERANCE).ceil().max(0_f32) as usize).min(bin_count);
+            let (first, end) = (first_bin(low), first_bin(high));
+            *out = if first < end {
+                bins[first..end].iter().copied().fold(f32::NEG_INFINITY, f32::max)
+            } else {
+                let position = (self.centers[band] / self.bin_hz).clamp(0_f32, (bin_count - 1) as f32);
+                let below = position.floor() as usize;
+                let above = (below + 1).min(bin_count - 1);
+                let t = position - below as f32;
+                bins[below] * (1_f32 - t) + bins[above] * t
+            };
+        }
+    }
+}
+
+// Fractional octave edges on the base two grid through 1 kHz, with the band count spread over the octaves
+// between `min_hz` and `max_hz`.
+fn octave_edges(band_count: usize, min_hz: f32, max_hz: f32) -> Vec<f32> {
+    let octaves = (max_hz / min_hz).log2();
+    let per_octave = (band_count as f32 / octaves).round().max(1_f32);
+    let index = |hz: f32| per_octave * (hz / OCTAVE_REFERENCE_HZ).log2();
+    let first = (index(min_hz) + 0.5_f32).ceil() as i32;
+    let last = (index(max_hz) + 0.5_f32).floor() as i32;
+    (first..=last.max(first + 1))
+        .map(|k| OCTAVE_REFERENCE_HZ * ((k as f32 - 0.5_f32) / per_octave).exp2())
+        .collect()
+}
@@ -0,0 +1,112 @@
+use crate::sound_render::spectrum_analyzer::SpectrumAnalyzer;
+use std::time::Instant;
+
+/// Where spectrum timestamps come from.
+#[derive(Debug, Clone, Copy)]
+pub enum SpectrumClock {
+    /// Seconds of audio the analyzer has been fed, stamped at the newest sample of each analysis. Locked to
+    /// the audio stream, so it needs no wall clock and is deterministic for offline rendering.
+    SamplePosition,
+    /// Seconds since `start`, for captures that do not report how many samples they have delivered.
+    Monotonic(Instant),
+}
+
+impl Default for SpectrumClock {
+    fn default() -> Self {
+        SpectrumClock::Monotonic(Instant::now())
+    }
+}
+
+impl SpectrumClock {
+    pub fn now(&self, analyzer: &SpectrumAnalyzer) -> f64 {
+        match self {
+            SpectrumClock::SamplePosition => analyzer.position_secs(),
+            SpectrumClock::Monotonic(start) => start.elapsed().as_secs_f64(),
+        }
+    }
+}
+
+/// Ring buffer of timestamped spectra, so visuals can read the spectrum as it was some time ago, e.g. to
+/// line up with audio that reaches the speakers later than it reaches the analyzer.
+#[derive(Debug, Clone)]
+pub struct SpectrumHistory {
+    spectra: Vec<Vec<f32>>,
+    times: Vec<f64>,
+    /// Slot the next `push` writes.
+    write_pos: usize,
+    len: usize,
+}
+
+impl SpectrumHistory {
+    /// Room for `capacity` spectra of `bin_count` bins; the oldest is overwritten once full.
+    pub fn new(capacity: usize, bin_count: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            spectra: vec![vec![0_f32; bin_count]; capacity],
+            times: vec![f64::NEG_INFINITY; capacity],
+            write_pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Enough slots to hold `secs` of analyses taken every `hop_secs`.
+    pub fn with_duration(secs: f64, hop_secs: f64, bin_count: usize) -> Self {
+        Self::new((secs / hop_secs).ceil() as usize + 1, bin_count)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.spectra.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Stores `spectrum` as the newest entry. Timestamps are expected to never go backwards.
+    pub fn push(&mut self, time_secs: f64, spectrum: &[f32]) {
+        let slot = &mut self.spectra[self.write_pos];
+        let n = slot.len().min(spectrum.len());
+        slot[..n].copy_from_slice(&spectrum[..n]);
+        slot[n..].fill(0_f32);
+        self.times[self.write_pos] = time_secs;
+        self.write_pos = (self.write_pos + 1) % self.capacity();
+        self.len = (self.len + 1).min(self.capacity());
+    }
+
+    pub fn clear(&mut self) {
+        self.times.fill(f64::NEG_INFINITY);
+        self.write_pos = 0;
+        self.len = 0;
+    }
+
+    /// `age` 0 is the newest entry, 1 the one before it, and so on.
+    pub fn get(&self, age: usize) -> Option<(f64, &[f32])> {
+        if age >= self.len {
+            return None;
+        }
+        let slot = (self.write_pos + self.capacity() - 1 - age) % self.capacity();
+        Some((self.times[slot], &self.spectra[slot]))
+    }
+
+    pub fn latest(&self) -> Option<(f64, &[f32])> {
+        self.get(0)
+    }
+
+    /// The newest spectrum stamped at or before `time_secs`, or the oldest one kept when all of them are
+    /// newer. None only while empty.
+    pub fn at_time(&self, time_secs: f64) -> Option<(f64, &[f32])> {
+        let newer = (0..self.len)
+            .take_while(|&age| self.get(age).is_some_and(|(time, _)| time > time_secs))
+            .count();
+        self.get(newer.min(self.len.checked_sub(1)?))
+    }
+
+    /// The spectrum as of `secs_ago` before `now_secs`.
+    pub fn ago(&self, now_secs: f64, secs_ago: f64) -> Option<(f64, &[f32])> {
+        self.at_time(now_secs - secs_ago)
+    }
+}